@@ -1,33 +1,970 @@
-use clap::Parser;
-use tokio::sync::OnceCell;
+pub mod file;
 
-pub static CONFIG: OnceCell<Config> = OnceCell::const_new();
+use std::{ffi::OsString, path::PathBuf, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use clap::{
+    parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum,
+};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use self::file::ConfigFile;
+use crate::structs::{arbitrage_finder::DexCosts, confidence_band::ConfidenceBand};
+
+const SOL_USD_PRICE_ACCOUNT: &str = "H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG";
+const SOL_USD_FEED_ID: &str = "ef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d";
+const USDT_USD_PRICE_ACCOUNT: &str = "3vxLXJqLqF3JG5TCbYycbKWRBbCJQLxQmBGCkyqEEefL";
+const USDT_USD_FEED_ID: &str = "2b89b9dc8fdf9f34709a5b106b472f0f39bb6ca9ce04b0fd7f2e971688e2e53b";
+pub const USDT_MINT: &str = "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB";
+// Binance quote currencies assumed to be worth $1, amounts in USD can be added to their notional
+const USD_QUOTE_CURRENCIES: [&str; 5] = ["usdt", "usdc", "fdusd", "tusd", "usdp"];
 
 /*
-    Struct holding CLI optional arguments
+    Struct holding CLI optional arguments.
+    Values missing from the CLI and environment variables are taken from the --config file, if provided.
 */
 #[derive(Parser)]
 pub struct Config {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    // TOML or YAML configuration file, see Readme.md for the structure
+    #[arg(long, env = "KEYROCK_TASK_CONFIG")]
+    pub config: Option<PathBuf>,
+
     // Pair from Binance spot market
     #[arg(long, short, default_value = "solusdt")]
     pub binance_ticker: String,
 
-    // Price id pubkey from Pyth
+    // Price id pubkey from Pyth, or hex price feed id when prices are read from Hermes
     // List of available ids (Solana) can be found here:
     // https://pyth.network/price-feeds?cluster=solana-mainnet-beta
+    #[arg(long, short, default_value = SOL_USD_PRICE_ACCOUNT)]
+    pub pyth_price_id: String,
+
+    // Pairs to monitor as <binance_ticker>=<pyth_price_id> mappings, e.g. solusdt=H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG.
+    // Takes precedence over the single pair set with --binance-ticker and --pyth-price-id.
+    #[arg(long = "pair", env = "KEYROCK_TASK_PAIRS", value_delimiter = ',', value_parser = parse_pair)]
+    pub pairs: Vec<PairConfig>,
+
+    // Binance WS endpoint serving combined streams
+    #[arg(
+        long,
+        env = "BINANCE_WEBSOCKET_URL",
+        default_value = "wss://stream.binance.com:9443/stream"
+    )]
+    pub binance_websocket_url: String,
+
+    // Binance REST API endpoint serving order book snapshots
+    #[arg(
+        long,
+        env = "BINANCE_REST_URL",
+        default_value = "https://api.binance.com"
+    )]
+    pub binance_rest_url: String,
+
+    // Timeout of a single Binance REST request
+    #[arg(long, default_value_t = 2000)]
+    pub binance_rest_timeout_ms: u64,
+
+    // Binance VIP tier setting the default maker and taker rates
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=9))]
+    pub binance_vip_tier: u8,
+
+    // Whether Binance fees are paid in BNB, discounting the VIP tier rates
+    #[arg(long, env = "BINANCE_BNB_DISCOUNT")]
+    pub binance_bnb_discount: bool,
+
+    // Rates of specific symbols as <binance_ticker>=<maker>:<taker> fractions, e.g. fdusdusdt=0:0.0001.
+    // They are taken as they are, without the BNB discount.
+    #[arg(long = "binance-fee", value_delimiter = ',', value_parser = parse_fee)]
+    pub binance_fees: Vec<FeeConfig>,
+
+    // Binance account API key and secret, if both are set the commission rates are fetched from the account
+    #[arg(long, env = "BINANCE_API_KEY")]
+    pub binance_api_key: Option<String>,
+    #[arg(long, env = "BINANCE_API_SECRET", hide_env_values = true)]
+    pub binance_api_secret: Option<String>,
+
+    // Source of the Binance order book, top 20 levels or the full book synced from diff events
+    #[arg(long, value_enum, default_value_t = OrderBookMode::Partial)]
+    pub binance_order_book: OrderBookMode,
+
+    // Swap fee of the DEX pools, in bps of the DEX notional
+    #[arg(long, default_value = "0")]
+    pub dex_swap_fee_bps: Decimal,
+
+    // Swap fees of specific pools as <binance_ticker>=<bps>, e.g. solusdt=25
+    #[arg(long = "dex-swap-fee", value_delimiter = ',', value_parser = parse_swap_fee)]
+    pub dex_swap_fees: Vec<SwapFeeConfig>,
+
+    // Estimated slippage of the DEX swap, in bps of the DEX notional
+    #[arg(long, default_value = "0")]
+    pub dex_slippage_bps: Decimal,
+
+    // Orca Whirlpools quoted instead of the Pyth price as <binance_ticker>=<pool address>.
+    // Token A of the pool has to be the base asset of the Binance pair.
+    #[arg(long = "whirlpool", value_delimiter = ',', value_parser = parse_pool)]
+    pub whirlpools: Vec<PoolConfig>,
+
+    // Raydium AMM v4 or CPMM pools quoted instead of the Pyth price as <binance_ticker>=<pool address>.
+    // The first token of the pool (coin, token 0) has to be the base asset of the Binance pair.
+    #[arg(long = "raydium-pool", value_delimiter = ',', value_parser = parse_pool)]
+    pub raydium_pools: Vec<PoolConfig>,
+
+    // OpenBook v2 markets quoted instead of the Pyth price as <binance_ticker>=<market address>,
+    // walking the resting orders of the market with its taker fee
+    #[arg(long = "openbook-market", value_delimiter = ',', value_parser = parse_pool)]
+    pub openbook_markets: Vec<PoolConfig>,
+
+    // Mint pairs quoted through the Jupiter aggregator instead of the Pyth price,
+    // as <binance_ticker>=<base mint>:<quote mint>
+    #[arg(long = "jupiter", value_delimiter = ',', value_parser = parse_jupiter)]
+    pub jupiter: Vec<JupiterConfig>,
+
+    // Jupiter quote API, e.g. a local stub replaying recorded quotes
+    #[arg(long, default_value = "https://quote-api.jup.ag/v6")]
+    pub jupiter_url: String,
+
+    // Quote currency amount of the Jupiter quotes, larger opportunities can't be quoted
+    #[arg(long, default_value = "1000")]
+    pub jupiter_notional: Decimal,
+
+    // Solana JSON-RPC endpoint serving DEX pool accounts
     #[arg(
         long,
-        short,
-        default_value = "H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG"
+        env = "SOLANA_RPC_URL",
+        default_value = "https://api.mainnet-beta.solana.com"
     )]
+    pub solana_rpc_url: String,
+
+    // Interval between consecutive DEX pool polls
+    #[arg(long, default_value_t = 1000)]
+    pub dex_poll_interval_ms: u64,
+
+    // Solana transaction fees of the DEX leg, converted to the quote currency with the SOL/USD Pyth price,
+    // so pairs not quoted in USD can only be monitored with no fees
+    #[arg(long, default_value_t = 5000)]
+    pub solana_base_fee_lamports: u64,
+    #[arg(long, default_value_t = 0)]
+    pub solana_priority_fee_lamports: u64,
+
+    // Pyth SOL/USD price id, defaults to the price account or, when prices are read from Hermes, the feed id
+    #[arg(long)]
+    pub sol_usd_price_id: Option<String>,
+
+    // Solana JSON-RPC endpoint serving Pyth price accounts
+    #[arg(
+        long,
+        env = "PYTH_RPC_URL",
+        default_value = "https://pythnet.rpcpool.com"
+    )]
+    pub pyth_rpc_url: String,
+
+    // Solana PubSub WS endpoint, if set Pyth price account changes are pushed instead of polled
+    #[arg(long, env = "PYTH_WS_URL")]
+    pub pyth_ws_url: Option<String>,
+
+    // Time spent polling after the Pyth price account subscription drops, before subscribing again
+    #[arg(long, default_value_t = 5000)]
+    pub pyth_resubscribe_interval_ms: u64,
+
+    // Pyth Hermes endpoint, if set prices are streamed from it (and polled when the stream drops)
+    // instead of being read from the Solana price account
+    #[arg(long, env = "HERMES_URL")]
+    pub hermes_url: Option<String>,
+
+    // Interval between consecutive Pyth price account polls
+    #[arg(long, default_value_t = 400)]
+    pub pyth_poll_interval_ms: u64,
+
+    // Timeout of a single Pyth RPC request
+    #[arg(long, default_value_t = 2000)]
+    pub pyth_rpc_timeout_ms: u64,
+
+    // Number of retries of a failed Pyth price poll, the price is cleared once they are exhausted
+    #[arg(long, default_value_t = 3)]
+    pub pyth_max_retries: u32,
+
+    // Delay before the first retry of a failed Pyth price poll, doubled with every next retry
+    #[arg(long, default_value_t = 100)]
+    pub pyth_retry_backoff_ms: u64,
+
+    // Multiplier of the Pyth confidence interval bounding the fair price, 2.12 is the 95% range of Laplace distribution
+    #[arg(long, default_value = "2.12")]
+    pub confidence_multiplier: Decimal,
+
+    // Model of the band around the Pyth price bounding the fair price, overrides --confidence-multiplier,
+    // one of laplace:<90|95|99>, gaussian:<90|95|99>, bps:<bps> or multiplier:<multiplier>
+    #[arg(long)]
+    pub confidence_band: Option<ConfidenceBand>,
+
+    // Confidence bands of specific pairs as <binance_ticker>=<band>, e.g. solusdt=gaussian:99
+    #[arg(long = "pair-confidence-band", value_delimiter = ',', value_parser = parse_confidence_band)]
+    pub confidence_bands: Vec<ConfidenceBandConfig>,
+
+    // Opportunities with lower estimated profit (in the quote currency) are not reported
+    #[arg(long, default_value = "0")]
+    pub min_profit: Decimal,
+
+    // Opportunities with lower net profit in bps of the Binance notional are not reported
+    #[arg(long, default_value = "0")]
+    pub min_edge_bps: Decimal,
+
+    // Opportunities with lower Binance notional (in the quote currency) are not reported
+    #[arg(long, default_value = "0")]
+    pub min_notional: Decimal,
+
+    // Binance notional (in the quote currency) of a single opportunity is capped at this value
+    #[arg(long)]
+    pub max_notional: Option<Decimal>,
+
+    // Relative change of the quantity or net profit of an open opportunity reported as an update, 0.05 is 5%
+    #[arg(long, default_value = "0.05")]
+    pub opportunity_update_tolerance: Decimal,
+
+    // Whether prices of USDT pairs are converted to USD with the USDT/USD Pyth price before comparing,
+    // instead of assuming USDT is exactly $1
+    #[arg(long, env = "KEYROCK_TASK_NORMALIZE_USDT")]
+    pub normalize_usdt: bool,
+
+    // Pyth USDT/USD price id, defaults to the price account or, when prices are read from Hermes, the feed id
+    #[arg(long)]
+    pub usdt_usd_price_id: Option<String>,
+
+    // Whether opportunity events are not printed to stdout, e.g. when they are only consumed through the library
+    #[arg(long, env = "KEYROCK_TASK_QUIET")]
+    pub quiet: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    // Checks the configuration file, reporting errors with line numbers
+    ValidateConfig { path: PathBuf },
+}
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderBookMode {
+    // <symbol>@depth20@100ms stream
+    Partial,
+    // <symbol>@depth@100ms stream synced with the /api/v3/depth snapshot
+    Full,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct PairConfig {
+    pub binance_ticker: String,
     pub pyth_price_id: String,
 }
 
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct FeeConfig {
+    pub binance_ticker: String,
+    pub maker: Decimal,
+    pub taker: Decimal,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct SwapFeeConfig {
+    pub binance_ticker: String,
+    pub bps: Decimal,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct PoolConfig {
+    pub binance_ticker: String,
+    pub address: String,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ConfidenceBandConfig {
+    pub binance_ticker: String,
+    pub band: ConfidenceBand,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct JupiterConfig {
+    pub binance_ticker: String,
+    pub base_mint: String,
+    pub quote_mint: String,
+}
+
 impl Config {
     /*
-        Parses CLI arguments into Config instance
+        Parses CLI arguments and the configuration file into Config instance, exiting with an error message on failure
     */
+    #[allow(clippy::new_without_default)] // Default reading process arguments would be surprising
     pub fn new() -> Self {
-        Self::parse()
+        let matches = Self::command().get_matches();
+        Self::from_matches(&matches).unwrap_or_else(|err| {
+            eprintln!("Invalid configuration: {}", err);
+            std::process::exit(2);
+        })
+    }
+
+    /*
+        Parses the provided arguments and the configuration file into Config instance
+    */
+    pub fn load_from<I, T>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let matches = Self::command().try_get_matches_from(args)?;
+        Self::from_matches(&matches)
+    }
+
+    fn from_matches(matches: &ArgMatches) -> Result<Self> {
+        let mut config = Self::from_arg_matches(matches)?;
+        if let Some(path) = &config.config {
+            let config_file = ConfigFile::validate(path).map_err(|errors| {
+                let errors = errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+                anyhow!(errors.join("\n"))
+            })?;
+            config.merge(config_file, matches);
+        }
+        Ok(config)
+    }
+
+    /*
+        Takes values from the file for the arguments not provided explicitly, in the CLI or environment variables
+    */
+    fn merge(&mut self, config_file: ConfigFile, matches: &ArgMatches) {
+        let is_explicit = |id: &str| {
+            matches!(
+                matches.value_source(id),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        };
+        macro_rules! merge {
+            ($($value:expr => $field:ident),* $(,)?) => {
+                $(
+                    if let Some(value) = $value {
+                        if !is_explicit(stringify!($field)) {
+                            self.$field = value.into();
+                        }
+                    }
+                )*
+            };
+        }
+
+        // A single pair given explicitly overrides the list from the file as well
+        let explicit_pairs = ["pairs", "binance_ticker", "pyth_price_id"]
+            .into_iter()
+            .any(is_explicit);
+        if !config_file.pairs.is_empty() && !explicit_pairs {
+            self.pairs = config_file
+                .pairs
+                .into_iter()
+                .map(|pair| PairConfig {
+                    binance_ticker: pair.binance_ticker.to_lowercase(),
+                    pyth_price_id: pair.pyth_price_id,
+                })
+                .collect();
+        }
+
+        merge!(
+            config_file.binance.websocket_url => binance_websocket_url,
+            config_file.binance.vip_tier => binance_vip_tier,
+            config_file.binance.bnb_discount => binance_bnb_discount,
+            config_file.binance.fees => binance_fees,
+            config_file.binance.rest_url => binance_rest_url,
+            config_file.binance.rest_timeout_ms => binance_rest_timeout_ms,
+            config_file.binance.order_book => binance_order_book,
+            config_file.dex.swap_fee_bps => dex_swap_fee_bps,
+            config_file.dex.swap_fees => dex_swap_fees,
+            config_file.dex.slippage_bps => dex_slippage_bps,
+            config_file.dex.whirlpools => whirlpools,
+            config_file.dex.raydium_pools => raydium_pools,
+            config_file.dex.openbook_markets => openbook_markets,
+            config_file.dex.jupiter => jupiter,
+            config_file.dex.jupiter_url => jupiter_url,
+            config_file.dex.jupiter_notional => jupiter_notional,
+            config_file.dex.rpc_url => solana_rpc_url,
+            config_file.dex.poll_interval_ms => dex_poll_interval_ms,
+            config_file.dex.base_fee_lamports => solana_base_fee_lamports,
+            config_file.dex.priority_fee_lamports => solana_priority_fee_lamports,
+            config_file.dex.sol_usd_price_id => sol_usd_price_id,
+            config_file.pyth.rpc_url => pyth_rpc_url,
+            config_file.pyth.ws_url => pyth_ws_url,
+            config_file.pyth.hermes_url => hermes_url,
+            config_file.pyth.resubscribe_interval_ms => pyth_resubscribe_interval_ms,
+            config_file.pyth.poll_interval_ms => pyth_poll_interval_ms,
+            config_file.pyth.rpc_timeout_ms => pyth_rpc_timeout_ms,
+            config_file.pyth.max_retries => pyth_max_retries,
+            config_file.pyth.retry_backoff_ms => pyth_retry_backoff_ms,
+            config_file.arbitrage.confidence_multiplier => confidence_multiplier,
+            config_file.arbitrage.confidence_band => confidence_band,
+            config_file.arbitrage.confidence_bands => confidence_bands,
+            config_file.arbitrage.min_profit => min_profit,
+            config_file.arbitrage.min_edge_bps => min_edge_bps,
+            config_file.arbitrage.min_notional => min_notional,
+            config_file.arbitrage.max_notional => max_notional,
+            config_file.arbitrage.update_tolerance => opportunity_update_tolerance,
+            config_file.arbitrage.normalize_usdt => normalize_usdt,
+            config_file.pyth.usdt_usd_price_id => usdt_usd_price_id,
+            config_file.outputs.stdout.map(|stdout| !stdout) => quiet,
+        );
+    }
+
+    /*
+        Returns the DEX leg cost model of the pair
+    */
+    pub fn dex_costs(&self, binance_ticker: &str) -> DexCosts {
+        let swap_fee_bps = self
+            .dex_swap_fees
+            .iter()
+            .find(|swap_fee| swap_fee.binance_ticker.eq_ignore_ascii_case(binance_ticker))
+            .map_or(self.dex_swap_fee_bps, |swap_fee| swap_fee.bps);
+
+        DexCosts {
+            swap_fee_bps,
+            slippage_bps: self.dex_slippage_bps,
+            base_fee_lamports: self.solana_base_fee_lamports,
+            priority_fee_lamports: self.solana_priority_fee_lamports,
+        }
+    }
+
+    /*
+        Returns the confidence band of the pair, falling back to the default band and then to the multiplier
+    */
+    pub fn confidence_band(&self, binance_ticker: &str) -> ConfidenceBand {
+        self.confidence_bands
+            .iter()
+            .find(|band| band.binance_ticker.eq_ignore_ascii_case(binance_ticker))
+            .map(|band| band.band)
+            .or(self.confidence_band)
+            .unwrap_or(ConfidenceBand::Multiplier(self.confidence_multiplier))
+    }
+
+    /*
+        Returns the address of the Whirlpool quoted for the pair, if there is one
+    */
+    pub fn whirlpool(&self, binance_ticker: &str) -> Option<&str> {
+        self.whirlpools
+            .iter()
+            .find(|pool| pool.binance_ticker.eq_ignore_ascii_case(binance_ticker))
+            .map(|pool| pool.address.as_str())
+    }
+
+    /*
+        Returns the address of the Raydium pool quoted for the pair, if there is one
+    */
+    pub fn raydium_pool(&self, binance_ticker: &str) -> Option<&str> {
+        self.raydium_pools
+            .iter()
+            .find(|pool| pool.binance_ticker.eq_ignore_ascii_case(binance_ticker))
+            .map(|pool| pool.address.as_str())
+    }
+
+    /*
+        Returns the address of the OpenBook market quoted for the pair, if there is one
+    */
+    pub fn openbook_market(&self, binance_ticker: &str) -> Option<&str> {
+        self.openbook_markets
+            .iter()
+            .find(|market| market.binance_ticker.eq_ignore_ascii_case(binance_ticker))
+            .map(|market| market.address.as_str())
+    }
+
+    /*
+        Returns the mint pair quoted through Jupiter for the pair, if there is one
+    */
+    pub fn jupiter(&self, binance_ticker: &str) -> Option<&JupiterConfig> {
+        self.jupiter
+            .iter()
+            .find(|jupiter| jupiter.binance_ticker.eq_ignore_ascii_case(binance_ticker))
+    }
+
+    pub fn sol_usd_price_id(&self) -> &str {
+        match (&self.sol_usd_price_id, &self.hermes_url) {
+            (Some(sol_usd_price_id), _) => sol_usd_price_id,
+            (None, Some(_)) => SOL_USD_FEED_ID,
+            (None, None) => SOL_USD_PRICE_ACCOUNT,
+        }
+    }
+
+    pub fn usdt_usd_price_id(&self) -> &str {
+        match (&self.usdt_usd_price_id, &self.hermes_url) {
+            (Some(usdt_usd_price_id), _) => usdt_usd_price_id,
+            (None, Some(_)) => USDT_USD_FEED_ID,
+            (None, None) => USDT_USD_PRICE_ACCOUNT,
+        }
+    }
+
+    /*
+        Whether the DEX leg pays Solana transaction fees, which are converted with the SOL/USD price
+    */
+    pub fn has_solana_fees(&self) -> bool {
+        self.solana_base_fee_lamports + self.solana_priority_fee_lamports > 0
+    }
+
+    /*
+        Returns whether Binance prices of the pair are converted from USDT to USD, unless its DEX pool
+        is quoted in USDT as well
+    */
+    pub fn normalizes_usdt(&self, binance_ticker: &str) -> bool {
+        self.normalize_usdt && binance_ticker.to_lowercase().ends_with("usdt")
+    }
+
+    /*
+        Returns the monitored pairs, falling back to the single pair if no list is provided
+    */
+    pub fn pairs(&self) -> Vec<PairConfig> {
+        if !self.pairs.is_empty() {
+            return self.pairs.clone();
+        }
+
+        vec![PairConfig {
+            binance_ticker: self.binance_ticker.clone(),
+            pyth_price_id: self.pyth_price_id.clone(),
+        }]
+    }
+}
+
+/*
+    Returns whether the Binance pair is quoted in one of the USD stablecoins
+*/
+pub fn is_usd_quoted(binance_ticker: &str) -> bool {
+    let binance_ticker = binance_ticker.to_lowercase();
+    USD_QUOTE_CURRENCIES
+        .iter()
+        .any(|currency| binance_ticker.ends_with(currency))
+}
+
+/*
+    Parses <binance_ticker>=<bps> swap fee
+*/
+fn parse_swap_fee(value: &str) -> Result<SwapFeeConfig, String> {
+    let parse = || {
+        let (binance_ticker, bps) = value.split_once('=')?;
+        Some(SwapFeeConfig {
+            binance_ticker: binance_ticker.trim().to_lowercase(),
+            bps: Decimal::from_str(bps.trim()).ok()?,
+        })
+    };
+
+    match parse() {
+        Some(swap_fee) if !swap_fee.binance_ticker.is_empty() => Ok(swap_fee),
+        _ => Err(format!("expected <binance_ticker>=<bps>, got '{}'", value)),
+    }
+}
+
+/*
+    Parses <binance_ticker>=<pool address> mapping
+*/
+fn parse_pool(value: &str) -> Result<PoolConfig, String> {
+    match value.split_once('=') {
+        Some((binance_ticker, address))
+            if !binance_ticker.trim().is_empty() && !address.trim().is_empty() =>
+        {
+            Ok(PoolConfig {
+                binance_ticker: binance_ticker.trim().to_lowercase(),
+                address: address.trim().to_string(),
+            })
+        }
+        _ => Err(format!(
+            "expected <binance_ticker>=<pool address>, got '{}'",
+            value
+        )),
+    }
+}
+
+/*
+    Parses <binance_ticker>=<band> confidence band
+*/
+fn parse_confidence_band(value: &str) -> Result<ConfidenceBandConfig, String> {
+    match value.split_once('=') {
+        Some((binance_ticker, band)) if !binance_ticker.trim().is_empty() => {
+            Ok(ConfidenceBandConfig {
+                binance_ticker: binance_ticker.trim().to_lowercase(),
+                band: band.parse()?,
+            })
+        }
+        _ => Err(format!("expected <binance_ticker>=<band>, got '{}'", value)),
+    }
+}
+
+/*
+    Parses <binance_ticker>=<base mint>:<quote mint> pair quoted by Jupiter, the notional is set by --jupiter-notional
+*/
+fn parse_jupiter(value: &str) -> Result<JupiterConfig, String> {
+    match value.split_once('=').and_then(|(binance_ticker, mints)| {
+        let (base_mint, quote_mint) = mints.split_once(':')?;
+        Some((binance_ticker.trim(), base_mint.trim(), quote_mint.trim()))
+    }) {
+        Some((binance_ticker, base_mint, quote_mint))
+            if !binance_ticker.is_empty() && !base_mint.is_empty() && !quote_mint.is_empty() =>
+        {
+            Ok(JupiterConfig {
+                binance_ticker: binance_ticker.to_lowercase(),
+                base_mint: base_mint.to_string(),
+                quote_mint: quote_mint.to_string(),
+            })
+        }
+        _ => Err(format!(
+            "expected <binance_ticker>=<base mint>:<quote mint>, got '{}'",
+            value
+        )),
+    }
+}
+
+/*
+    Parses <binance_ticker>=<pyth_price_id> mapping
+*/
+fn parse_pair(value: &str) -> Result<PairConfig, String> {
+    match value.split_once('=') {
+        Some((binance_ticker, pyth_price_id))
+            if !binance_ticker.trim().is_empty() && !pyth_price_id.trim().is_empty() =>
+        {
+            Ok(PairConfig {
+                binance_ticker: binance_ticker.trim().to_lowercase(),
+                pyth_price_id: pyth_price_id.trim().to_string(),
+            })
+        }
+        _ => Err(format!(
+            "expected <binance_ticker>=<pyth_price_id>, got '{}'",
+            value
+        )),
+    }
+}
+
+/*
+    Parses <binance_ticker>=<maker>:<taker> rates
+*/
+fn parse_fee(value: &str) -> Result<FeeConfig, String> {
+    let parse = || {
+        let (binance_ticker, rates) = value.split_once('=')?;
+        let (maker, taker) = rates.split_once(':')?;
+        Some(FeeConfig {
+            binance_ticker: binance_ticker.trim().to_lowercase(),
+            maker: Decimal::from_str(maker.trim()).ok()?,
+            taker: Decimal::from_str(taker.trim()).ok()?,
+        })
+    };
+
+    match parse() {
+        Some(fee) if !fee.binance_ticker.is_empty() => Ok(fee),
+        _ => Err(format!(
+            "expected <binance_ticker>=<maker>:<taker>, got '{}'",
+            value
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use clap::Parser;
+    use rust_decimal::Decimal;
+
+    use super::{
+        file::tests::write_config_file, ConfidenceBand, Config, FeeConfig, OrderBookMode,
+        PairConfig,
+    };
+
+    const CONFIG_FILE: &str = r#"
+[[pairs]]
+binance_ticker = "SOLUSDT"
+pyth_price_id = "H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG"
+
+[[pairs]]
+binance_ticker = "bnbusdt"
+pyth_price_id = "4CkQJBxhU8EZ2UjhigbtdaPbpTe6mqf811fipYBFbSYN"
+
+[binance]
+websocket_url = "ws://127.0.0.1:9443/stream"
+order_book = "full"
+
+[pyth]
+poll_interval_ms = 1000
+ws_url = "ws://127.0.0.1:8900"
+
+[arbitrage]
+min_profit = 0.25
+
+[outputs]
+stdout = false
+"#;
+
+    #[test]
+    fn test_pairs() {
+        let config = Config::parse_from(["keyrock-task", "-b", "btcusdt", "-p", "GVXR"]);
+        assert_eq!(
+            config.pairs(),
+            vec![PairConfig {
+                binance_ticker: "btcusdt".to_string(),
+                pyth_price_id: "GVXR".to_string(),
+            }]
+        );
+
+        let config = Config::parse_from(["keyrock-task", "--pair", "SOLUSDT=H6AR,bnbusdt=4CkQ"]);
+        assert_eq!(
+            config
+                .pairs()
+                .into_iter()
+                .map(|pair| pair.binance_ticker)
+                .collect::<Vec<_>>(),
+            vec!["solusdt", "bnbusdt"]
+        );
+
+        let result = Config::try_parse_from(["keyrock-task", "--pair", "solusdt"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_fees() {
+        let config = Config::parse_from(["keyrock-task", "--binance-fee", "FDUSDUSDT=0:0.0001"]);
+        assert_eq!(
+            config.binance_fees,
+            vec![FeeConfig {
+                binance_ticker: "fdusdusdt".to_string(),
+                maker: Decimal::ZERO,
+                taker: Decimal::from_str("0.0001").unwrap(),
+            }]
+        );
+        assert!(
+            Config::try_parse_from(["keyrock-task", "--binance-fee", "solusdt=0.001"]).is_err()
+        );
+        assert!(Config::try_parse_from(["keyrock-task", "--binance-vip-tier", "10"]).is_err());
+
+        let path = write_config_file(
+            "config_fees.yaml",
+            "binance:\n  vip_tier: 2\n  bnb_discount: true\n  fees:\n    - binance_ticker: solbnb\n      maker: 0\n      taker: 0\n",
+        );
+        let config =
+            Config::load_from(["keyrock-task", "--config", path.to_str().unwrap()]).unwrap();
+        assert_eq!(config.binance_vip_tier, 2);
+        assert!(config.binance_bnb_discount);
+        assert_eq!(config.binance_fees[0].binance_ticker, "solbnb");
+    }
+
+    #[test]
+    fn test_dex_costs() {
+        let config = Config::parse_from([
+            "keyrock-task",
+            "--dex-swap-fee-bps",
+            "30",
+            "--dex-swap-fee",
+            "SOLUSDC=4",
+            "--solana-priority-fee-lamports",
+            "20000",
+        ]);
+        assert_eq!(config.dex_costs("solusdt").swap_fee_bps, Decimal::from(30));
+        let dex_costs = config.dex_costs("solusdc");
+        assert_eq!(dex_costs.swap_fee_bps, Decimal::from(4));
+        assert_eq!(dex_costs.base_fee_lamports, 5000);
+        assert_eq!(dex_costs.priority_fee_lamports, 20000);
+        assert!(Config::try_parse_from(["keyrock-task", "--dex-swap-fee", "solusdt"]).is_err());
+
+        assert_eq!(config.sol_usd_price_id(), super::SOL_USD_PRICE_ACCOUNT);
+        let config = Config::parse_from(["keyrock-task", "--hermes-url", "http://127.0.0.1"]);
+        assert_eq!(config.sol_usd_price_id(), super::SOL_USD_FEED_ID);
+    }
+
+    #[test]
+    fn test_confidence_bands() {
+        let config = Config::parse_from(["keyrock-task", "--confidence-multiplier", "1.5"]);
+        assert_eq!(
+            config.confidence_band("solusdt"),
+            ConfidenceBand::Multiplier(Decimal::from_str("1.5").unwrap())
+        );
+
+        let config = Config::parse_from([
+            "keyrock-task",
+            "--confidence-band",
+            "gaussian:99",
+            "--pair-confidence-band",
+            "SOLUSDC=bps:20,bnbusdt=laplace:90",
+        ]);
+        assert_eq!(
+            config.confidence_band("solusdt"),
+            "gaussian:99".parse().unwrap()
+        );
+        assert_eq!(
+            config.confidence_band("solusdc"),
+            ConfidenceBand::Bps(Decimal::from(20))
+        );
+        assert_eq!(
+            config.confidence_band("bnbusdt"),
+            "laplace:90".parse().unwrap()
+        );
+        assert!(
+            Config::try_parse_from(["keyrock-task", "--confidence-band", "laplace:50"]).is_err()
+        );
+        assert!(
+            Config::try_parse_from(["keyrock-task", "--pair-confidence-band", "solusdt"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_normalize_usdt() {
+        let config = Config::parse_from(["keyrock-task"]);
+        assert!(!config.normalizes_usdt("solusdt"));
+        assert_eq!(config.usdt_usd_price_id(), super::USDT_USD_PRICE_ACCOUNT);
+
+        let config = Config::parse_from([
+            "keyrock-task",
+            "--normalize-usdt",
+            "--hermes-url",
+            "http://127.0.0.1",
+        ]);
+        assert!(config.normalizes_usdt("SOLUSDT"));
+        assert!(!config.normalizes_usdt("solusdc"));
+        assert_eq!(config.usdt_usd_price_id(), super::USDT_USD_FEED_ID);
+    }
+
+    #[test]
+    fn test_whirlpools() {
+        let config = Config::parse_from([
+            "keyrock-task",
+            "--whirlpool",
+            "SOLUSDC=HJPjoWUrhoZzkNfRpHuieeFk9WcZWjwy6PBjZ81ngndJ",
+        ]);
+        assert_eq!(
+            config.whirlpool("solusdc"),
+            Some("HJPjoWUrhoZzkNfRpHuieeFk9WcZWjwy6PBjZ81ngndJ")
+        );
+        assert_eq!(config.whirlpool("solusdt"), None);
+        assert_eq!(config.raydium_pool("solusdc"), None);
+        let config = Config::parse_from([
+            "keyrock-task",
+            "--raydium-pool",
+            "solusdc=58oQChx4yWmvKdwLLZzBi4ChoCc2fqCUWBkwMihLYQo2",
+        ]);
+        assert_eq!(
+            config.raydium_pool("SOLUSDC"),
+            Some("58oQChx4yWmvKdwLLZzBi4ChoCc2fqCUWBkwMihLYQo2")
+        );
+        let config = Config::parse_from([
+            "keyrock-task",
+            "--openbook-market",
+            "solusdc=CFSMrBssNG8Ud1edW59jNLnq2cwrQ9uY5cM3wXmqRJj3",
+        ]);
+        assert_eq!(
+            config.openbook_market("solusdc"),
+            Some("CFSMrBssNG8Ud1edW59jNLnq2cwrQ9uY5cM3wXmqRJj3")
+        );
+        let config = Config::parse_from([
+            "keyrock-task",
+            "--jupiter",
+            "SOLUSDC=So11111111111111111111111111111111111111112:EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+        ]);
+        assert_eq!(
+            config.jupiter("solusdc"),
+            Some(&super::JupiterConfig {
+                binance_ticker: "solusdc".to_string(),
+                base_mint: "So11111111111111111111111111111111111111112".to_string(),
+                quote_mint: "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string(),
+            })
+        );
+        assert_eq!(config.jupiter_notional, Decimal::from(1000));
+        assert!(Config::try_parse_from(["keyrock-task", "--jupiter", "solusdc=So1111"]).is_err());
+        assert!(Config::try_parse_from(["keyrock-task", "--whirlpool", "solusdc"]).is_err());
+    }
+
+    #[test]
+    fn test_load_from_file() {
+        let path = write_config_file("config.toml", CONFIG_FILE);
+        let config =
+            Config::load_from(["keyrock-task", "--config", path.to_str().unwrap()]).unwrap();
+
+        assert_eq!(
+            config
+                .pairs()
+                .into_iter()
+                .map(|pair| pair.binance_ticker)
+                .collect::<Vec<_>>(),
+            vec!["solusdt", "bnbusdt"]
+        );
+        assert_eq!(config.binance_websocket_url, "ws://127.0.0.1:9443/stream");
+        assert_eq!(config.binance_order_book, OrderBookMode::Full);
+        assert_eq!(config.pyth_poll_interval_ms, 1000);
+        assert_eq!(config.pyth_ws_url.as_deref(), Some("ws://127.0.0.1:8900"));
+        assert_eq!(config.min_profit, Decimal::from_str("0.25").unwrap());
+        assert!(config.quiet);
+        // Defaults are kept for values missing from the file
+        assert_eq!(config.pyth_max_retries, 3);
+        assert_eq!(
+            config.confidence_multiplier,
+            Decimal::from_str("2.12").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_cli_overrides_file() {
+        let path = write_config_file("config_override.toml", CONFIG_FILE);
+        let config = Config::load_from([
+            "keyrock-task",
+            "--config",
+            path.to_str().unwrap(),
+            "--pyth-poll-interval-ms",
+            "200",
+            "--binance-order-book",
+            "partial",
+            "-b",
+            "btcusdt",
+        ])
+        .unwrap();
+
+        assert_eq!(config.pyth_poll_interval_ms, 200);
+        assert_eq!(config.binance_order_book, OrderBookMode::Partial);
+        // Single pair given explicitly replaces the list from the file
+        assert_eq!(config.pairs().len(), 1);
+        assert_eq!(config.pairs()[0].binance_ticker, "btcusdt");
+        assert_eq!(config.binance_websocket_url, "ws://127.0.0.1:9443/stream");
+    }
+
+    #[test]
+    fn test_env_overrides_file() {
+        // The variables are only set in a child process running this test, other tests read the environment too
+        if std::env::var_os("BINANCE_REST_URL").is_none() {
+            let path = write_config_file(
+                "config_env.toml",
+                "[binance]\nrest_url = \"http://from-file\"\nwebsocket_url = \"ws://from-file\"\n",
+            );
+            let status = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "config::tests::test_env_overrides_file"])
+                .env("BINANCE_REST_URL", "http://from-env")
+                .env("KEYROCK_TASK_CONFIG", path)
+                .status()
+                .unwrap();
+            assert!(status.success());
+            return;
+        }
+
+        let config = Config::load_from(["keyrock-task"]).unwrap();
+        assert_eq!(config.binance_rest_url, "http://from-env");
+        assert_eq!(config.binance_websocket_url, "ws://from-file");
+    }
+
+    #[test]
+    fn test_load_from_invalid_file() {
+        let path = write_config_file("config_invalid.toml", "[pyth]\nmax_retries = -1\n");
+        let result = Config::load_from(["keyrock-task", "--config", path.to_str().unwrap()]);
+        match result {
+            Ok(_) => panic!("Expected the configuration to be invalid"),
+            Err(err) => assert!(err.to_string().contains("config_invalid.toml:2:")),
+        }
+
+        // Values parsed fine but rejected by the validation
+        let path = write_config_file(
+            "config_invalid_pair.toml",
+            "[[pairs]]\nbinance_ticker = \"solusdt\"\npyth_price_id = \"not-a-pubkey\"\n",
+        );
+        let result = Config::load_from(["keyrock-task", "--config", path.to_str().unwrap()]);
+        match result {
+            Ok(_) => panic!("Expected the configuration to be invalid"),
+            Err(err) => assert!(err.to_string().contains("config_invalid_pair.toml:3:")),
+        }
     }
 }
@@ -0,0 +1,658 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use rust_decimal::Decimal;
+use serde::{
+    de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    Deserialize,
+};
+use solana_program::pubkey::Pubkey;
+
+use crate::structs::{
+    arbitrage_finder::DexCosts, cex::fees::FeeSchedule, confidence_band::ConfidenceBand,
+    on_chain::price_source::parse_price_id,
+};
+
+use super::{
+    is_usd_quoted, ConfidenceBandConfig, FeeConfig, JupiterConfig, OrderBookMode, PairConfig,
+    PoolConfig, SwapFeeConfig,
+};
+
+/*
+    Struct representing a TOML or YAML configuration file.
+    Every value is optional, missing ones are taken from CLI arguments, environment variables or defaults.
+*/
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    #[serde(default)]
+    pub pairs: Vec<PairConfig>,
+    #[serde(default)]
+    pub binance: BinanceSection,
+    #[serde(default)]
+    pub pyth: PythSection,
+    #[serde(default)]
+    pub dex: DexSection,
+    #[serde(default)]
+    pub arbitrage: ArbitrageSection,
+    #[serde(default)]
+    pub outputs: OutputsSection,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BinanceSection {
+    pub websocket_url: Option<String>,
+    pub rest_url: Option<String>,
+    pub rest_timeout_ms: Option<u64>,
+    pub order_book: Option<OrderBookMode>,
+    pub vip_tier: Option<u8>,
+    pub bnb_discount: Option<bool>,
+    pub fees: Option<Vec<FeeConfig>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PythSection {
+    pub rpc_url: Option<String>,
+    pub ws_url: Option<String>,
+    pub hermes_url: Option<String>,
+    pub resubscribe_interval_ms: Option<u64>,
+    pub poll_interval_ms: Option<u64>,
+    pub rpc_timeout_ms: Option<u64>,
+    pub max_retries: Option<u32>,
+    pub retry_backoff_ms: Option<u64>,
+    pub usdt_usd_price_id: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DexSection {
+    pub swap_fee_bps: Option<Decimal>,
+    pub swap_fees: Option<Vec<SwapFeeConfig>>,
+    pub slippage_bps: Option<Decimal>,
+    pub whirlpools: Option<Vec<PoolConfig>>,
+    pub raydium_pools: Option<Vec<PoolConfig>>,
+    pub openbook_markets: Option<Vec<PoolConfig>>,
+    pub jupiter: Option<Vec<JupiterConfig>>,
+    pub jupiter_url: Option<String>,
+    pub jupiter_notional: Option<Decimal>,
+    pub rpc_url: Option<String>,
+    pub poll_interval_ms: Option<u64>,
+    pub base_fee_lamports: Option<u64>,
+    pub priority_fee_lamports: Option<u64>,
+    pub sol_usd_price_id: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArbitrageSection {
+    pub confidence_multiplier: Option<Decimal>,
+    pub confidence_band: Option<ConfidenceBand>,
+    pub confidence_bands: Option<Vec<ConfidenceBandConfig>>,
+    pub min_profit: Option<Decimal>,
+    pub min_edge_bps: Option<Decimal>,
+    pub min_notional: Option<Decimal>,
+    pub max_notional: Option<Decimal>,
+    pub update_tolerance: Option<Decimal>,
+    pub normalize_usdt: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputsSection {
+    // Whether opportunity events are printed to stdout, the opposite of --quiet
+    pub stdout: Option<bool>,
+}
+
+/*
+    Error found in the configuration file, pointing at the line when it is known
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigFileError {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigFileError {}
+
+impl ConfigFile {
+    /*
+        Reads and parses the file, the format is picked by the extension (.toml, .yaml or .yml)
+    */
+    pub fn load(path: &Path) -> Result<Self, ConfigFileError> {
+        let contents = read(path)?;
+        Self::parse(path, &contents)
+    }
+
+    /*
+        Reads, parses and validates the file, reporting every error found
+    */
+    pub fn validate(path: &Path) -> Result<Self, Vec<ConfigFileError>> {
+        let contents = read(path).map_err(|err| vec![err])?;
+        let config_file = Self::parse(path, &contents).map_err(|err| vec![err])?;
+
+        let mut errors = Vec::new();
+        let mut error = |value_path: &[Step], message: String| {
+            errors.push(ConfigFileError {
+                path: path.to_path_buf(),
+                line: find_line(path, &contents, value_path),
+                message,
+            })
+        };
+        let has_solana_fees = config_file
+            .dex
+            .base_fee_lamports
+            .unwrap_or(DexCosts::default().base_fee_lamports)
+            + config_file.dex.priority_fee_lamports.unwrap_or_default()
+            > 0;
+        let mut binance_tickers = HashMap::new();
+        for (index, pair) in config_file.pairs.iter().enumerate() {
+            let pair_path = |key| [Step::Key("pairs"), Step::Index(index), Step::Key(key)];
+            if let Err(err) = parse_price_id(&pair.pyth_price_id) {
+                error(
+                    &pair_path("pyth_price_id"),
+                    format!("invalid pyth_price_id: {}", err),
+                );
+            }
+
+            if count(&mut binance_tickers, &pair.binance_ticker) > 0 {
+                error(
+                    &pair_path("binance_ticker"),
+                    format!("pair {} is configured twice", pair.binance_ticker),
+                );
+            } else if has_solana_fees && !is_usd_quoted(&pair.binance_ticker) {
+                error(
+                    &pair_path("binance_ticker"),
+                    format!(
+                        "pair {} is not quoted in USD, Solana fees can't be converted into its quote currency",
+                        pair.binance_ticker
+                    ),
+                );
+            }
+        }
+        for (section, name, price_id) in [
+            ("dex", "sol_usd_price_id", &config_file.dex.sol_usd_price_id),
+            (
+                "pyth",
+                "usdt_usd_price_id",
+                &config_file.pyth.usdt_usd_price_id,
+            ),
+        ] {
+            if let Some(price_id) = price_id {
+                if let Err(err) = parse_price_id(price_id) {
+                    error(
+                        &[Step::Key(section), Step::Key(name)],
+                        format!("invalid {}: {}", name, err),
+                    );
+                }
+            }
+        }
+        let mut quoted_tickers = HashMap::new();
+        for (index, jupiter) in config_file.dex.jupiter.iter().flatten().enumerate() {
+            for (key, mint) in [
+                ("base_mint", &jupiter.base_mint),
+                ("quote_mint", &jupiter.quote_mint),
+            ] {
+                if let Err(err) = Pubkey::from_str(mint) {
+                    error(
+                        &[
+                            Step::Key("dex"),
+                            Step::Key("jupiter"),
+                            Step::Index(index),
+                            Step::Key(key),
+                        ],
+                        format!("invalid Jupiter mint {}: {}", mint, err),
+                    );
+                }
+            }
+            count(&mut quoted_tickers, &jupiter.binance_ticker);
+        }
+        for (key, kind, pools) in [
+            ("whirlpools", "whirlpool", &config_file.dex.whirlpools),
+            (
+                "raydium_pools",
+                "Raydium pool",
+                &config_file.dex.raydium_pools,
+            ),
+            (
+                "openbook_markets",
+                "OpenBook market",
+                &config_file.dex.openbook_markets,
+            ),
+        ] {
+            for (index, pool) in pools.iter().flatten().enumerate() {
+                let address_path = [
+                    Step::Key("dex"),
+                    Step::Key(key),
+                    Step::Index(index),
+                    Step::Key("address"),
+                ];
+                if let Err(err) = Pubkey::from_str(&pool.address) {
+                    error(
+                        &address_path,
+                        format!("invalid {} address {}: {}", kind, pool.address, err),
+                    );
+                }
+                if count(&mut quoted_tickers, &pool.binance_ticker) > 0 {
+                    error(
+                        &address_path,
+                        format!("{} is quoted by more than one DEX", pool.binance_ticker),
+                    );
+                }
+            }
+        }
+        if let Some(vip_tier) = config_file.binance.vip_tier {
+            if let Err(err) = FeeSchedule::new(vip_tier, false) {
+                error(
+                    &[Step::Key("binance"), Step::Key("vip_tier")],
+                    err.to_string(),
+                );
+            }
+        }
+        errors.sort_by_key(|error| error.line);
+
+        if errors.is_empty() {
+            Ok(config_file)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn parse(path: &Path, contents: &str) -> Result<Self, ConfigFileError> {
+        let error = |line, message| ConfigFileError {
+            path: path.to_path_buf(),
+            line,
+            message,
+        };
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => toml::from_str(contents).map_err(|err| {
+                let line = err
+                    .span()
+                    .map(|span| contents[..span.start].matches('\n').count() + 1);
+                error(line, err.message().to_string())
+            }),
+            Some("yaml" | "yml") => serde_yaml::from_str(contents).map_err(|err| {
+                let line = err.location().map(|location| location.line());
+                // The location is reported separately
+                let message = err.to_string();
+                let message = message.split(" at line ").next().unwrap_or_default();
+                error(line, message.to_string())
+            }),
+            _ => Err(error(
+                None,
+                "unsupported format, expected .toml, .yaml or .yml file".to_string(),
+            )),
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<String, ConfigFileError> {
+    std::fs::read_to_string(path).map_err(|err| ConfigFileError {
+        path: path.to_path_buf(),
+        line: None,
+        message: err.to_string(),
+    })
+}
+
+/*
+    Returns how many times the value was counted before, ignoring case
+*/
+fn count(counts: &mut HashMap<String, usize>, value: &str) -> usize {
+    let count = counts.entry(value.to_lowercase()).or_default();
+    *count += 1;
+    *count - 1
+}
+
+/*
+    Step of the path to a value in the file
+*/
+#[derive(Clone, Copy)]
+enum Step<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+/*
+    Returns number of the line holding the value under the path, used for errors found after parsing.
+    The file is deserialized again up to the value, failing right at it, so the line comes from the position
+    the parser attaches to the error.
+*/
+fn find_line(path: &Path, contents: &str, value_path: &[Step]) -> Option<usize> {
+    let probe = Probe(value_path);
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("toml") => {
+            let err = probe.deserialize(toml::Deserializer::new(contents)).err()?;
+            err.span()
+                .map(|span| contents[..span.start].matches('\n').count() + 1)
+        }
+        Some("yaml" | "yml") => {
+            let err = probe
+                .deserialize(serde_yaml::Deserializer::from_str(contents))
+                .err()?;
+            err.location().map(|location| location.line())
+        }
+        _ => None,
+    }
+}
+
+/*
+    Walks down the path, skipping every other value, and fails when it reaches the value under the path.
+    Nothing fails if there is no such value.
+*/
+struct Probe<'a>(&'a [Step<'a>]);
+
+// Fails on any value, from inside of the parser so that the error points at the value
+struct Found;
+
+impl<'de> DeserializeSeed<'de> for Probe<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        if self.0.is_empty() {
+            deserializer.deserialize_any(Found)
+        } else {
+            deserializer.deserialize_any(self)
+        }
+    }
+}
+
+impl<'de> Visitor<'de> for Probe<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            match self.0 {
+                [Step::Key(step), rest @ ..] if *step == key => {
+                    return map.next_value_seed(Probe(rest));
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let [Step::Index(index), rest @ ..] = self.0 else {
+            while seq.next_element::<IgnoredAny>()?.is_some() {}
+            return Ok(());
+        };
+        for _ in 0..*index {
+            if seq.next_element::<IgnoredAny>()?.is_none() {
+                return Ok(());
+            }
+        }
+        seq.next_element_seed(Probe(rest))?;
+        Ok(())
+    }
+
+    fn visit_bool<E: de::Error>(self, _: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E: de::Error>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E: de::Error>(self, _: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E: de::Error>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E: de::Error>(self, _: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<(), E> {
+        Ok(())
+    }
+}
+
+impl<'de> Visitor<'de> for Found {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        // Every visit fails with invalid type, the message is not used
+        formatter.write_str("nothing")
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{path::PathBuf, str::FromStr};
+
+    use rust_decimal::Decimal;
+
+    use super::{ConfidenceBand, ConfigFile};
+    use crate::config::{OrderBookMode, PairConfig};
+
+    /*
+        Writes the contents to a file unique for the test in the temporary directory
+    */
+    pub fn write_config_file(name: &str, contents: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("keyrock-task-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_load_toml() {
+        let path = write_config_file(
+            "load.toml",
+            r#"
+[[pairs]]
+binance_ticker = "solusdt"
+pyth_price_id = "H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG"
+
+[binance]
+rest_url = "http://127.0.0.1:8080"
+order_book = "full"
+
+[pyth]
+max_retries = 5
+
+[arbitrage]
+confidence_multiplier = 1.96
+min_profit = "0.5"
+max_notional = 5000
+confidence_band = "gaussian:95"
+confidence_bands = [{ binance_ticker = "solusdt", band = "bps:15" }]
+"#,
+        );
+
+        let config_file = ConfigFile::load(&path).unwrap();
+        assert_eq!(
+            config_file.pairs,
+            vec![PairConfig {
+                binance_ticker: "solusdt".to_string(),
+                pyth_price_id: "H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG".to_string(),
+            }]
+        );
+        assert_eq!(
+            config_file.binance.rest_url.as_deref(),
+            Some("http://127.0.0.1:8080")
+        );
+        assert_eq!(config_file.binance.order_book, Some(OrderBookMode::Full));
+        assert_eq!(config_file.binance.websocket_url, None);
+        assert_eq!(config_file.pyth.max_retries, Some(5));
+        assert_eq!(
+            config_file.arbitrage.confidence_multiplier,
+            Some(Decimal::from_str("1.96").unwrap())
+        );
+        assert_eq!(
+            config_file.arbitrage.min_profit,
+            Some(Decimal::from_str("0.5").unwrap())
+        );
+        assert_eq!(
+            config_file.arbitrage.max_notional,
+            Some(Decimal::from(5000))
+        );
+        assert_eq!(config_file.arbitrage.min_notional, None);
+        assert_eq!(
+            config_file.arbitrage.confidence_band,
+            Some("gaussian:95".parse().unwrap())
+        );
+        assert_eq!(
+            config_file.arbitrage.confidence_bands.unwrap()[0].band,
+            ConfidenceBand::Bps(Decimal::from(15))
+        );
+    }
+
+    #[test]
+    fn test_load_yaml() {
+        let path = write_config_file(
+            "load.yaml",
+            r#"
+pairs:
+  - binance_ticker: bnbusdt
+    pyth_price_id: 4CkQJBxhU8EZ2UjhigbtdaPbpTe6mqf811fipYBFbSYN
+pyth:
+  hermes_url: https://hermes.pyth.network
+"#,
+        );
+
+        let config_file = ConfigFile::load(&path).unwrap();
+        assert_eq!(config_file.pairs[0].binance_ticker, "bnbusdt");
+        assert_eq!(
+            config_file.pyth.hermes_url.as_deref(),
+            Some("https://hermes.pyth.network")
+        );
+    }
+
+    #[test]
+    fn test_load_reports_line() {
+        // Unknown field in TOML
+        {
+            let path = write_config_file(
+                "unknown_field.toml",
+                "[binance]\nrest_url = \"http://127.0.0.1\"\nrest_timeout = 10\n",
+            );
+            let err = ConfigFile::load(&path).unwrap_err();
+            assert_eq!(err.line, Some(3));
+            assert!(err.message.contains("rest_timeout"));
+            assert_eq!(
+                err.to_string(),
+                format!("{}:3: {}", path.display(), err.message)
+            );
+        }
+
+        // Invalid value in YAML
+        {
+            let path = write_config_file(
+                "invalid_value.yml",
+                "pyth:\n  poll_interval_ms: 400\n  max_retries: many\n",
+            );
+            let err = ConfigFile::load(&path).unwrap_err();
+            assert_eq!(err.line, Some(3));
+            assert!(!err.message.contains("at line"));
+        }
+    }
+
+    #[test]
+    fn test_load_unsupported_format() {
+        let path = write_config_file("unsupported.json", "{}");
+        let err = ConfigFile::load(&path).unwrap_err();
+        assert_eq!(err.line, None);
+    }
+
+    #[test]
+    fn test_validate() {
+        let path = write_config_file(
+            "validate.toml",
+            r#"[[pairs]]
+binance_ticker = "solusdt"
+pyth_price_id = "not-a-pubkey"
+
+[[pairs]]
+binance_ticker = "SOLUSDT"
+pyth_price_id = "H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG"
+"#,
+        );
+
+        let errors = ConfigFile::validate(&path).unwrap_err();
+        assert_eq!(
+            errors.iter().map(|err| err.line).collect::<Vec<_>>(),
+            vec![Some(3), Some(6)]
+        );
+        assert!(errors[1].message.contains("configured twice"));
+
+        let path = write_config_file(
+            "validate_pools.toml",
+            r#"[dex]
+whirlpools = [{ binance_ticker = "solusdc", address = "HJPjoWUrhoZzkNfRpHuieeFk9WcZWjwy6PBjZ81ngndJ" }]
+raydium_pools = [
+    { binance_ticker = "solusdt", address = "not-a-pubkey" },
+    { binance_ticker = "solusdc", address = "58oQChx4yWmvKdwLLZzBi4ChoCc2fqCUWBkwMihLYQo2" },
+]
+"#,
+        );
+        let errors = ConfigFile::validate(&path).unwrap_err();
+        assert_eq!(
+            errors.iter().map(|err| err.line).collect::<Vec<_>>(),
+            vec![Some(4), Some(5)]
+        );
+        assert!(errors[1].message.contains("more than one DEX"));
+
+        // Lines are not confused by the same text appearing earlier in the file
+        let path = write_config_file(
+            "validate_lines.yaml",
+            r#"# SOLUSDT pyth_price_id is not-a-pubkey
+pairs:
+  - binance_ticker: solusdt
+    pyth_price_id: not-a-pubkey
+  - binance_ticker: SOLUSDT
+    pyth_price_id: H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG
+"#,
+        );
+        let errors = ConfigFile::validate(&path).unwrap_err();
+        assert_eq!(
+            errors.iter().map(|err| err.line).collect::<Vec<_>>(),
+            vec![Some(4), Some(5)]
+        );
+
+        // Solana fees are converted into USD only
+        let path = write_config_file(
+            "validate_quote.toml",
+            "[[pairs]]\nbinance_ticker = \"solbnb\"\npyth_price_id = \"H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG\"\n",
+        );
+        let errors = ConfigFile::validate(&path).unwrap_err();
+        assert_eq!(errors[0].line, Some(2));
+        assert!(errors[0].message.contains("not quoted in USD"));
+        let path = write_config_file(
+            "validate_quote_no_fees.toml",
+            "[[pairs]]\nbinance_ticker = \"solbnb\"\npyth_price_id = \"H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG\"\n\n[dex]\nbase_fee_lamports = 0\n",
+        );
+        assert!(ConfigFile::validate(&path).is_ok());
+
+        let path = write_config_file(
+            "valid.toml",
+            "[[pairs]]\nbinance_ticker = \"solusdt\"\npyth_price_id = \"H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG\"\n",
+        );
+        assert!(ConfigFile::validate(&path).is_ok());
+    }
+}
@@ -0,0 +1,37 @@
+/*
+    Library finding arbitrage opportunities between Binance and Solana markets.
+    The binary is a thin CLI on top of App, other services can embed the feeds and the finder directly.
+*/
+pub mod config;
+pub mod structs;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+
+pub use config::{
+    file::ConfigFile, ConfidenceBandConfig, Config, JupiterConfig, OrderBookMode, PairConfig,
+};
+pub use structs::{
+    app::App,
+    arbitrage_finder::{
+        ArbitrageCandidate, ArbitrageCosts, ArbitrageDirection, ArbitrageFinder,
+        ArbitrageOpportunity, ArbitrageSettings, DexCosts, RejectionCounts, RejectionReason,
+    },
+    cex::binance::{
+        Binance, BinanceEvent, BinanceResponse, BinanceStreamData, BookTickerData,
+        PartialDepthData, PriceLevel,
+    },
+    cex::fees::{FeeRates, FeeSchedule},
+    confidence_band::{ConfidenceBand, Quantile},
+    on_chain::{
+        dex_quoter::{DexPoolSource, DexQuote, DexQuoter, SwapMode},
+        hermes::Hermes,
+        jupiter::{Jupiter, JupiterQuote, JupiterQuoter},
+        openbook::{ClobQuoter, OpenBook},
+        orca::{Orca, WhirlpoolQuoter},
+        price_source::{PriceSource, PriceStream},
+        pyth::{Pyth, PythStream},
+        raydium::{ConstantProductQuoter, Raydium},
+    },
+    opportunity_tracker::{OpportunityEvent, OpportunityTracker, TrackedOpportunity},
+    state::{PairState, State},
+};
@@ -1,84 +1,107 @@
-use structs::{
-    arbitrage_finder::ArbitrageFinder,
-    state::{State, STATE},
-};
-use tokio::task::JoinHandle;
+use std::path::Path;
 
-mod config;
-mod structs;
+use keyrock_task::{
+    config::{file::ConfigFile, Command},
+    App, Config, OpportunityEvent,
+};
 
 #[tokio::main]
 async fn main() {
-    let tasks = vec![
-        handle_pyth_price_update().await,
-        handle_binance_ticker_data_update().await,
-        handle_finding_arbitrage_opportunities().await,
-    ];
-
-    handle_shutdown(tasks).await;
-}
-
-async fn handle_pyth_price_update() -> JoinHandle<()> {
-    println!("Spawning Pyth price updater");
+    let config = Config::new();
+    if let Some(Command::ValidateConfig { path }) = &config.command {
+        std::process::exit(validate_config(path));
+    }
 
-    tokio::spawn({
-        let state = STATE.get_or_init(|| async { State::new().await }).await;
+    let mut app = App::new(config)
+        .await
+        .expect("Could not initialize the application");
+    let opportunity_events = (!app.config().quiet).then(|| app.subscribe_opportunity_events());
+    for pair in app.config().pairs() {
+        println!(
+            "Monitoring Binance {} against Pyth price account {}",
+            pair.binance_ticker, pair.pyth_price_id
+        );
+    }
 
-        async move {
-            loop {
-                state.update_latest_pyth_price().await;
+    app.start();
+    if let Some(mut opportunity_events) = opportunity_events {
+        tokio::spawn(async move {
+            while let Ok(event) = opportunity_events.recv().await {
+                match event {
+                    OpportunityEvent::Opened(tracked) => {
+                        println!(
+                            "Opened opportunity #{}!\n{:#?}\n",
+                            tracked.id, tracked.opportunity
+                        );
+                    }
+                    OpportunityEvent::Updated(tracked) => {
+                        println!(
+                            "Updated opportunity #{}\n{:#?}\n",
+                            tracked.id, tracked.opportunity
+                        );
+                    }
+                    OpportunityEvent::Closed(tracked) => {
+                        println!(
+                            "Closed opportunity #{} of {} after {:?}, peak net profit {}\n",
+                            tracked.id,
+                            tracked.opportunity.symbol,
+                            tracked.duration(),
+                            tracked.peak_net_profit
+                        );
+                    }
+                }
             }
-        }
-    })
-}
-
-async fn handle_binance_ticker_data_update() -> JoinHandle<()> {
-    println!("Spawning Binance ticker data updater");
-
-    tokio::spawn({
-        let state = STATE.get_or_init(|| async { State::new().await }).await;
+        });
+    }
 
-        async move {
-            loop {
-                state.update_latest_binance_ticker_data().await;
-            }
-        }
-    })
+    handle_shutdown(app).await;
 }
 
-async fn handle_finding_arbitrage_opportunities() -> JoinHandle<()> {
-    println!("Searching for arbitrage opportunities");
-
-    tokio::spawn({
-        let state = STATE.get_or_init(|| async { State::new().await }).await;
-        let mut arbitrage_finder = ArbitrageFinder::new();
-
-        async move {
-            loop {
-                let maybe_opportunity = arbitrage_finder
-                    .find_opportunity(
-                        state.get_latest_pyth_price(),
-                        state.get_latest_binance_ticker_data(),
-                        state.binance_taker_fee,
-                    )
-                    .await;
-                if let Some(opportunity) = maybe_opportunity {
-                    println!("Found an opportunity!\n{:#?}\n", opportunity);
-                }
-            }
+/*
+    Prints errors found in the configuration file and returns the exit code
+*/
+fn validate_config(path: &Path) -> i32 {
+    match ConfigFile::validate(path) {
+        Ok(_) => {
+            println!("{} is valid", path.display());
+            0
+        }
+        Err(errors) => {
+            errors.iter().for_each(|err| eprintln!("{}", err));
+            1
         }
-    })
+    }
 }
 
-async fn handle_shutdown(tasks: Vec<JoinHandle<()>>) {
+async fn handle_shutdown(mut app: App) {
     match tokio::signal::ctrl_c().await {
         Ok(()) => {
-            println!("\nAborting tasks...");
-            tasks.into_iter().for_each(|task| task.abort());
+            // Tells whether the thresholds or the market suppressed the signals
+            for pair in app.config().pairs() {
+                if let Some(rejections) = app.subscribe_rejections(&pair.binance_ticker) {
+                    let rejections = *rejections.borrow();
+                    println!(
+                        "\nRejected candidates of {}: {} (insufficient liquidity: {}, unprofitable: {}, \
+                        min notional: {}, min profit: {}, min edge: {})",
+                        pair.binance_ticker,
+                        rejections.total(),
+                        rejections.insufficient_liquidity,
+                        rejections.unprofitable,
+                        rejections.min_notional,
+                        rejections.min_profit,
+                        rejections.min_edge
+                    );
+                    println!(
+                        "Directions of {} not crossing the DEX price: {}",
+                        pair.binance_ticker, rejections.not_crossed
+                    );
+                }
+            }
 
-            println!("Terminating Binance WS connection...");
-            let state = STATE.get_or_init(|| async { State::new().await }).await;
-            state.terminate().await;
+            println!("\nAborting tasks and terminating Binance WS connection...");
+            if let Err(err) = app.shutdown().await {
+                eprintln!("Unable to terminate Binance WS connection: {}", err);
+            }
 
             println!("Finished");
         }
@@ -0,0 +1,856 @@
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Result;
+use solana_program::pubkey::Pubkey;
+use tokio::{
+    sync::{broadcast, watch},
+    task::JoinHandle,
+    time::{Instant, MissedTickBehavior},
+};
+
+use crate::config::{Config, USDT_MINT};
+
+use super::{
+    arbitrage_finder::{
+        ArbitrageCandidate, ArbitrageFinder, ArbitrageOpportunity, ArbitrageSettings,
+        RejectionCounts,
+    },
+    on_chain::{
+        dex_quoter::DexQuoter,
+        hermes::Hermes,
+        price_source::{PriceSource, PriceStream},
+        pyth::{Pyth, PythStream},
+    },
+    opportunity_tracker::{OpportunityEvent, OpportunityTracker},
+    state::State,
+};
+
+const OPPORTUNITY_CHANNEL_CAPACITY: usize = 1024;
+
+/*
+    Struct owning a single, independent instance of the application: its configuration,
+    connections, state and the arbitrage finders of the monitored pairs
+*/
+pub struct App {
+    config: Config,
+    state: Arc<State>,
+    price_stream: Option<Arc<dyn PriceStream + Send + Sync>>,
+    opportunities: broadcast::Sender<ArbitrageOpportunity>,
+    opportunity_events: broadcast::Sender<OpportunityEvent>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl App {
+    /*
+        Creates instance of App reading Pyth prices from Hermes or, if it is not configured, from the RPC endpoint
+    */
+    pub async fn new(config: Config) -> Result<Self> {
+        let timeout = Duration::from_millis(config.pyth_rpc_timeout_ms);
+        let price_source: Box<dyn PriceSource + Send + Sync> = match &config.hermes_url {
+            Some(hermes_url) => Box::new(Hermes::new(hermes_url, timeout)?),
+            None => Box::new(Pyth::new(&config.pyth_rpc_url, timeout)),
+        };
+        Self::with_price_source(config, price_source).await
+    }
+
+    /*
+        Creates instance of App polling Pyth prices from the provided source.
+        Price updates are pushed from Hermes or the PubSub endpoint instead, if either is configured.
+    */
+    pub async fn with_price_source(
+        config: Config,
+        price_source: Box<dyn PriceSource + Send + Sync>,
+    ) -> Result<Self> {
+        let timeout = Duration::from_millis(config.pyth_rpc_timeout_ms);
+        let price_stream: Option<Arc<dyn PriceStream + Send + Sync>> =
+            match (&config.hermes_url, &config.pyth_ws_url) {
+                (Some(hermes_url), _) => Some(Arc::new(Hermes::new(hermes_url, timeout)?)),
+                (None, Some(pyth_ws_url)) => Some(Arc::new(PythStream::new(pyth_ws_url))),
+                (None, None) => None,
+            };
+        Self::with_price_sources(config, price_source, price_stream).await
+    }
+
+    /*
+        Creates instance of App with both Pyth price sources provided, the configured Hermes and PubSub endpoints
+        are ignored. Prices are pushed from the stream and polled from the source while it is not subscribed,
+        or only polled if there is no stream. Pushed prices are cleared once they get too old by their publish time,
+        even if the stream never ends, but the stream has to return when it stalls for polling to take over.
+    */
+    pub async fn with_price_sources(
+        config: Config,
+        price_source: Box<dyn PriceSource + Send + Sync>,
+        price_stream: Option<Arc<dyn PriceStream + Send + Sync>>,
+    ) -> Result<Self> {
+        let state = State::new(&config, price_source).await?;
+        let (opportunities, _) = broadcast::channel(OPPORTUNITY_CHANNEL_CAPACITY);
+        let (opportunity_events, _) = broadcast::channel(OPPORTUNITY_CHANNEL_CAPACITY);
+
+        Ok(Self {
+            config,
+            state: Arc::new(state),
+            price_stream,
+            opportunities,
+            opportunity_events,
+            tasks: Vec::new(),
+        })
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /*
+        Returns a receiver of opportunities opened or updated after the call
+    */
+    pub fn subscribe_opportunities(&self) -> broadcast::Receiver<ArbitrageOpportunity> {
+        self.opportunities.subscribe()
+    }
+
+    /*
+        Returns a receiver of opportunity lifecycle events emitted after the call
+    */
+    pub fn subscribe_opportunity_events(&self) -> broadcast::Receiver<OpportunityEvent> {
+        self.opportunity_events.subscribe()
+    }
+
+    /*
+        Returns a receiver of counts of the pair's rejected candidates, if the pair is monitored
+    */
+    pub fn subscribe_rejections(
+        &self,
+        binance_ticker: &str,
+    ) -> Option<watch::Receiver<RejectionCounts>> {
+        self.state
+            .pair(binance_ticker)
+            .map(|pair| pair.subscribe_rejections())
+    }
+
+    /*
+        Spawns an updater per Pyth price feed, the Binance updater, an order book resync task per pair
+        with the full order book and an arbitrage finder per pair
+    */
+    pub fn start(&mut self) {
+        for pyth_price_id in self.state.pyth_price_ids() {
+            self.tasks
+                .push(self.handle_pyth_price_update(pyth_price_id));
+        }
+        self.tasks.push(self.handle_binance_order_book_update());
+        for pair in self.state.pairs() {
+            if pair.has_full_order_book() {
+                self.tasks
+                    .push(self.handle_binance_order_book_resync(&pair.binance_ticker));
+            }
+            if pair.has_dex_pool() {
+                self.tasks
+                    .push(self.handle_dex_pool_update(&pair.binance_ticker));
+            }
+            self.tasks
+                .push(self.handle_finding_arbitrage_opportunities(&pair.binance_ticker));
+        }
+    }
+
+    /*
+        Aborts spawned tasks and unsubscribes from the Binance WS order book streams
+    */
+    pub async fn shutdown(&mut self) -> Result<()> {
+        self.tasks.drain(..).for_each(|task| task.abort());
+        self.state.terminate().await
+    }
+
+    /*
+        Keeps the Pyth feed price up to date, either polling it or, if a price stream is configured, subscribing to it.
+        When the subscription drops or stalls, the price is polled until the next subscription attempt.
+    */
+    fn handle_pyth_price_update(&self, pyth_price_id: Pubkey) -> JoinHandle<()> {
+        let state = self.state.clone();
+        let price_stream = self.price_stream.clone();
+        let resubscribe_interval = Duration::from_millis(self.config.pyth_resubscribe_interval_ms);
+        let mut poll_interval =
+            tokio::time::interval(Duration::from_millis(self.config.pyth_poll_interval_ms));
+        // A slow poll delays the next one instead of causing a burst of catch-up polls
+        poll_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        if price_stream.is_some() {
+            println!("Spawning Pyth price subscriber for {}", pyth_price_id);
+        } else {
+            println!("Spawning Pyth price updater for {}", pyth_price_id);
+        }
+
+        tokio::spawn(async move {
+            loop {
+                if let Some(price_stream) = &price_stream {
+                    match state
+                        .stream_latest_pyth_price(&pyth_price_id, price_stream.as_ref())
+                        .await
+                    {
+                        Ok(()) => eprintln!(
+                            "Pyth price subscription of {} dropped, polling instead",
+                            pyth_price_id
+                        ),
+                        Err(err) => eprintln!(
+                            "Pyth price subscription of {} failed: {}, polling instead",
+                            pyth_price_id, err
+                        ),
+                    }
+                }
+
+                let resubscribe_at = Instant::now() + resubscribe_interval;
+                while price_stream.is_none() || Instant::now() < resubscribe_at {
+                    poll_interval.tick().await;
+                    state.update_latest_pyth_price(&pyth_price_id).await;
+                }
+            }
+        })
+    }
+
+    fn handle_binance_order_book_update(&self) -> JoinHandle<()> {
+        println!("Spawning Binance order book updater");
+        let state = self.state.clone();
+
+        tokio::spawn(async move {
+            loop {
+                state.update_latest_binance_order_book().await;
+            }
+        })
+    }
+
+    fn handle_dex_pool_update(&self, binance_ticker: &str) -> JoinHandle<()> {
+        println!("Spawning DEX pool updater for {}", binance_ticker);
+        let state = self.state.clone();
+        let binance_ticker = binance_ticker.to_string();
+        let mut poll_interval =
+            tokio::time::interval(Duration::from_millis(self.config.dex_poll_interval_ms));
+        poll_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        tokio::spawn(async move {
+            let pair = state.pair(&binance_ticker).unwrap();
+            loop {
+                poll_interval.tick().await;
+                pair.update_latest_dex_quoter().await;
+            }
+        })
+    }
+
+    /*
+        Fetches order book snapshots of the pair whenever its full order book gets out of sync
+    */
+    fn handle_binance_order_book_resync(&self, binance_ticker: &str) -> JoinHandle<()> {
+        let state = self.state.clone();
+        let binance_ticker = binance_ticker.to_string();
+
+        tokio::spawn(async move {
+            // Pairs are fixed for the lifetime of State
+            let pair = state.pair(&binance_ticker).unwrap();
+            loop {
+                pair.resync_binance_order_book().await;
+            }
+        })
+    }
+
+    fn handle_finding_arbitrage_opportunities(&self, binance_ticker: &str) -> JoinHandle<()> {
+        println!(
+            "Searching for arbitrage opportunities of {}",
+            binance_ticker
+        );
+        let state = self.state.clone();
+        let binance_ticker = binance_ticker.to_string();
+        let mut arbitrage_finder = ArbitrageFinder::new(
+            &binance_ticker,
+            ArbitrageSettings {
+                confidence_band: self.config.confidence_band(&binance_ticker),
+                min_profit: self.config.min_profit,
+                min_edge_bps: self.config.min_edge_bps,
+                min_notional: self.config.min_notional,
+                max_notional: self.config.max_notional,
+                dex_costs: self.config.dex_costs(&binance_ticker),
+                normalize_quote: self
+                    .config
+                    .normalizes_usdt(&binance_ticker)
+                    .then(|| Pubkey::from_str(USDT_MINT).unwrap()),
+            },
+        );
+        let mut opportunity_tracker =
+            OpportunityTracker::new(self.config.opportunity_update_tolerance);
+        let opportunities = self.opportunities.clone();
+        let opportunity_events = self.opportunity_events.clone();
+
+        tokio::spawn(async move {
+            // Pairs are fixed for the lifetime of State
+            let pair = state.pair(&binance_ticker).unwrap();
+            let mut latest_pyth_price = pair.subscribe_latest_pyth_price();
+            let mut latest_binance_order_book = pair.subscribe_latest_binance_order_book();
+            let mut latest_sol_price = state.subscribe_latest_sol_price();
+            let mut latest_usdt_price = state.subscribe_latest_usdt_price();
+            let mut latest_dex_quoter = pair.subscribe_latest_dex_quoter();
+
+            loop {
+                // Wakes up only when any of the inputs changes, fails only if State is dropped
+                let changed = tokio::select! {
+                    changed = latest_pyth_price.changed() => changed,
+                    changed = latest_binance_order_book.changed() => changed,
+                    changed = latest_sol_price.changed() => changed,
+                    changed = latest_usdt_price.changed() => changed,
+                    changed = latest_dex_quoter.changed() => changed,
+                };
+                if changed.is_err() {
+                    break;
+                }
+
+                let pyth_price = *latest_pyth_price.borrow_and_update();
+                let binance_order_book = latest_binance_order_book.borrow_and_update().clone();
+                let sol_price = *latest_sol_price.borrow_and_update();
+                let usdt_price = *latest_usdt_price.borrow_and_update();
+                let dex_quoter = latest_dex_quoter.borrow_and_update().clone();
+                // Pyth price is not a substitute for the pool quotes
+                if pair.has_dex_pool() && dex_quoter.is_none() {
+                    continue;
+                }
+
+                let candidates = arbitrage_finder.find_opportunities(
+                    pyth_price,
+                    sol_price,
+                    usdt_price,
+                    dex_quoter
+                        .as_deref()
+                        .map(|dex_quoter| dex_quoter as &dyn DexQuoter),
+                    binance_order_book.as_deref(),
+                    pair.binance_fees.taker,
+                );
+                pair.update_rejections(*arbitrage_finder.rejections());
+                let found: Vec<_> = candidates
+                    .into_iter()
+                    .filter(ArbitrageCandidate::is_accepted)
+                    .map(|candidate| candidate.opportunity)
+                    .collect();
+                for event in opportunity_tracker.update(SystemTime::now(), &found) {
+                    // No receivers is not an error, opportunities are just not observed
+                    if let OpportunityEvent::Opened(tracked) | OpportunityEvent::Updated(tracked) =
+                        &event
+                    {
+                        let _ = opportunities.send(tracked.opportunity.clone());
+                    }
+                    let _ = opportunity_events.send(event);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration};
+
+    use clap::Parser;
+    use rust_decimal::Decimal;
+    use solana_program::pubkey::Pubkey;
+
+    use super::App;
+    use crate::{
+        config::Config,
+        structs::{
+            arbitrage_finder::ArbitrageDirection,
+            cex::binance::{PartialDepthData, PriceLevel},
+            on_chain::pyth::PythStream,
+            opportunity_tracker::OpportunityEvent,
+        },
+        test_support::{
+            eventually,
+            jupiter_fixtures::{sol_usdc_buy_quote, sol_usdc_sell_quote},
+            mock_binance::MockBinanceServer,
+            mock_http::MockHttpServer,
+            mock_pubsub::MockPubsubServer,
+            openbook_fixtures::{market_accounts, sol_usdc_market, LeafFixture},
+            orca_fixtures::{sol_usdc_whirlpool, whirlpool_accounts},
+            pyth_fixtures::{PriceAccountFixture, PriceAccountFixtures},
+            raydium_fixtures::{amm_v4_accounts, sol_usdc_amm_v4},
+            solana_fixtures::{mint_account_data, SOL_MINT, USDC_MINT},
+        },
+    };
+
+    const SOL_USD_PRICE_ID: &str = "H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG";
+    const BNB_USD_PRICE_ID: &str = "4CkQJBxhU8EZ2UjhigbtdaPbpTe6mqf811fipYBFbSYN";
+
+    fn partial_depth(bid: (&str, &str), ask: (&str, &str)) -> PartialDepthData {
+        let level = |(price, quantity): (&str, &str)| PriceLevel {
+            price: Decimal::from_str(price).unwrap(),
+            quantity: Decimal::from_str(quantity).unwrap(),
+        };
+        PartialDepthData {
+            last_update_id: 1,
+            bids: vec![level(bid)],
+            asks: vec![level(ask)],
+        }
+    }
+
+    fn app_config(server: &MockBinanceServer, ticker: &str, extra_args: &[&str]) -> Config {
+        let server_url = server.url();
+        let args = [
+            "keyrock-task",
+            "--binance-ticker",
+            ticker,
+            "--pyth-price-id",
+            SOL_USD_PRICE_ID,
+            "--binance-websocket-url",
+            &server_url,
+        ];
+        Config::parse_from(args.iter().chain(extra_args))
+    }
+
+    fn price_fixtures() -> PriceAccountFixtures {
+        let mut fixtures = PriceAccountFixtures::new();
+        // l: 68.43263012 h: 71.27225988
+        for price_id in [SOL_USD_PRICE_ID, BNB_USD_PRICE_ID] {
+            fixtures.insert(
+                Pubkey::from_str(price_id).unwrap(),
+                PriceAccountFixture {
+                    price: 69852445,
+                    conf: 669724,
+                    expo: -6,
+                    ..Default::default()
+                },
+            );
+        }
+        fixtures
+    }
+
+    async fn start_app(server: &MockBinanceServer, ticker: &str, extra_args: &[&str]) -> App {
+        let config = app_config(server, ticker, extra_args);
+        let mut app = App::with_price_source(config, Box::new(price_fixtures()))
+            .await
+            .unwrap();
+        app.start();
+        app
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_independent_instances() {
+        let sol_server = MockBinanceServer::start().await;
+        let bnb_server = MockBinanceServer::start().await;
+        let mut sol_app = start_app(&sol_server, "solusdt", &[]).await;
+        let mut bnb_app = start_app(&bnb_server, "bnbusdt", &["--binance-bnb-discount"]).await;
+        let mut sol_opportunities = sol_app.subscribe_opportunities();
+        let mut bnb_opportunities = bnb_app.subscribe_opportunities();
+
+        assert_eq!(sol_app.config().binance_ticker, "solusdt");
+        assert_eq!(
+            bnb_app.state.pair("bnbusdt").unwrap().binance_fees.taker,
+            Decimal::new(75, 5)
+        );
+
+        for (server, ticker) in [(&sol_server, "solusdt"), (&bnb_server, "bnbusdt")] {
+            server.push_partial_depth(
+                ticker,
+                &partial_depth(("71.3833", "0.8574"), ("72.0012", "0.9245")),
+            );
+        }
+
+        let sol_opportunity =
+            tokio::time::timeout(Duration::from_secs(1), sol_opportunities.recv())
+                .await
+                .unwrap()
+                .unwrap();
+        let bnb_opportunity =
+            tokio::time::timeout(Duration::from_secs(1), bnb_opportunities.recv())
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(
+            sol_opportunity.direction,
+            ArbitrageDirection::SellBinanceBuyDex
+        );
+        assert_eq!(
+            sol_opportunity.net_profit,
+            Decimal::from_str("0.0336525").unwrap()
+        );
+        assert_eq!(
+            bnb_opportunity.direction,
+            ArbitrageDirection::SellBinanceBuyDex
+        );
+        assert_eq!(
+            bnb_opportunity.net_profit,
+            Decimal::from_str("0.04895351").unwrap()
+        );
+
+        sol_app.shutdown().await.unwrap();
+        bnb_app.shutdown().await.unwrap();
+        eventually(|| sol_server.active_subscriptions().is_empty()).await;
+        eventually(|| bnb_server.active_subscriptions().is_empty()).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_multiple_pairs() {
+        let server = MockBinanceServer::start().await;
+        let pairs = format!("solusdt={},bnbusdt={}", SOL_USD_PRICE_ID, BNB_USD_PRICE_ID);
+        let mut app = start_app(&server, "solusdt", &["--pair", &pairs]).await;
+        let mut opportunities = app.subscribe_opportunities();
+
+        server.push_partial_depth(
+            "solusdt",
+            &partial_depth(("71.3833", "0.8574"), ("72.0012", "0.9245")),
+        );
+        server.push_partial_depth(
+            "bnbusdt",
+            &partial_depth(("67.5421", "1.1258"), ("67.8423", "2.5569")),
+        );
+
+        let mut found = Vec::new();
+        for _ in 0..2 {
+            let opportunity = tokio::time::timeout(Duration::from_secs(1), opportunities.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            found.push((opportunity.symbol, opportunity.direction));
+        }
+        found.sort_by(|left, right| left.0.cmp(&right.0));
+        assert_eq!(
+            found,
+            vec![
+                ("BNBUSDT".to_string(), ArbitrageDirection::BuyBinanceSellDex),
+                ("SOLUSDT".to_string(), ArbitrageDirection::SellBinanceBuyDex),
+            ]
+        );
+
+        app.shutdown().await.unwrap();
+        eventually(|| server.active_subscriptions().is_empty()).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_opportunity_events() {
+        let server = MockBinanceServer::start().await;
+        let mut app = start_app(&server, "solusdt", &[]).await;
+        let mut events = app.subscribe_opportunity_events();
+
+        server.push_partial_depth(
+            "solusdt",
+            &partial_depth(("71.3833", "0.8574"), ("72.0012", "0.9245")),
+        );
+        let OpportunityEvent::Opened(opened) =
+            tokio::time::timeout(Duration::from_secs(1), events.recv())
+                .await
+                .unwrap()
+                .unwrap()
+        else {
+            panic!("expected the opportunity to be opened");
+        };
+        assert_eq!(
+            opened.opportunity.direction,
+            ArbitrageDirection::SellBinanceBuyDex
+        );
+
+        // The bid no longer crosses the Pyth band
+        server.push_partial_depth(
+            "solusdt",
+            &partial_depth(("70.1234", "0.8574"), ("72.0012", "0.9245")),
+        );
+        let OpportunityEvent::Closed(closed) =
+            tokio::time::timeout(Duration::from_secs(1), events.recv())
+                .await
+                .unwrap()
+                .unwrap()
+        else {
+            panic!("expected the opportunity to be closed");
+        };
+        assert_eq!(closed.id, opened.id);
+        assert_eq!(closed.first_seen, opened.first_seen);
+        assert_eq!(closed.peak_net_profit, opened.opportunity.net_profit);
+
+        app.shutdown().await.unwrap();
+        eventually(|| server.active_subscriptions().is_empty()).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_pyth_subscription_falls_back_to_polling() {
+        let binance_server = MockBinanceServer::start().await;
+        let pubsub_server = MockPubsubServer::start().await;
+        let pubsub_url = pubsub_server.url();
+        let mut app = start_app(
+            &binance_server,
+            "solusdt",
+            &[
+                "--pyth-ws-url",
+                &pubsub_url,
+                "--pyth-resubscribe-interval-ms",
+                "200",
+                "--pyth-poll-interval-ms",
+                "10",
+            ],
+        )
+        .await;
+        let mut latest_pyth_price = app
+            .state
+            .pair("solusdt")
+            .unwrap()
+            .subscribe_latest_pyth_price();
+        let price_id = Pubkey::from_str(SOL_USD_PRICE_ID).unwrap();
+
+        // Pushed prices are used while subscribed
+        eventually(|| pubsub_server.active_subscriptions() == vec![price_id.to_string()]).await;
+        assert!(latest_pyth_price.borrow().is_none());
+        let pushed_price = PriceAccountFixture {
+            price: 70000000,
+            expo: -6,
+            ..Default::default()
+        };
+        pubsub_server.notify_account(&price_id, pushed_price.to_account_data());
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            latest_pyth_price.wait_for(|price| price.map(|price| price.price) == Some(70000000)),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        // Polled prices are used once the subscription drops
+        pubsub_server.disconnect();
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            latest_pyth_price.wait_for(|price| price.map(|price| price.price) == Some(69852445)),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        // Subscribes again after the resubscribe interval
+        eventually(|| pubsub_server.total_connections() == 2).await;
+        eventually(|| pubsub_server.active_subscriptions() == vec![price_id.to_string()]).await;
+
+        app.shutdown().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_injected_price_stream() {
+        let binance_server = MockBinanceServer::start().await;
+        let pubsub_server = MockPubsubServer::start().await;
+        // The stream is not configured, only injected
+        let config = app_config(
+            &binance_server,
+            "solusdt",
+            &["--pyth-poll-interval-ms", "60000"],
+        );
+        let mut app = App::with_price_sources(
+            config,
+            Box::new(price_fixtures()),
+            Some(Arc::new(PythStream::new(&pubsub_server.url()))),
+        )
+        .await
+        .unwrap();
+        app.start();
+        let mut latest_pyth_price = app
+            .state
+            .pair("solusdt")
+            .unwrap()
+            .subscribe_latest_pyth_price();
+        let price_id = Pubkey::from_str(SOL_USD_PRICE_ID).unwrap();
+
+        eventually(|| pubsub_server.active_subscriptions() == vec![price_id.to_string()]).await;
+        let pushed_price = PriceAccountFixture {
+            price: 70000000,
+            expo: -6,
+            ..Default::default()
+        };
+        pubsub_server.notify_account(&price_id, pushed_price.to_account_data());
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            latest_pyth_price.wait_for(|price| price.map(|price| price.price) == Some(70000000)),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        app.shutdown().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_whirlpool_quotes() {
+        let binance_server = MockBinanceServer::start().await;
+        let rpc_server = MockHttpServer::start().await;
+        let whirlpool_address = Pubkey::new_unique();
+        rpc_server.solana_accounts(
+            "/",
+            whirlpool_accounts(&whirlpool_address, &sol_usdc_whirlpool()),
+        );
+        let whirlpool = format!("solusdt={}", whirlpool_address);
+        let rpc_url = rpc_server.url();
+        let mut app = start_app(
+            &binance_server,
+            "solusdt",
+            &[
+                "--whirlpool",
+                &whirlpool,
+                "--solana-rpc-url",
+                &rpc_url,
+                "--dex-poll-interval-ms",
+                "10",
+            ],
+        )
+        .await;
+        let mut opportunities = app.subscribe_opportunities();
+
+        // Within the Pyth confidence band, but above the pool price
+        binance_server.push_partial_depth("solusdt", &partial_depth(("71.5", "1"), ("72", "1")));
+
+        let opportunity = tokio::time::timeout(Duration::from_secs(1), opportunities.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(opportunity.direction, ArbitrageDirection::SellBinanceBuyDex);
+        assert_eq!(opportunity.quantity, Decimal::ONE);
+        assert_eq!(opportunity.dex_price, Decimal::from(70));
+        // 71.5 - 0.0715 Binance fee - 70.229213 paid to the pool - 0.00034926 Solana fee
+        assert_eq!(
+            opportunity.net_profit,
+            Decimal::from_str("1.19893774").unwrap()
+        );
+
+        app.shutdown().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_raydium_quotes() {
+        let binance_server = MockBinanceServer::start().await;
+        let rpc_server = MockHttpServer::start().await;
+        let pool_address = Pubkey::new_unique();
+        rpc_server.solana_accounts("/", amm_v4_accounts(&pool_address, &sol_usdc_amm_v4()));
+        let raydium_pool = format!("solusdt={}", pool_address);
+        let rpc_url = rpc_server.url();
+        let mut app = start_app(
+            &binance_server,
+            "solusdt",
+            &[
+                "--raydium-pool",
+                &raydium_pool,
+                "--solana-rpc-url",
+                &rpc_url,
+                "--dex-poll-interval-ms",
+                "10",
+            ],
+        )
+        .await;
+        let mut opportunities = app.subscribe_opportunities();
+
+        binance_server.push_partial_depth("solusdt", &partial_depth(("71.5", "1"), ("72", "1")));
+
+        let opportunity = tokio::time::timeout(Duration::from_secs(1), opportunities.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(opportunity.direction, ArbitrageDirection::SellBinanceBuyDex);
+        assert_eq!(opportunity.quantity, Decimal::ONE);
+        // 71.5 - 0.0715 Binance fee - 70.245686 paid to the pool - 0.00034926 Solana fee
+        assert_eq!(
+            opportunity.net_profit,
+            Decimal::from_str("1.18246474").unwrap()
+        );
+
+        app.shutdown().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_openbook_quotes() {
+        let binance_server = MockBinanceServer::start().await;
+        let rpc_server = MockHttpServer::start().await;
+        let market_address = Pubkey::new_unique();
+        rpc_server.solana_accounts(
+            "/",
+            market_accounts(
+                &market_address,
+                &sol_usdc_market(),
+                &[LeafFixture::new(69900, 1000)],
+                &[LeafFixture::new(70100, 1000)],
+            ),
+        );
+        let openbook_market = format!("solusdt={}", market_address);
+        let rpc_url = rpc_server.url();
+        let mut app = start_app(
+            &binance_server,
+            "solusdt",
+            &[
+                "--openbook-market",
+                &openbook_market,
+                "--solana-rpc-url",
+                &rpc_url,
+                "--dex-poll-interval-ms",
+                "10",
+            ],
+        )
+        .await;
+        let mut opportunities = app.subscribe_opportunities();
+
+        binance_server.push_partial_depth("solusdt", &partial_depth(("71.5", "1"), ("72", "1")));
+
+        let opportunity = tokio::time::timeout(Duration::from_secs(1), opportunities.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(opportunity.direction, ArbitrageDirection::SellBinanceBuyDex);
+        assert_eq!(opportunity.dex_price, Decimal::from_str("70.1").unwrap());
+        // 71.5 - 0.0715 Binance fee - 70.12804 paid for the ask with the taker fee - 0.00034926 Solana fee
+        assert_eq!(
+            opportunity.net_profit,
+            Decimal::from_str("1.30011074").unwrap()
+        );
+
+        app.shutdown().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_jupiter_quotes() {
+        let binance_server = MockBinanceServer::start().await;
+        let jupiter_server = MockHttpServer::start().await;
+        jupiter_server.solana_accounts(
+            "/",
+            HashMap::from([
+                (Pubkey::from_str(SOL_MINT).unwrap(), mint_account_data(9)),
+                (Pubkey::from_str(USDC_MINT).unwrap(), mint_account_data(6)),
+            ]),
+        );
+        jupiter_server.json_by_query(
+            "/quote",
+            vec![
+                (&format!("inputMint={}", USDC_MINT), sol_usdc_buy_quote()),
+                (&format!("inputMint={}", SOL_MINT), sol_usdc_sell_quote()),
+            ],
+        );
+        let jupiter = format!("solusdt={}:{}", SOL_MINT, USDC_MINT);
+        let url = jupiter_server.url();
+        let mut app = start_app(
+            &binance_server,
+            "solusdt",
+            &[
+                "--jupiter",
+                &jupiter,
+                "--jupiter-url",
+                &url,
+                "--jupiter-notional",
+                "700",
+                "--solana-rpc-url",
+                &url,
+                "--dex-poll-interval-ms",
+                "10",
+            ],
+        )
+        .await;
+        let mut opportunities = app.subscribe_opportunities();
+
+        binance_server.push_partial_depth("solusdt", &partial_depth(("71.5", "1"), ("72", "1")));
+
+        let opportunity = tokio::time::timeout(Duration::from_secs(1), opportunities.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(opportunity.direction, ArbitrageDirection::SellBinanceBuyDex);
+        assert_eq!(opportunity.quantity, Decimal::ONE);
+        // 71.5 - 0.0715 Binance fee - 700 / 9.96 at the average price of the route - 0.00034926 Solana fee
+        assert_eq!(
+            opportunity.net_profit,
+            Decimal::from_str("1.14702624").unwrap()
+        );
+
+        app.shutdown().await.unwrap();
+    }
+}
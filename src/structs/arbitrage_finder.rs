@@ -1,143 +1,564 @@
-use std::{str::FromStr, sync::Arc};
-
 use pyth_sdk_solana::Price;
-use rust_decimal::Decimal;
-use tokio::sync::RwLock;
+use rust_decimal::{Decimal, RoundingStrategy};
+use solana_program::pubkey::Pubkey;
 
-use super::cex::binance::BookTickerData;
+use super::{
+    cex::{
+        binance::PriceLevel,
+        order_book::{OrderBook, OrderSide},
+    },
+    confidence_band::ConfidenceBand,
+    on_chain::dex_quoter::{DexQuoter, SwapMode},
+};
 
 /*
-    Struct for finding arbitrage opportunities between Binance and DEXes
+    Struct for finding arbitrage opportunities of a single pair between Binance and DEXes
 */
 pub struct ArbitrageFinder {
+    symbol: String,
+    settings: ArbitrageSettings,
     last_found: Option<ArbitrageOpportunity>,
+    rejections: RejectionCounts,
+}
+
+/*
+    Struct holding tunables of the search
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArbitrageSettings {
+    // Band around the Pyth price bounding the fair price
+    pub confidence_band: ConfidenceBand,
+    // Opportunities with lower net profit are not reported
+    pub min_profit: Decimal,
+    // Opportunities with lower net profit in bps of the Binance notional are not reported
+    pub min_edge_bps: Decimal,
+    // Opportunities with lower Binance notional are not reported
+    pub min_notional: Decimal,
+    // Binance notional of a single opportunity, in the quote currency, is capped at this value, the last level
+    // is taken partially
+    pub max_notional: Option<Decimal>,
+    pub dex_costs: DexCosts,
+    // Mint of the quote currency (e.g. USDT) if Binance prices are converted to USD with its USD price,
+    // instead of assuming it is exactly $1. DEX pools quoted in the same mint are compared without conversion.
+    pub normalize_quote: Option<Pubkey>,
+}
+
+impl Default for ArbitrageSettings {
+    fn default() -> Self {
+        Self {
+            // 95% quantile of Laplace distribution, 2.12 times the confidence interval
+            confidence_band: ConfidenceBand::default(),
+            min_profit: Decimal::ZERO,
+            min_edge_bps: Decimal::ZERO,
+            min_notional: Decimal::ZERO,
+            max_notional: None,
+            dex_costs: DexCosts::default(),
+            normalize_quote: None,
+        }
+    }
+}
+
+/*
+    Struct holding the cost model of the on-chain leg
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DexCosts {
+    // Swap fee of the pool, in bps of the DEX notional
+    pub swap_fee_bps: Decimal,
+    // Estimated price impact of the swap, in bps of the DEX notional
+    pub slippage_bps: Decimal,
+    // Solana transaction fees, paid once per opportunity and converted with the SOL/USD Pyth price, so the pair
+    // has to be quoted in USD
+    pub base_fee_lamports: u64,
+    pub priority_fee_lamports: u64,
+}
+
+impl Default for DexCosts {
+    fn default() -> Self {
+        Self {
+            swap_fee_bps: Decimal::ZERO,
+            slippage_bps: Decimal::ZERO,
+            // Single signature
+            base_fee_lamports: 5000,
+            priority_fee_lamports: 0,
+        }
+    }
+}
+
+impl DexCosts {
+    fn network_fee_lamports(&self) -> u64 {
+        self.base_fee_lamports + self.priority_fee_lamports
+    }
 }
 
 impl ArbitrageFinder {
-    pub fn new() -> Self {
-        return Self { last_found: None };
+    /*
+        Creates a finder tagging opportunities with the provided Binance symbol
+    */
+    pub fn new(symbol: &str, settings: ArbitrageSettings) -> Self {
+        Self {
+            symbol: symbol.to_uppercase(),
+            settings,
+            last_found: None,
+            rejections: RejectionCounts::default(),
+        }
+    }
+
+    /*
+        Returns counts of the candidates rejected so far, by reason
+    */
+    pub fn rejections(&self) -> &RejectionCounts {
+        &self.rejections
     }
 
     /*
-        Compares Binance and Pyth prices to find arbitrage opportunities
+        Returns the best opportunity passing the thresholds, unless it is the same as the last one returned.
+        See find_opportunities for the arguments.
     */
-    pub async fn find_opportunity(
+    pub fn find_opportunity(
         &mut self,
-        latest_pyth_price: Arc<RwLock<Option<Price>>>,
-        latest_binance_ticker_data: Arc<RwLock<Option<BookTickerData>>>,
+        latest_pyth_price: Option<Price>,
+        latest_sol_price: Option<Price>,
+        latest_quote_price: Option<Price>,
+        latest_dex_quoter: Option<&dyn DexQuoter>,
+        latest_binance_order_book: Option<&OrderBook>,
         binance_fee: Decimal,
     ) -> Option<ArbitrageOpportunity> {
-        let (latest_pyth_price_read, latest_binance_ticker_data_read) =
-            tokio::join!(latest_pyth_price.read(), latest_binance_ticker_data.read());
+        let opportunity = self
+            .find_opportunities(
+                latest_pyth_price,
+                latest_sol_price,
+                latest_quote_price,
+                latest_dex_quoter,
+                latest_binance_order_book,
+                binance_fee,
+            )
+            .into_iter()
+            .find(ArbitrageCandidate::is_accepted)?
+            .opportunity;
 
-        if latest_pyth_price_read.is_none() || latest_binance_ticker_data_read.is_none() {
+        if self.last_found.as_ref() == Some(&opportunity) {
             return None;
         }
+        self.last_found = Some(opportunity.clone());
 
-        let pyth_price = (*latest_pyth_price_read).unwrap();
-        drop(latest_pyth_price_read);
-        let binance_ticker_data = (*latest_binance_ticker_data_read).clone().unwrap();
-        drop(latest_binance_ticker_data_read);
+        Some(opportunity)
+    }
 
-        let (pyth_confident_95_price_higher, pyth_confident_95_price_lower) =
-            self.calculate_pyth_confident_95_price(pyth_price);
+    /*
+        Compares Binance order book levels against the DEX in both directions and returns the candidates
+        ranked from the best one, those passing the thresholds first.
+        DEX prices are quoted from the pool if one is provided, otherwise Pyth confidence band is used with
+        the configured DEX costs. The SOL/USD price is needed only to convert Solana transaction fees, if there are any,
+        and the USD price of the Binance quote currency only if the quote is normalized.
+        Nothing is evaluated, so an empty Vec is returned, if any of the needed inputs is missing.
+    */
+    pub fn find_opportunities(
+        &mut self,
+        latest_pyth_price: Option<Price>,
+        latest_sol_price: Option<Price>,
+        latest_quote_price: Option<Price>,
+        latest_dex_quoter: Option<&dyn DexQuoter>,
+        latest_binance_order_book: Option<&OrderBook>,
+        binance_fee: Decimal,
+    ) -> Vec<ArbitrageCandidate> {
+        self.evaluate(
+            latest_pyth_price,
+            latest_sol_price,
+            latest_quote_price,
+            latest_dex_quoter,
+            latest_binance_order_book,
+            binance_fee,
+        )
+        .unwrap_or_default()
+    }
+
+    fn evaluate(
+        &mut self,
+        latest_pyth_price: Option<Price>,
+        latest_sol_price: Option<Price>,
+        latest_quote_price: Option<Price>,
+        latest_dex_quoter: Option<&dyn DexQuoter>,
+        latest_binance_order_book: Option<&OrderBook>,
+        binance_fee: Decimal,
+    ) -> Option<Vec<ArbitrageCandidate>> {
+        let binance_order_book = latest_binance_order_book?;
+        // Binance levels are compared in USD, a depeg of the quote currency is not an opportunity,
+        // unless the DEX pool is quoted in the same currency
+        let dex_quote_mint = latest_dex_quoter.and_then(|dex_quoter| dex_quoter.quote_mint());
+        let normalize_quote = self
+            .settings
+            .normalize_quote
+            .is_some_and(|quote_mint| dex_quote_mint != Some(quote_mint));
+        let quote_rate = if normalize_quote {
+            to_decimal(latest_quote_price?)
+        } else {
+            Decimal::ONE
+        };
+        let to_usd = |level: PriceLevel| PriceLevel {
+            price: level.price.checked_mul(quote_rate).unwrap(),
+            quantity: level.quantity,
+        };
 
-        // Search for SellBinanceBuyDex opportunity
-        let binance_best_bid_price = Decimal::from_str(&binance_ticker_data.b).unwrap();
-        if binance_best_bid_price.gt(&pyth_confident_95_price_higher) {
-            let quantity = Decimal::from_str(&binance_ticker_data.B).unwrap();
-            return self.calculate_arbitrage_opportunity(
-                binance_best_bid_price,
-                pyth_confident_95_price_higher,
+        let network_fee_lamports = self.settings.dex_costs.network_fee_lamports();
+        let solana_fee = if network_fee_lamports == 0 {
+            Decimal::ZERO
+        } else {
+            // Costs of the DEX leg can't be estimated without the SOL price
+            let sol_price = to_decimal(latest_sol_price?);
+            Decimal::new(network_fee_lamports.try_into().unwrap(), 9)
+                .checked_mul(sol_price)
+                .unwrap()
+        };
+
+        let (dex_buy_pricing, dex_sell_pricing) = match latest_dex_quoter {
+            Some(dex_quoter) => (DexPricing::Pool(dex_quoter), DexPricing::Pool(dex_quoter)),
+            None => {
+                let (pyth_confident_price_higher, pyth_confident_price_lower) =
+                    self.calculate_pyth_confident_price(latest_pyth_price?);
+                (
+                    DexPricing::Oracle(pyth_confident_price_higher),
+                    DexPricing::Oracle(pyth_confident_price_lower),
+                )
+            }
+        };
+
+        // Quantity of the Binance side filling the notional cap, not capped if the side is not deep enough
+        let max_quantity = |binance_side| {
+            self.settings.max_notional.and_then(|max_notional| {
+                binance_order_book
+                    .fill_notional(binance_side, max_notional)
+                    .map(|fill| fill.quantity)
+            })
+        };
+        let bids = cap_quantity(binance_order_book.bids(), max_quantity(OrderSide::Sell));
+        let asks = cap_quantity(binance_order_book.asks(), max_quantity(OrderSide::Buy));
+
+        // A crossed or stale book may hold opportunities in both directions at once
+        let mut candidates: Vec<ArbitrageCandidate> = [
+            self.calculate_arbitrage_opportunity(
+                bids.map(to_usd),
+                dex_buy_pricing,
                 binance_fee,
-                quantity,
+                solana_fee,
+                quote_rate,
                 ArbitrageDirection::SellBinanceBuyDex,
-            );
-        }
-
-        // Search for BuyBinanceSellDex opportunity
-        let binance_best_ask_price = Decimal::from_str(&binance_ticker_data.a).unwrap();
-        if binance_best_ask_price.lt(&pyth_confident_95_price_lower) {
-            let quantity = Decimal::from_str(&binance_ticker_data.A).unwrap();
-            return self.calculate_arbitrage_opportunity(
-                binance_best_ask_price,
-                pyth_confident_95_price_lower,
+            ),
+            self.calculate_arbitrage_opportunity(
+                asks.map(to_usd),
+                dex_sell_pricing,
                 binance_fee,
-                quantity,
+                solana_fee,
+                quote_rate,
                 ArbitrageDirection::BuyBinanceSellDex,
-            );
-        }
+            ),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        candidates.sort_by(|a, b| {
+            b.is_accepted()
+                .cmp(&a.is_accepted())
+                .then(b.opportunity.edge_bps.cmp(&a.opportunity.edge_bps))
+        });
 
-        None
+        Some(candidates)
     }
 
     /*
-        Calculates probable (95%) price using Pyth price and confidence feed and Laplace distribution
-        https://docs.pyth.network/documentation/solana-price-feeds/best-practices#confidence-intervals
+        Calculates probable price range using Pyth price and confidence feed bounded by the confidence band,
+        by default the 95% range of Laplace distribution
     */
-    fn calculate_pyth_confident_95_price(&self, pyth_price: Price) -> (Decimal, Decimal) {
-        let exponential = pyth_price.expo.abs() as u32;
+    fn calculate_pyth_confident_price(&self, pyth_price: Price) -> (Decimal, Decimal) {
+        let exponential = pyth_price.expo.unsigned_abs();
         let price = Decimal::new(pyth_price.price, exponential);
         let confidence = Decimal::new(pyth_price.conf.try_into().unwrap(), exponential);
-        let confidence_95 = confidence.checked_mul(Decimal::new(212, 2)).unwrap();
-
-        (
-            price.checked_add(confidence_95).unwrap(),
-            price.checked_sub(confidence_95).unwrap(),
-        )
+        self.settings.confidence_band.bounds(price, confidence)
     }
 
     /*
-        Calculates estimated profit and returns Option<ArbitrageOpportunity> instance depending on the calculation
+        Walks the order book levels from the best one, as long as each of them is still profitable after
+        the Binance fee and the DEX costs of the level, and returns
+        ArbitrageCandidate with the total quantity, its volume weighted price and profit net of all the costs,
+        including the Solana fee. The best level is always taken, so the candidate shows how far from profitable
+        the direction is, and the candidates of a crossed direction not passing the thresholds are counted
+        by the rejection reason. If the DEX can't fill even the best level, the candidate has no quantity and
+        is rejected for the insufficient liquidity.
+        None is returned only if the order book side is empty.
     */
     fn calculate_arbitrage_opportunity(
         &mut self,
-        binance_price: Decimal,
-        pyth_price: Decimal,
+        binance_levels: impl Iterator<Item = PriceLevel>,
+        dex_pricing: DexPricing,
         binance_fee: Decimal,
-        quantity: Decimal,
+        solana_fee: Decimal,
+        quote_rate: Decimal,
         arbitrage_direction: ArbitrageDirection,
-    ) -> Option<ArbitrageOpportunity> {
-        let estimated_profit = (binance_price - pyth_price)
-            .abs()
-            .checked_mul(quantity)
-            .unwrap()
-            - quantity
-                .checked_mul(binance_price)
-                .unwrap()
-                .checked_mul(binance_fee)
-                .unwrap();
+    ) -> Option<ArbitrageCandidate> {
+        let mut binance_levels = binance_levels.peekable();
+        let binance_best_price = binance_levels.peek()?.price;
+        let dex_side = match arbitrage_direction {
+            ArbitrageDirection::SellBinanceBuyDex => OrderSide::Buy,
+            ArbitrageDirection::BuyBinanceSellDex => OrderSide::Sell,
+        };
 
-        if estimated_profit.le(&Decimal::ZERO) {
-            return None;
+        let mut quantity = Decimal::ZERO;
+        let mut notional = Decimal::ZERO;
+        let mut binance_fees = Decimal::ZERO;
+        let mut dex_leg = DexLeg::default();
+
+        for level in binance_levels {
+            let level_notional = level.price.checked_mul(level.quantity).unwrap();
+            let level_binance_fee = level_notional.checked_mul(binance_fee).unwrap();
+            // Quoting fails if the pool can't fill the quantity
+            let Some(next_dex_leg) = dex_pricing.leg(
+                dex_side,
+                quantity + level.quantity,
+                &self.settings.dex_costs,
+            ) else {
+                break;
+            };
+            let level_dex_value = next_dex_leg.value - dex_leg.value;
+            let level_profit = match arbitrage_direction {
+                ArbitrageDirection::SellBinanceBuyDex => level_notional - level_dex_value,
+                ArbitrageDirection::BuyBinanceSellDex => level_dex_value - level_notional,
+            } - level_binance_fee;
+            if level_profit.le(&Decimal::ZERO) && !quantity.is_zero() {
+                break;
+            }
+
+            quantity += level.quantity;
+            notional += level_notional;
+            binance_fees += level_binance_fee;
+            dex_leg = next_dex_leg;
+        }
+
+        let gross_profit = match arbitrage_direction {
+            ArbitrageDirection::SellBinanceBuyDex => notional - dex_leg.spot_value,
+            ArbitrageDirection::BuyBinanceSellDex => dex_leg.spot_value - notional,
+        };
+        let costs = ArbitrageCosts {
+            binance_fee: binance_fees,
+            dex_swap_fee: dex_leg.fee,
+            dex_slippage: dex_leg.slippage,
+            solana_fee,
+        };
+        let net_profit = gross_profit - costs.total();
+        let edge_bps = net_profit
+            .checked_mul(BPS)
+            .unwrap()
+            .checked_div(notional)
+            .unwrap_or_default();
+        let crossed = match arbitrage_direction {
+            ArbitrageDirection::SellBinanceBuyDex => {
+                binance_best_price.gt(&dex_pricing.price(dex_side))
+            }
+            ArbitrageDirection::BuyBinanceSellDex => {
+                binance_best_price.lt(&dex_pricing.price(dex_side))
+            }
+        };
+        let rejection_reason = if !crossed {
+            Some(RejectionReason::NotCrossed)
+        } else if quantity.is_zero() {
+            Some(RejectionReason::InsufficientLiquidity)
+        } else if net_profit.le(&Decimal::ZERO) {
+            Some(RejectionReason::Unprofitable)
+        } else if notional.lt(&self.settings.min_notional) {
+            Some(RejectionReason::MinNotional)
+        } else if net_profit.lt(&self.settings.min_profit) {
+            Some(RejectionReason::MinProfit)
+        } else if edge_bps.lt(&self.settings.min_edge_bps) {
+            Some(RejectionReason::MinEdge)
+        } else {
+            None
+        };
+        if let Some(rejection_reason) = rejection_reason {
+            self.rejections.record(rejection_reason);
         }
 
         let opportunity = ArbitrageOpportunity {
+            symbol: self.symbol.clone(),
             direction: arbitrage_direction,
             quantity: quantity.normalize(),
-            estimated_profit: estimated_profit.normalize().round_dp(8),
-            binance_price: binance_price.normalize(),
-            pyth_price: pyth_price.normalize(),
+            gross_profit: gross_profit.round_dp(8).normalize(),
+            net_profit: net_profit.round_dp(8).normalize(),
+            edge_bps: edge_bps.round_dp(2).normalize(),
+            costs: costs.round_dp(8),
+            // The best price if nothing could be filled
+            binance_price: notional
+                .checked_div(quantity)
+                .unwrap_or(binance_best_price)
+                .round_dp(8)
+                .normalize(),
+            dex_price: dex_pricing.price(dex_side).round_dp(8).normalize(),
+            quote_rate: quote_rate.normalize(),
         };
 
-        if let Some(last_opportunity) = self.last_found {
-            if last_opportunity == opportunity {
-                return None;
-            }
+        Some(ArbitrageCandidate {
+            opportunity,
+            rejection_reason,
+        })
+    }
+}
+
+const BPS: Decimal = Decimal::from_parts(10000, 0, 0, false, 0);
+
+fn to_decimal(price: Price) -> Decimal {
+    Decimal::new(price.price, price.expo.unsigned_abs())
+}
+
+/*
+    Cuts the levels down to the quantity, the last level taken rounded down to its precision
+*/
+fn cap_quantity<'a>(
+    levels: impl Iterator<Item = PriceLevel> + 'a,
+    max_quantity: Option<Decimal>,
+) -> impl Iterator<Item = PriceLevel> + 'a {
+    let mut remaining_quantity = max_quantity;
+    levels.map_while(move |level| {
+        let Some(remaining_quantity) = &mut remaining_quantity else {
+            return Some(level);
+        };
+        let quantity = level
+            .quantity
+            .min(*remaining_quantity)
+            .round_dp_with_strategy(level.quantity.scale(), RoundingStrategy::ToZero);
+        *remaining_quantity -= quantity;
+        (!quantity.is_zero()).then_some(PriceLevel {
+            price: level.price,
+            quantity,
+        })
+    })
+}
+
+/*
+    Source of DEX prices of a single search
+*/
+#[derive(Clone, Copy)]
+enum DexPricing<'a> {
+    // Bound of the Pyth confidence band, with the configured swap fee and slippage
+    Oracle(Decimal),
+    // Quotes of the pool
+    Pool(&'a dyn DexQuoter),
+}
+
+/*
+    DEX leg of the quantity, in the quote currency
+*/
+#[derive(Default)]
+struct DexLeg {
+    value: Decimal,      // paid when buying, received when selling
+    spot_value: Decimal, // at the price before any costs
+    fee: Decimal,
+    slippage: Decimal,
+}
+
+impl DexPricing<'_> {
+    fn price(&self, side: OrderSide) -> Decimal {
+        match self {
+            Self::Oracle(price) => *price,
+            Self::Pool(dex_quoter) => dex_quoter.best_price(side),
         }
-        self.last_found = Some(opportunity);
+    }
+
+    fn leg(&self, side: OrderSide, quantity: Decimal, dex_costs: &DexCosts) -> Option<DexLeg> {
+        let spot_value = quantity.checked_mul(self.price(side)).unwrap();
+        let (value, fee) = match self {
+            Self::Oracle(_) => {
+                let fee = spot_value.checked_mul(dex_costs.swap_fee_bps).unwrap() / BPS;
+                let slippage = spot_value.checked_mul(dex_costs.slippage_bps).unwrap() / BPS;
+                let value = match side {
+                    OrderSide::Buy => spot_value + fee + slippage,
+                    OrderSide::Sell => spot_value - fee - slippage,
+                };
+                (value, fee)
+            }
+            Self::Pool(dex_quoter) => match side {
+                OrderSide::Buy => {
+                    let quote = dex_quoter
+                        .quote(OrderSide::Buy, SwapMode::ExactOut, quantity)
+                        .ok()?;
+                    (quote.amount_in, quote.fee)
+                }
+                OrderSide::Sell => {
+                    let quote = dex_quoter
+                        .quote(OrderSide::Sell, SwapMode::ExactIn, quantity)
+                        .ok()?;
+                    // Fee is paid in the base token
+                    (
+                        quote.amount_out,
+                        quote.fee.checked_mul(self.price(side)).unwrap(),
+                    )
+                }
+            },
+        };
 
-        return self.last_found;
+        // Whatever is not the fee is the price impact
+        let slippage = match side {
+            OrderSide::Buy => value - spot_value - fee,
+            OrderSide::Sell => spot_value - value - fee,
+        };
+        Some(DexLeg {
+            value,
+            spot_value,
+            fee,
+            slippage,
+        })
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ArbitrageOpportunity {
+    pub symbol: String, // Binance symbol, e.g. SOLUSDT
     pub direction: ArbitrageDirection,
-    pub quantity: Decimal,
-    pub estimated_profit: Decimal,
-    pub binance_price: Decimal,
-    pub pyth_price: Decimal,
+    pub quantity: Decimal,      // maximum profitable quantity
+    pub gross_profit: Decimal,  // price difference of the quantity, before any costs
+    pub net_profit: Decimal,    // gross profit minus all the costs
+    pub edge_bps: Decimal,      // net profit in bps of the Binance notional
+    pub costs: ArbitrageCosts,  // costs of both legs, in the quote currency
+    pub binance_price: Decimal, // volume weighted price of the quantity on Binance, converted with quote_rate
+    pub dex_price: Decimal, // Pyth confidence band bound, the pool spot price or the best on-chain bid/ask, before any costs
+    pub quote_rate: Decimal, // USD price of the Binance quote currency used for the conversion, 1 if not normalized
+}
+
+/*
+    Evaluated direction of a single search, reported as an opportunity only if there is no rejection reason
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArbitrageCandidate {
+    pub opportunity: ArbitrageOpportunity, // direction, size, edge and profit of the candidate, which can be negative
+    pub rejection_reason: Option<RejectionReason>,
+}
+
+impl ArbitrageCandidate {
+    pub fn is_accepted(&self) -> bool {
+        self.rejection_reason.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArbitrageCosts {
+    // Binance leg
+    pub binance_fee: Decimal,
+    // DEX leg
+    pub dex_swap_fee: Decimal,
+    pub dex_slippage: Decimal,
+    pub solana_fee: Decimal, // base and priority fees of the transaction
+}
+
+impl ArbitrageCosts {
+    pub fn total(&self) -> Decimal {
+        self.binance_fee + self.dex_swap_fee + self.dex_slippage + self.solana_fee
+    }
+
+    fn round_dp(&self, dp: u32) -> Self {
+        Self {
+            binance_fee: self.binance_fee.round_dp(dp).normalize(),
+            dex_swap_fee: self.dex_swap_fee.round_dp(dp).normalize(),
+            dex_slippage: self.dex_slippage.round_dp(dp).normalize(),
+            solana_fee: self.solana_fee.round_dp(dp).normalize(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -146,21 +567,177 @@ pub enum ArbitrageDirection {
     BuyBinanceSellDex,
 }
 
+/*
+    Reason of not reporting a candidate
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectionReason {
+    // The best Binance level doesn't cross the DEX price, there is no opportunity in the direction
+    NotCrossed,
+    // The DEX can't fill even the quantity of the best Binance level
+    InsufficientLiquidity,
+    // Nothing is left after the costs, the market suppressed it
+    Unprofitable,
+    // The rest are suppressed by the configured thresholds
+    MinNotional,
+    MinProfit,
+    MinEdge,
+}
+
+/*
+    Counts of rejected candidates by reason. Directions which don't cross are counted separately and left out
+    of the total, one of the directions doesn't cross on nearly every update, so their count mostly tells how long
+    the pair was watched.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RejectionCounts {
+    pub not_crossed: u64,
+    pub insufficient_liquidity: u64,
+    pub unprofitable: u64,
+    pub min_notional: u64,
+    pub min_profit: u64,
+    pub min_edge: u64,
+}
+
+impl RejectionCounts {
+    pub fn record(&mut self, reason: RejectionReason) {
+        match reason {
+            RejectionReason::NotCrossed => self.not_crossed += 1,
+            RejectionReason::InsufficientLiquidity => self.insufficient_liquidity += 1,
+            RejectionReason::Unprofitable => self.unprofitable += 1,
+            RejectionReason::MinNotional => self.min_notional += 1,
+            RejectionReason::MinProfit => self.min_profit += 1,
+            RejectionReason::MinEdge => self.min_edge += 1,
+        }
+    }
+
+    /*
+        Returns the count of rejected candidates of crossed directions
+    */
+    pub fn total(&self) -> u64 {
+        self.insufficient_liquidity
+            + self.unprofitable
+            + self.min_notional
+            + self.min_profit
+            + self.min_edge
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::{str::FromStr, sync::Arc};
+    use std::str::FromStr;
 
+    use anyhow::{anyhow, Result};
     use pyth_sdk_solana::Price;
     use rust_decimal::Decimal;
-    use tokio::sync::RwLock;
+    use solana_program::pubkey::Pubkey;
+
+    use crate::{
+        config::USDT_MINT,
+        structs::{
+            cex::{
+                binance::{PartialDepthData, PriceLevel},
+                order_book::{OrderBook, OrderSide},
+            },
+            confidence_band::ConfidenceBand,
+            on_chain::{
+                dex_quoter::{DexQuote, DexQuoter, SwapMode},
+                openbook::ClobQuoter,
+            },
+        },
+    };
+
+    use super::{
+        ArbitrageCosts, ArbitrageDirection, ArbitrageFinder, ArbitrageSettings, DexCosts,
+        RejectionCounts, RejectionReason,
+    };
+
+    fn order_book(bids: &[(&str, &str)], asks: &[(&str, &str)]) -> OrderBook {
+        let levels = |levels: &[(&str, &str)]| {
+            levels
+                .iter()
+                .map(|(price, quantity)| PriceLevel {
+                    price: Decimal::from_str(price).unwrap(),
+                    quantity: Decimal::from_str(quantity).unwrap(),
+                })
+                .collect()
+        };
+        OrderBook::from(&PartialDepthData {
+            last_update_id: 1,
+            bids: levels(bids),
+            asks: levels(asks),
+        })
+    }
 
-    use crate::structs::cex::binance::BookTickerData;
+    /*
+        Pool at 70 with 0.3% fee on the input, moving the price by 0.1 per unit of the base token
+        and holding up to 2 units of it
+    */
+    struct StubQuoter;
+
+    impl DexQuoter for StubQuoter {
+        fn spot_price(&self) -> Decimal {
+            Decimal::from(70)
+        }
+
+        fn quote(&self, side: OrderSide, mode: SwapMode, amount: Decimal) -> Result<DexQuote> {
+            let impact = Decimal::new(1, 1);
+            let fee_rate = Decimal::new(3, 3);
+            if amount > Decimal::TWO {
+                return Err(anyhow!("Not enough liquidity"));
+            }
 
-    use super::{ArbitrageDirection, ArbitrageFinder};
+            match (side, mode) {
+                (OrderSide::Buy, SwapMode::ExactOut) => {
+                    let amount_in = amount * (self.spot_price() + impact * amount);
+                    Ok(DexQuote {
+                        amount_in: amount_in * (Decimal::ONE + fee_rate),
+                        amount_out: amount,
+                        fee: amount_in * fee_rate,
+                    })
+                }
+                (OrderSide::Sell, SwapMode::ExactIn) => {
+                    let fee = amount * fee_rate;
+                    let amount_in = amount - fee;
+                    Ok(DexQuote {
+                        amount_in: amount,
+                        amount_out: amount_in * (self.spot_price() - impact * amount_in),
+                        fee,
+                    })
+                }
+                _ => Err(anyhow!("unsupported quote")),
+            }
+        }
+    }
+
+    // No DEX leg costs, the Binance fee only
+    fn settings() -> ArbitrageSettings {
+        ArbitrageSettings {
+            dex_costs: DexCosts {
+                base_fee_lamports: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn usdt_mint() -> Pubkey {
+        Pubkey::from_str(USDT_MINT).unwrap()
+    }
+
+    // l: 68.43263012 h: 71.27225988
+    fn pyth_price() -> Option<Price> {
+        Some(Price {
+            price: 69852445,
+            conf: 669724,
+            expo: -6,
+            ..Default::default()
+        })
+    }
 
     #[test]
-    fn test_calculate_pyth_confident_95_price() {
-        let arbitrage_finder = ArbitrageFinder::new();
+    fn test_calculate_pyth_confident_price() {
+        let arbitrage_finder = ArbitrageFinder::new("solusdt", ArbitrageSettings::default());
         let price = Price {
             price: 4856126854,
             conf: 612455,
@@ -168,201 +745,843 @@ mod tests {
             ..Default::default()
         };
 
-        let (higher, lower) = arbitrage_finder.calculate_pyth_confident_95_price(price);
+        let (higher, lower) = arbitrage_finder.calculate_pyth_confident_price(price);
         assert_eq!(lower.normalize().to_string(), "48548.284494");
         assert_eq!(higher.normalize().to_string(), "48574.252586");
+
+        let arbitrage_finder = ArbitrageFinder::new(
+            "btcusdt",
+            ArbitrageSettings {
+                confidence_band: ConfidenceBand::Multiplier(Decimal::ONE),
+                ..Default::default()
+            },
+        );
+        let (higher, lower) = arbitrage_finder.calculate_pyth_confident_price(price);
+        assert_eq!(lower.normalize().to_string(), "48555.14399");
+        assert_eq!(higher.normalize().to_string(), "48567.39309");
+
+        let arbitrage_finder = ArbitrageFinder::new(
+            "btcusdt",
+            ArbitrageSettings {
+                confidence_band: "gaussian:99".parse().unwrap(),
+                ..Default::default()
+            },
+        );
+        let (higher, lower) = arbitrage_finder.calculate_pyth_confident_price(price);
+        assert_eq!(lower.normalize().to_string(), "48545.4916992");
+        assert_eq!(higher.normalize().to_string(), "48577.0453808");
     }
 
-    #[tokio::test]
-    async fn test_find_opportunity_data_none() {
-        let mut arbitrage_finder = ArbitrageFinder::new();
+    #[test]
+    fn test_find_opportunity_min_profit() {
+        let latest_binance_order_book =
+            order_book(&[("71.3833", "0.8574")], &[("72.0012", "0.9245")]);
+        let find_opportunity = |min_profit: &str| {
+            ArbitrageFinder::new(
+                "solusdt",
+                ArbitrageSettings {
+                    min_profit: Decimal::from_str(min_profit).unwrap(),
+                    ..settings()
+                },
+            )
+            .find_opportunity(
+                pyth_price(),
+                None,
+                None,
+                None,
+                Some(&latest_binance_order_book),
+                Decimal::new(1, 3),
+            )
+        };
 
-        // Both none
-        {
-            let result = arbitrage_finder
+        // Estimated profit is 0.03400176
+        assert!(find_opportunity("0.034").is_some());
+        assert!(find_opportunity("0.035").is_none());
+    }
+
+    #[test]
+    fn test_find_opportunity_thresholds() {
+        let latest_binance_order_book =
+            order_book(&[("71.3833", "0.8574")], &[("72.0012", "0.9245")]);
+        let find_opportunity = |arbitrage_finder: &mut ArbitrageFinder| {
+            arbitrage_finder.find_opportunity(
+                pyth_price(),
+                None,
+                None,
+                None,
+                Some(&latest_binance_order_book),
+                Decimal::new(1, 3),
+            )
+        };
+
+        // Notional is 61.20404142 and the edge is 5.56 bps
+        let mut arbitrage_finder = ArbitrageFinder::new("solusdt", settings());
+        let opportunity = find_opportunity(&mut arbitrage_finder).unwrap();
+        assert_eq!(opportunity.edge_bps.to_string(), "5.56");
+
+        let mut arbitrage_finder = ArbitrageFinder::new(
+            "solusdt",
+            ArbitrageSettings {
+                min_edge_bps: Decimal::from(6),
+                ..settings()
+            },
+        );
+        assert!(find_opportunity(&mut arbitrage_finder).is_none());
+        assert!(find_opportunity(&mut arbitrage_finder).is_none());
+        assert_eq!(arbitrage_finder.rejections().min_edge, 2);
+        let mut arbitrage_finder = ArbitrageFinder::new(
+            "solusdt",
+            ArbitrageSettings {
+                min_notional: Decimal::from(100),
+                min_profit: Decimal::ONE,
+                ..settings()
+            },
+        );
+        assert!(find_opportunity(&mut arbitrage_finder).is_none());
+        assert_eq!(
+            *arbitrage_finder.rejections(),
+            RejectionCounts {
+                // The other direction doesn't cross
+                not_crossed: 1,
+                min_notional: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(arbitrage_finder.rejections().total(), 1);
+
+        // The level is taken only partially, rounded down to its precision
+        let mut arbitrage_finder = ArbitrageFinder::new(
+            "solusdt",
+            ArbitrageSettings {
+                max_notional: Some(Decimal::from(30)),
+                ..settings()
+            },
+        );
+        let opportunity = find_opportunity(&mut arbitrage_finder).unwrap();
+        assert_eq!(opportunity.quantity.to_string(), "0.4202");
+        assert_eq!(opportunity.edge_bps.to_string(), "5.56");
+
+        // Nothing is left after the Binance fee
+        let mut arbitrage_finder = ArbitrageFinder::new("solusdt", settings());
+        let result = arbitrage_finder.find_opportunity(
+            pyth_price(),
+            None,
+            None,
+            None,
+            Some(&latest_binance_order_book),
+            Decimal::new(1, 2),
+        );
+        assert!(result.is_none());
+        assert_eq!(arbitrage_finder.rejections().unprofitable, 1);
+        assert_eq!(arbitrage_finder.rejections().total(), 1);
+    }
+
+    #[test]
+    fn test_find_opportunities_both_directions() {
+        // Stale book crossing both bounds of the Pyth band
+        let latest_binance_order_book = order_book(&[("71.3833", "0.8574")], &[("68.2012", "0.5")]);
+        let mut arbitrage_finder = ArbitrageFinder::new(
+            "solusdt",
+            ArbitrageSettings {
+                min_edge_bps: Decimal::from(10),
+                ..settings()
+            },
+        );
+        let find_opportunities = |arbitrage_finder: &mut ArbitrageFinder| {
+            arbitrage_finder.find_opportunities(
+                pyth_price(),
+                None,
+                None,
+                None,
+                Some(&latest_binance_order_book),
+                Decimal::new(1, 3),
+            )
+        };
+
+        let candidates = find_opportunities(&mut arbitrage_finder);
+        assert_eq!(candidates.len(), 2);
+        assert!(candidates[0].is_accepted());
+        assert_eq!(
+            candidates[0].opportunity.direction,
+            ArbitrageDirection::BuyBinanceSellDex
+        );
+        assert_eq!(candidates[0].opportunity.edge_bps.to_string(), "23.93");
+        assert_eq!(
+            candidates[1].rejection_reason,
+            Some(RejectionReason::MinEdge)
+        );
+        assert_eq!(
+            candidates[1].opportunity.direction,
+            ArbitrageDirection::SellBinanceBuyDex
+        );
+        assert_eq!(candidates[1].opportunity.edge_bps.to_string(), "5.56");
+
+        // The best one is reported once
+        assert_eq!(
+            arbitrage_finder
                 .find_opportunity(
-                    Arc::new(RwLock::new(None)),
-                    Arc::new(RwLock::new(None)),
-                    Decimal::default(),
+                    pyth_price(),
+                    None,
+                    None,
+                    None,
+                    Some(&latest_binance_order_book),
+                    Decimal::new(1, 3),
                 )
-                .await;
+                .map(|opportunity| opportunity.direction),
+            Some(ArbitrageDirection::BuyBinanceSellDex)
+        );
+        assert!(arbitrage_finder
+            .find_opportunity(
+                pyth_price(),
+                None,
+                None,
+                None,
+                Some(&latest_binance_order_book),
+                Decimal::new(1, 3),
+            )
+            .is_none());
+
+        // Directions not crossing the band are evaluated as well
+        let latest_binance_order_book =
+            order_book(&[("71.2012", "0.8574")], &[("72.0012", "0.9245")]);
+        let rejections = *arbitrage_finder.rejections();
+        let candidates = arbitrage_finder.find_opportunities(
+            pyth_price(),
+            None,
+            None,
+            None,
+            Some(&latest_binance_order_book),
+            Decimal::new(1, 3),
+        );
+        assert!(candidates
+            .iter()
+            .all(|candidate| candidate.rejection_reason == Some(RejectionReason::NotCrossed)));
+        assert!(candidates[0].opportunity.net_profit.is_sign_negative());
+        // but counted apart from the rejections of crossed directions
+        assert_eq!(
+            *arbitrage_finder.rejections(),
+            RejectionCounts {
+                not_crossed: rejections.not_crossed + 2,
+                ..rejections
+            }
+        );
+        assert_eq!(arbitrage_finder.rejections().total(), rejections.total());
+        assert!(arbitrage_finder
+            .find_opportunities(None, None, None, None, None, Decimal::ZERO)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_find_opportunity_data_none() {
+        let mut arbitrage_finder = ArbitrageFinder::new("solusdt", settings());
+
+        // Both none
+        {
+            let result =
+                arbitrage_finder.find_opportunity(None, None, None, None, None, Decimal::default());
             assert!(result.is_none());
         }
 
         // Only binance data none
         {
+            let result = arbitrage_finder.find_opportunity(
+                Some(Price::default()),
+                None,
+                None,
+                None,
+                None,
+                Decimal::default(),
+            );
+            assert!(result.is_none());
+        }
+
+        // Only pyth data none
+        {
+            let result = arbitrage_finder.find_opportunity(
+                None,
+                None,
+                None,
+                None,
+                Some(&OrderBook::default()),
+                Decimal::default(),
+            );
+            assert!(result.is_none());
+        }
+
+        // Empty order book
+        {
+            let result = arbitrage_finder.find_opportunity(
+                pyth_price(),
+                None,
+                None,
+                None,
+                Some(&OrderBook::default()),
+                Decimal::default(),
+            );
+            assert!(result.is_none());
+        }
+    }
+
+    #[test]
+    fn test_find_opportunity() {
+        let mut arbitrage_finder = ArbitrageFinder::new("solusdt", settings());
+
+        // SellBinanceBuyDex direction
+        {
+            let latest_binance_order_book =
+                order_book(&[("71.3833", "0.8574")], &[("72.0012", "0.9245")]);
+
             let result = arbitrage_finder
                 .find_opportunity(
-                    Arc::new(RwLock::new(Some(Price::default()))),
-                    Arc::new(RwLock::new(None)),
-                    Decimal::default(),
+                    pyth_price(),
+                    None,
+                    None,
+                    None,
+                    Some(&latest_binance_order_book),
+                    Decimal::new(1, 3),
                 )
-                .await;
-            assert!(result.is_none());
+                .unwrap();
+            assert_eq!(result.symbol, "SOLUSDT");
+            assert_eq!(result.direction, ArbitrageDirection::SellBinanceBuyDex);
+            assert_eq!(result.quantity, Decimal::from_str("0.8574").unwrap());
+            assert_eq!(result.net_profit, Decimal::from_str("0.03400176").unwrap());
+            assert_eq!(result.binance_price, Decimal::from_str("71.3833").unwrap());
         }
 
-        // Only pyth data none
+        // SellBinanceBuyDex direction, but too large fee
         {
+            let latest_binance_order_book =
+                order_book(&[("71.3833", "0.8574")], &[("72.0012", "0.9245")]);
+
+            let result = arbitrage_finder.find_opportunity(
+                pyth_price(),
+                None,
+                None,
+                None,
+                Some(&latest_binance_order_book),
+                Decimal::new(5, 3),
+            );
+            assert!(result.is_none())
+        }
+
+        // BuyBinanceSellDex direction
+        {
+            let latest_binance_order_book =
+                order_book(&[("67.5421", "1.1258")], &[("67.8423", "2.5569")]);
+
             let result = arbitrage_finder
                 .find_opportunity(
-                    Arc::new(RwLock::new(None)),
-                    Arc::new(RwLock::new(Some(BookTickerData::default()))),
-                    Decimal::default(),
+                    pyth_price(),
+                    None,
+                    None,
+                    None,
+                    Some(&latest_binance_order_book),
+                    Decimal::new(1, 3),
                 )
-                .await;
+                .unwrap();
+            assert_eq!(result.direction, ArbitrageDirection::BuyBinanceSellDex);
+            assert_eq!(result.quantity, Decimal::from_str("2.5569").unwrap());
+            assert_eq!(result.net_profit, Decimal::from_str("1.33594911").unwrap());
+        }
+
+        // BuyBinanceSellDex direction, but too large fee
+        {
+            let latest_binance_order_book =
+                order_book(&[("67.5421", "1.1258")], &[("67.8423", "2.5569")]);
+
+            let result = arbitrage_finder.find_opportunity(
+                pyth_price(),
+                None,
+                None,
+                None,
+                Some(&latest_binance_order_book),
+                Decimal::new(1, 2),
+            );
+            assert!(result.is_none());
+        }
+
+        // No opportunity found
+        {
+            let latest_binance_order_book =
+                order_book(&[("69.2222", "1.1258")], &[("69.1111", "2.5569")]);
+
+            let result = arbitrage_finder.find_opportunity(
+                pyth_price(),
+                None,
+                None,
+                None,
+                Some(&latest_binance_order_book),
+                Decimal::new(1, 3),
+            );
             assert!(result.is_none());
         }
     }
 
-    #[tokio::test]
-    async fn test_find_opportunity() {
-        let mut arbitrage_finder = ArbitrageFinder::new();
+    #[test]
+    fn test_find_opportunity_walks_levels() {
+        let mut arbitrage_finder = ArbitrageFinder::new("solusdt", settings());
 
-        // SellBinanceBuyDex direction
+        // SellBinanceBuyDex direction, stops at the first level not profitable after the fee
         {
-            // l: 68.43263012 h: 71.27225988
-            let latest_pyth_price = Arc::new(RwLock::new(Some(Price {
-                price: 69852445,
-                conf: 669724,
-                expo: -6,
-                ..Default::default()
-            })));
-            let latest_binance_ticker_data = Arc::new(RwLock::new(Some(BookTickerData {
-                b: "71.3833".to_string(),
-                B: "0.8574".to_string(),
-                a: "72.0012".to_string(),
-                A: "0.9245".to_string(),
-                ..Default::default()
-            })));
+            let latest_binance_order_book = order_book(
+                &[
+                    ("71.3833", "0.8574"),
+                    ("71.35", "1"),
+                    ("71.30", "5"),
+                    ("71.20", "10"),
+                ],
+                &[("72.0012", "0.9245")],
+            );
 
             let result = arbitrage_finder
                 .find_opportunity(
-                    latest_pyth_price,
-                    latest_binance_ticker_data,
+                    pyth_price(),
+                    None,
+                    None,
+                    None,
+                    Some(&latest_binance_order_book),
                     Decimal::new(1, 3),
                 )
-                .await
                 .unwrap();
             assert_eq!(result.direction, ArbitrageDirection::SellBinanceBuyDex);
-            assert_eq!(result.quantity, Decimal::from_str("0.8574").unwrap());
+            assert_eq!(result.quantity, Decimal::from_str("1.8574").unwrap());
+            assert_eq!(result.net_profit, Decimal::from_str("0.04039188").unwrap());
             assert_eq!(
-                result.estimated_profit,
-                Decimal::from_str("0.03400176").unwrap()
+                result.binance_price,
+                Decimal::from_str("71.36537171").unwrap()
             );
         }
 
-        // SellBinanceBuyDex direction, but too large fee
+        // BuyBinanceSellDex direction, stops at the first level not profitable after the fee
         {
-            // l: 68.43263012 h: 71.27225988
-            let latest_pyth_price = Arc::new(RwLock::new(Some(Price {
-                price: 69852445,
-                conf: 669724,
-                expo: -6,
-                ..Default::default()
-            })));
-            let latest_binance_ticker_data = Arc::new(RwLock::new(Some(BookTickerData {
-                b: "71.3833".to_string(),
-                B: "0.8574".to_string(),
-                a: "72.0012".to_string(),
-                A: "0.9245".to_string(),
-                ..Default::default()
-            })));
+            let latest_binance_order_book = order_book(
+                &[("67.5421", "1.1258")],
+                &[("67.8423", "2.5569"), ("68", "3"), ("68.4", "10")],
+            );
 
             let result = arbitrage_finder
                 .find_opportunity(
-                    latest_pyth_price,
-                    latest_binance_ticker_data,
-                    Decimal::new(5, 3),
+                    pyth_price(),
+                    None,
+                    None,
+                    None,
+                    Some(&latest_binance_order_book),
+                    Decimal::new(1, 3),
                 )
-                .await;
-            assert!(result.is_none())
+                .unwrap();
+            assert_eq!(result.direction, ArbitrageDirection::BuyBinanceSellDex);
+            assert_eq!(result.quantity, Decimal::from_str("5.5569").unwrap());
+            assert_eq!(result.net_profit, Decimal::from_str("2.42983947").unwrap());
+            assert_eq!(
+                result.binance_price,
+                Decimal::from_str("67.9274374").unwrap()
+            );
         }
 
-        // BuyBinanceSellDex direction
+        // Levels on the other side of the Pyth price are never used, even with no fee
         {
-            // l: 68.43263012 h: 71.27225988
-            let latest_pyth_price = Arc::new(RwLock::new(Some(Price {
-                price: 69852445,
-                conf: 669724,
-                expo: -6,
-                ..Default::default()
-            })));
-            let latest_binance_ticker_data = Arc::new(RwLock::new(Some(BookTickerData {
-                b: "67.5421".to_string(),
-                B: "1.1258".to_string(),
-                a: "67.8423".to_string(),
-                A: "2.5569".to_string(),
+            let latest_binance_order_book = order_book(
+                &[("71.3833", "0.8574"), ("60", "100")],
+                &[("72.0012", "0.9245")],
+            );
+
+            let result = arbitrage_finder
+                .find_opportunity(
+                    pyth_price(),
+                    None,
+                    None,
+                    None,
+                    Some(&latest_binance_order_book),
+                    Decimal::ZERO,
+                )
+                .unwrap();
+            assert_eq!(result.quantity, Decimal::from_str("0.8574").unwrap());
+        }
+    }
+
+    #[test]
+    fn test_find_opportunity_dex_costs() {
+        let latest_binance_order_book =
+            order_book(&[("71.3833", "0.8574")], &[("72.0012", "0.9245")]);
+        let mut arbitrage_finder = ArbitrageFinder::new(
+            "solusdt",
+            ArbitrageSettings {
+                dex_costs: DexCosts {
+                    swap_fee_bps: Decimal::ONE,
+                    slippage_bps: Decimal::from_str("0.5").unwrap(),
+                    base_fee_lamports: 5000,
+                    priority_fee_lamports: 10000,
+                },
                 ..Default::default()
-            })));
+            },
+        );
+
+        // Solana fee can't be converted without SOL price
+        {
+            let result = arbitrage_finder.find_opportunity(
+                pyth_price(),
+                None,
+                None,
+                None,
+                Some(&latest_binance_order_book),
+                Decimal::new(1, 3),
+            );
+            assert!(result.is_none());
+        }
 
+        // Per leg costs
+        {
             let result = arbitrage_finder
                 .find_opportunity(
-                    latest_pyth_price,
-                    latest_binance_ticker_data,
+                    pyth_price(),
+                    pyth_price(),
+                    None,
+                    None,
+                    Some(&latest_binance_order_book),
                     Decimal::new(1, 3),
                 )
-                .await
                 .unwrap();
-            assert_eq!(result.direction, ArbitrageDirection::BuyBinanceSellDex);
-            assert_eq!(result.quantity, Decimal::from_str("2.5569").unwrap());
+            assert_eq!(result.quantity, Decimal::from_str("0.8574").unwrap());
+            assert_eq!(result.gross_profit, Decimal::from_str("0.0952058").unwrap());
             assert_eq!(
-                result.estimated_profit,
-                Decimal::from_str("1.33594911").unwrap()
+                result.costs,
+                ArbitrageCosts {
+                    binance_fee: Decimal::from_str("0.06120404").unwrap(),
+                    dex_swap_fee: Decimal::from_str("0.00611088").unwrap(),
+                    dex_slippage: Decimal::from_str("0.00305544").unwrap(),
+                    solana_fee: Decimal::from_str("0.00104779").unwrap(),
+                }
             );
+            assert_eq!(result.net_profit, Decimal::from_str("0.02378765").unwrap());
         }
 
-        // BuyBinanceSellDex direction, but too large fee
+        // Solana fee larger than the profit
         {
-            // l: 68.43263012 h: 71.27225988
-            let latest_pyth_price = Arc::new(RwLock::new(Some(Price {
-                price: 69852445,
-                conf: 669724,
-                expo: -6,
-                ..Default::default()
-            })));
-            let latest_binance_ticker_data = Arc::new(RwLock::new(Some(BookTickerData {
-                b: "67.5421".to_string(),
-                B: "1.1258".to_string(),
-                a: "67.8423".to_string(),
-                A: "2.5569".to_string(),
-                ..Default::default()
-            })));
+            let mut arbitrage_finder = ArbitrageFinder::new(
+                "solusdt",
+                ArbitrageSettings {
+                    dex_costs: DexCosts {
+                        priority_fee_lamports: 1_000_000,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            );
+            let result = arbitrage_finder.find_opportunity(
+                pyth_price(),
+                pyth_price(),
+                None,
+                None,
+                Some(&latest_binance_order_book),
+                Decimal::new(1, 3),
+            );
+            assert!(result.is_none());
+        }
+    }
+
+    #[test]
+    fn test_find_opportunity_dex_pool() {
+        let mut arbitrage_finder = ArbitrageFinder::new("solusdc", settings());
+
+        // SellBinanceBuyDex direction, stops when the pool can't fill the quantity
+        {
+            let latest_binance_order_book =
+                order_book(&[("71.5", "1"), ("71", "1"), ("70.5", "5")], &[("72", "1")]);
 
             let result = arbitrage_finder
                 .find_opportunity(
-                    latest_pyth_price,
-                    latest_binance_ticker_data,
-                    Decimal::new(1, 2),
+                    None,
+                    None,
+                    None,
+                    Some(&StubQuoter),
+                    Some(&latest_binance_order_book),
+                    Decimal::new(1, 3),
                 )
-                .await;
-            assert!(result.is_none());
+                .unwrap();
+            assert_eq!(result.direction, ArbitrageDirection::SellBinanceBuyDex);
+            assert_eq!(result.quantity, Decimal::TWO);
+            assert_eq!(result.dex_price, Decimal::from(70));
+            assert_eq!(result.gross_profit, Decimal::from_str("2.5").unwrap());
+            assert_eq!(
+                result.costs,
+                ArbitrageCosts {
+                    binance_fee: Decimal::from_str("0.1425").unwrap(),
+                    dex_swap_fee: Decimal::from_str("0.4212").unwrap(),
+                    dex_slippage: Decimal::from_str("0.4").unwrap(),
+                    solana_fee: Decimal::ZERO,
+                }
+            );
+            assert_eq!(result.net_profit, Decimal::from_str("1.5363").unwrap());
         }
 
-        // No opportunity found
+        // The pool can't fill the best level, both directions are still reported
         {
-            // l: 68.43263012 h: 71.27225988
-            let latest_pyth_price = Arc::new(RwLock::new(Some(Price {
-                price: 69852445,
-                conf: 669724,
-                expo: -6,
-                ..Default::default()
-            })));
-            let latest_binance_ticker_data = Arc::new(RwLock::new(Some(BookTickerData {
-                b: "69.2222".to_string(),
-                B: "1.1258".to_string(),
-                a: "69.1111".to_string(),
-                A: "2.5569".to_string(),
-                ..Default::default()
-            })));
+            let latest_binance_order_book = order_book(&[("71.5", "3")], &[("72", "1")]);
+            let rejections = *arbitrage_finder.rejections();
+
+            let candidates = arbitrage_finder.find_opportunities(
+                None,
+                None,
+                None,
+                Some(&StubQuoter),
+                Some(&latest_binance_order_book),
+                Decimal::new(1, 3),
+            );
+            assert_eq!(candidates.len(), 2);
+            let candidate = candidates
+                .iter()
+                .find(|candidate| {
+                    candidate.opportunity.direction == ArbitrageDirection::SellBinanceBuyDex
+                })
+                .unwrap();
+            assert_eq!(
+                candidate.rejection_reason,
+                Some(RejectionReason::InsufficientLiquidity)
+            );
+            assert!(candidate.opportunity.quantity.is_zero());
+            assert_eq!(
+                candidate.opportunity.binance_price,
+                Decimal::from_str("71.5").unwrap()
+            );
+            assert_eq!(
+                arbitrage_finder.rejections().insufficient_liquidity,
+                rejections.insufficient_liquidity + 1
+            );
+        }
+
+        // BuyBinanceSellDex direction, stops at the first level not profitable after the price impact
+        {
+            let latest_binance_order_book =
+                order_book(&[("67", "1")], &[("68", "1"), ("69.95", "1")]);
 
             let result = arbitrage_finder
                 .find_opportunity(
-                    latest_pyth_price,
-                    latest_binance_ticker_data,
+                    None,
+                    None,
+                    None,
+                    Some(&StubQuoter),
+                    Some(&latest_binance_order_book),
                     Decimal::new(1, 3),
                 )
-                .await;
+                .unwrap();
+            assert_eq!(result.direction, ArbitrageDirection::BuyBinanceSellDex);
+            assert_eq!(result.quantity, Decimal::ONE);
+            assert_eq!(result.gross_profit, Decimal::TWO);
+            assert_eq!(
+                result.costs,
+                ArbitrageCosts {
+                    binance_fee: Decimal::from_str("0.068").unwrap(),
+                    dex_swap_fee: Decimal::from_str("0.21").unwrap(),
+                    dex_slippage: Decimal::from_str("0.0994009").unwrap(),
+                    solana_fee: Decimal::ZERO,
+                }
+            );
+            assert_eq!(result.net_profit, Decimal::from_str("1.6225991").unwrap());
+        }
+
+        // Pyth price inside the spread of the pool is ignored
+        {
+            let latest_binance_order_book = order_book(&[("69.9", "1")], &[("70.1", "1")]);
+
+            let result = arbitrage_finder.find_opportunity(
+                pyth_price(),
+                None,
+                None,
+                Some(&StubQuoter),
+                Some(&latest_binance_order_book),
+                Decimal::new(1, 3),
+            );
+            assert!(result.is_none());
+        }
+    }
+
+    #[test]
+    fn test_find_opportunity_clob() {
+        let mut arbitrage_finder = ArbitrageFinder::new("solusdc", settings());
+        let clob_quoter = ClobQuoter::new(
+            order_book(
+                &[("69.9", "1"), ("69.8", "2")],
+                &[("70.1", "1"), ("70.2", "2")],
+            ),
+            Decimal::new(4, 4),
+            Pubkey::default(),
+        );
+
+        // Binance bid above the mid price but inside the spread of the on-chain book
+        {
+            let latest_binance_order_book = order_book(&[("70.05", "1")], &[("72", "1")]);
+
+            let result = arbitrage_finder.find_opportunity(
+                None,
+                None,
+                None,
+                Some(&clob_quoter),
+                Some(&latest_binance_order_book),
+                Decimal::new(1, 3),
+            );
             assert!(result.is_none());
         }
+
+        // SellBinanceBuyDex direction, takes the asks of the on-chain book
+        {
+            let latest_binance_order_book = order_book(&[("71", "3")], &[("72", "1")]);
+
+            let result = arbitrage_finder
+                .find_opportunity(
+                    None,
+                    None,
+                    None,
+                    Some(&clob_quoter),
+                    Some(&latest_binance_order_book),
+                    Decimal::new(1, 3),
+                )
+                .unwrap();
+            assert_eq!(result.direction, ArbitrageDirection::SellBinanceBuyDex);
+            assert_eq!(result.quantity, Decimal::from(3));
+            assert_eq!(result.dex_price, Decimal::from_str("70.1").unwrap());
+            assert_eq!(result.gross_profit, Decimal::from_str("2.7").unwrap());
+            assert_eq!(
+                result.costs,
+                ArbitrageCosts {
+                    binance_fee: Decimal::from_str("0.213").unwrap(),
+                    dex_swap_fee: Decimal::from_str("0.0842").unwrap(),
+                    dex_slippage: Decimal::from_str("0.2").unwrap(),
+                    solana_fee: Decimal::ZERO,
+                }
+            );
+            assert_eq!(result.net_profit, Decimal::from_str("2.2028").unwrap());
+        }
+
+        // BuyBinanceSellDex direction, takes the bids of the on-chain book
+        {
+            let latest_binance_order_book = order_book(&[("68", "1")], &[("69", "1")]);
+
+            let result = arbitrage_finder
+                .find_opportunity(
+                    None,
+                    None,
+                    None,
+                    Some(&clob_quoter),
+                    Some(&latest_binance_order_book),
+                    Decimal::new(1, 3),
+                )
+                .unwrap();
+            assert_eq!(result.direction, ArbitrageDirection::BuyBinanceSellDex);
+            assert_eq!(result.dex_price, Decimal::from_str("69.9").unwrap());
+            assert_eq!(
+                result.costs.dex_swap_fee,
+                Decimal::from_str("0.02796").unwrap()
+            );
+            assert_eq!(result.costs.dex_slippage, Decimal::ZERO);
+            assert_eq!(result.net_profit, Decimal::from_str("0.80304").unwrap());
+        }
+    }
+
+    #[test]
+    fn test_find_opportunity_normalized_quote() {
+        let mut arbitrage_finder = ArbitrageFinder::new(
+            "solusdt",
+            ArbitrageSettings {
+                normalize_quote: Some(usdt_mint()),
+                ..settings()
+            },
+        );
+        let latest_binance_order_book = order_book(&[("71.4", "1")], &[("72", "1")]);
+        let usdt_price = |price: i64| {
+            Some(Price {
+                price,
+                conf: 10000,
+                expo: -8,
+                ..Default::default()
+            })
+        };
+
+        // USDT/USD price is required
+        let result = arbitrage_finder.find_opportunity(
+            pyth_price(),
+            None,
+            None,
+            None,
+            Some(&latest_binance_order_book),
+            Decimal::new(1, 3),
+        );
+        assert!(result.is_none());
+
+        // 20 bps depeg, 71.4 USDT is only 71.2572 USD, below the Pyth confidence band
+        let result = arbitrage_finder.find_opportunity(
+            pyth_price(),
+            None,
+            usdt_price(99800000),
+            None,
+            Some(&latest_binance_order_book),
+            Decimal::new(1, 3),
+        );
+        assert!(result.is_none());
+
+        // 5 bps depeg, 71.4 USDT is 71.3643 USD
+        let result = arbitrage_finder
+            .find_opportunity(
+                pyth_price(),
+                None,
+                usdt_price(99950000),
+                None,
+                Some(&latest_binance_order_book),
+                Decimal::new(1, 3),
+            )
+            .unwrap();
+        assert_eq!(result.direction, ArbitrageDirection::SellBinanceBuyDex);
+        assert_eq!(result.quote_rate, Decimal::from_str("0.9995").unwrap());
+        assert_eq!(result.binance_price, Decimal::from_str("71.3643").unwrap());
+        assert_eq!(result.net_profit, Decimal::from_str("0.02067582").unwrap());
+
+        // Pools quoted in another mint are compared in USD, which needs the USDT/USD price
+        let dex_order_book = order_book(&[("70.9", "1")], &[("71", "1")]);
+        let clob_quoter = ClobQuoter::new(dex_order_book.clone(), Decimal::ZERO, Pubkey::default());
+        let result = arbitrage_finder.find_opportunity(
+            pyth_price(),
+            None,
+            None,
+            Some(&clob_quoter),
+            Some(&latest_binance_order_book),
+            Decimal::new(1, 3),
+        );
+        assert!(result.is_none());
+
+        // Pools quoted in USDT are compared in USDT as they are
+        let clob_quoter = ClobQuoter::new(dex_order_book, Decimal::ZERO, usdt_mint());
+        let result = arbitrage_finder
+            .find_opportunity(
+                pyth_price(),
+                None,
+                None,
+                Some(&clob_quoter),
+                Some(&latest_binance_order_book),
+                Decimal::new(1, 3),
+            )
+            .unwrap();
+        assert_eq!(result.direction, ArbitrageDirection::SellBinanceBuyDex);
+        assert_eq!(result.quote_rate, Decimal::ONE);
+        assert_eq!(result.binance_price, Decimal::from_str("71.4").unwrap());
+
+        // The notional is capped in USDT, 0.5002 SOL would fit into the cap in USD
+        let mut arbitrage_finder = ArbitrageFinder::new(
+            "solusdt",
+            ArbitrageSettings {
+                normalize_quote: Some(usdt_mint()),
+                max_notional: Some(Decimal::from_str("35.7").unwrap()),
+                ..settings()
+            },
+        );
+        let result = arbitrage_finder
+            .find_opportunity(
+                pyth_price(),
+                None,
+                usdt_price(99950000),
+                None,
+                Some(&order_book(&[("71.4", "1.0000")], &[("72", "1")])),
+                Decimal::new(1, 3),
+            )
+            .unwrap();
+        assert_eq!(result.quantity, Decimal::from_str("0.5").unwrap());
     }
 }
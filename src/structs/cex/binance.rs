@@ -1,135 +1,277 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::{
+    collections::HashSet,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::{anyhow, Result};
 use futures_util::{
     stream::{SplitSink, SplitStream},
-    FutureExt, SinkExt, StreamExt,
+    SinkExt, StreamExt,
 };
-use serde::Deserialize;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use tokio::{net::TcpStream, sync::RwLock};
 use tokio_tungstenite::{
     tungstenite::{handshake::client::Response, Message},
     MaybeTlsStream, WebSocketStream,
 };
 
-const BINANCE_WEBSOCKET_URL: &str = "wss://stream.binance.com:9443/stream";
+const RECONNECT_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+type BinanceSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
 
 /*
     Struct representing Binance CEX responsible for connecting to Binance WS and fetching data about provided ticker/pair
 */
 pub struct Binance {
-    write: RwLock<SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>>,
-    read: RwLock<SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>>,
+    url: String,
+    write: RwLock<SplitSink<BinanceSocket, Message>>,
+    read: RwLock<SplitStream<BinanceSocket>>,
+    subscriptions: RwLock<HashSet<String>>,
 }
 
 impl Binance {
     /*
-        Connects to the WS
+        Connects to the WS under the provided URL
     */
-    pub async fn connect() -> Result<(Self, Response)> {
-        let (socket, response) = tokio_tungstenite::connect_async(BINANCE_WEBSOCKET_URL).await?;
+    pub async fn connect(url: &str) -> Result<(Self, Response)> {
+        let (socket, response) = tokio_tungstenite::connect_async(url).await?;
         let (write, read) = socket.split();
         Ok((
             Self {
+                url: url.to_string(),
                 write: RwLock::new(write),
                 read: RwLock::new(read),
+                subscriptions: RwLock::new(HashSet::new()),
             },
             response,
         ))
     }
 
     /*
-        Subscribes to a stream providing data about the ticker/pair
+        Subscribes to a stream providing best bid/ask data about the ticker/pair
     */
     pub async fn subscribe_to_ticker(&self, ticker: &str) -> Result<i64> {
+        self.subscribe_to_stream(&BookTickerData::stream(ticker))
+            .await
+    }
+
+    /*
+        Subscribes to a stream providing top 20 levels of the order book of the ticker/pair
+    */
+    pub async fn subscribe_to_partial_depth(&self, ticker: &str) -> Result<i64> {
+        self.subscribe_to_stream(&PartialDepthData::stream(ticker))
+            .await
+    }
+
+    /*
+        Subscribes to the stream with the provided name, returning id of the request
+    */
+    pub async fn subscribe_to_stream(&self, stream: &str) -> Result<i64> {
+        self.subscribe_to_streams(&[stream.to_string()]).await
+    }
+
+    /*
+        Subscribes to all the streams with a single request, returning its id
+    */
+    pub async fn subscribe_to_streams(&self, streams: &[String]) -> Result<i64> {
         let current_timestamp = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_millis() as i64; // doesn't overflow
-        let subscribe_request = format!(
-            "{{\"method\":\"SUBSCRIBE\",\"params\":[\"{}@bookTicker\"],\"id\":{}}}",
-            ticker, current_timestamp
-        );
-        let message = Message::Text(subscribe_request);
-
-        self.write.write().await.send(message).await.unwrap();
-        let maybe_result = self.read.write().await.next().await; // The first message is a response to the subscribe request
-
-        if let Some(inner) = maybe_result {
-            let message = String::from_utf8(inner.unwrap().into_data()).unwrap();
-            if !message.contains("\"result\":null") {
-                return Err(anyhow!(format!(
-                    "Could not subscribe for ticker {}: {}",
-                    ticker, message
-                )));
-            }
-        }
+
+        self.send_request("SUBSCRIBE", streams, current_timestamp)
+            .await
+            .map_err(|err| {
+                anyhow!(
+                    "Could not subscribe to streams {}: {}",
+                    streams.join(", "),
+                    err
+                )
+            })?;
+        self.subscriptions
+            .write()
+            .await
+            .extend(streams.iter().cloned());
 
         Ok(current_timestamp)
     }
 
     /*
-        Unsubscribes from the stream associated with the provided ticker and id returned while subscribing to it.
+        Unsubscribes from the bookTicker stream associated with the provided ticker and id returned while subscribing to it.
     */
     pub async fn unsubscribe(&self, ticker: &str, id: i64) -> Result<()> {
-        let unsubscribe_request = format!(
-            "{{\"method\":\"UNSUBSCRIBE\",\"params\":[\"{}@bookTicker\"],\"id\":{}}}",
-            ticker, id
-        );
-        let message = Message::Text(unsubscribe_request);
+        self.unsubscribe_from_stream(&BookTickerData::stream(ticker), id)
+            .await
+    }
+
+    /*
+        Unsubscribes from the stream and closes the connection, waiting for the confirmation of the request with the provided id
+    */
+    pub async fn unsubscribe_from_stream(&self, stream: &str, id: i64) -> Result<()> {
+        self.unsubscribe_from_streams(&[stream.to_string()], id)
+            .await
+    }
+
+    /*
+        Unsubscribes from all the streams and closes the connection, waiting for the confirmation of the request with the provided id
+    */
+    pub async fn unsubscribe_from_streams(&self, streams: &[String], id: i64) -> Result<()> {
+        let mut subscriptions = self.subscriptions.write().await;
+        streams.iter().for_each(|stream| {
+            subscriptions.remove(stream);
+        });
+        drop(subscriptions);
+
+        let unsubscribe_request = json!({
+            "method": "UNSUBSCRIBE",
+            "params": streams,
+            "id": id,
+        });
+        let message = Message::Text(unsubscribe_request.to_string());
 
         let mut write_write_lock = self.write.write().await;
-        write_write_lock.send(message).await.unwrap();
-        write_write_lock.close().await.unwrap();
+        write_write_lock.send(message).await?;
+        write_write_lock.close().await?;
         drop(write_write_lock);
 
         let mut read_write_lock = self.read.write().await;
         while let Some(inner) = read_write_lock.next().await {
-            let message = String::from_utf8(inner.unwrap().into_data()).unwrap();
+            let message = String::from_utf8(inner?.into_data())?;
             if message.contains("\"result\":null") {
                 return Ok(());
             }
         }
 
         Err(anyhow!(format!(
-            "Could not unsubscribe for ticker {} and id {}",
-            ticker, id
+            "Could not unsubscribe from streams {} and id {}",
+            streams.join(", "),
+            id
         )))
     }
 
     /*
-        Reads the next element of the stream and parses the JSON into BinanceResponse object
+        Reads the next element of the stream and parses the JSON into BinanceResponse object.
+        A closed or errored stream is reconnected before returning BinanceEvent::Reconnected.
     */
-    pub async fn read_next_message(&self) -> Option<BinanceResponse> {
-        self.read
-            .write()
-            .await
-            .next()
-            .then(|element| async {
-                if let Some(result) = element {
-                    let message = result.unwrap();
-
-                    if let Message::Ping(ping) = message {
-                        self.write
-                            .write()
-                            .await
-                            .send(Message::Pong(ping))
-                            .await
-                            .unwrap();
-                        return None;
-                    }
-
-                    let message_str = String::from_utf8(message.into_data()).unwrap();
-
-                    return Some(serde_json::from_str::<BinanceResponse>(&message_str).unwrap());
-                }
+    pub async fn read_next_message(&self) -> Option<BinanceEvent> {
+        let element = self.read.write().await.next().await;
 
+        match element {
+            Some(Ok(Message::Text(message))) => serde_json::from_str::<BinanceResponse>(&message)
+                .ok()
+                .map(BinanceEvent::Data),
+            Some(Ok(Message::Ping(ping))) => {
+                let pong_result = self.write.write().await.send(Message::Pong(ping)).await;
+                if let Err(err) = pong_result {
+                    eprintln!("Could not respond to Binance WS ping: {}", err);
+                    return Some(self.reconnect().await);
+                }
                 None
-            })
+            }
+            Some(Ok(Message::Close(_))) | Some(Err(_)) | None => Some(self.reconnect().await),
+            Some(Ok(_)) => None,
+        }
+    }
+
+    /*
+        Reconnects to the WS with exponential backoff until it succeeds and replays active subscriptions
+    */
+    async fn reconnect(&self) -> BinanceEvent {
+        let mut backoff = RECONNECT_INITIAL_BACKOFF;
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+            match self.try_reconnect().await {
+                Ok(()) => return BinanceEvent::Reconnected { attempts },
+                Err(err) => {
+                    eprintln!(
+                        "Binance WS reconnect attempt {} failed: {}, retrying in {:?}",
+                        attempts, err, backoff
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(RECONNECT_MAX_BACKOFF);
+                }
+            }
+        }
+    }
+
+    /*
+        Replaces the underlying socket with a new one and resubscribes to every active stream
+    */
+    async fn try_reconnect(&self) -> Result<()> {
+        let (socket, _) = tokio_tungstenite::connect_async(&self.url).await?;
+        let (write, read) = socket.split();
+        *self.write.write().await = write;
+        *self.read.write().await = read;
+
+        let streams = self
+            .subscriptions
+            .read()
+            .await
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        if streams.is_empty() {
+            return Ok(());
+        }
+
+        let id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64; // doesn't overflow
+        self.send_request("SUBSCRIBE", &streams, id).await
+    }
+
+    /*
+        Sends a request and waits for the response carrying the same id
+    */
+    async fn send_request(&self, method: &str, streams: &[String], id: i64) -> Result<()> {
+        let request = json!({
+            "method": method,
+            "params": streams,
+            "id": id,
+        });
+        self.write
+            .write()
             .await
+            .send(Message::Text(request.to_string()))
+            .await?;
+
+        let mut read_write_lock = self.read.write().await;
+        while let Some(inner) = read_write_lock.next().await {
+            let Message::Text(message) = inner? else {
+                continue;
+            };
+            let Ok(response) = serde_json::from_str::<Value>(&message) else {
+                continue;
+            };
+            if response.get("id").and_then(Value::as_i64) != Some(id) {
+                continue;
+            }
+
+            return match response.get("result") {
+                Some(Value::Null) => Ok(()),
+                _ => Err(anyhow!(message)),
+            };
+        }
+
+        Err(anyhow!("Stream closed before receiving a response"))
     }
 }
 
+/*
+    Events produced while reading the stream
+*/
+#[derive(Debug)]
+pub enum BinanceEvent {
+    Data(BinanceResponse),
+    Reconnected { attempts: u32 },
+}
+
 /*
     Structs representing JSON messages from the stream
 */
@@ -137,11 +279,28 @@ impl Binance {
 #[derive(Debug, Deserialize)]
 pub struct BinanceResponse {
     pub stream: String,
-    pub data: BookTickerData,
+    pub data: BinanceStreamData,
+}
+
+impl BinanceResponse {
+    /*
+        Returns the ticker/pair from the stream name, e.g. solusdt for solusdt@depth20@100ms
+    */
+    pub fn ticker(&self) -> &str {
+        self.stream.split('@').next().unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum BinanceStreamData {
+    BookTicker(BookTickerData),
+    PartialDepth(PartialDepthData),
+    DepthUpdate(DepthUpdateData),
 }
 
 #[allow(non_snake_case)]
-#[derive(Debug, Deserialize, Clone, Default)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
 pub struct BookTickerData {
     pub u: u64,    // order book updateId
     pub s: String, // symbol
@@ -151,24 +310,107 @@ pub struct BookTickerData {
     pub A: String, // best ask quantity
 }
 
+impl BookTickerData {
+    pub fn stream(ticker: &str) -> String {
+        format!("{}@bookTicker", ticker)
+    }
+}
+
+// Also returned by the /api/v3/depth REST endpoint as the order book snapshot
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct PartialDepthData {
+    #[serde(rename = "lastUpdateId")]
+    pub last_update_id: u64,
+    pub bids: Vec<PriceLevel>, // sorted from the best (highest) price
+    pub asks: Vec<PriceLevel>, // sorted from the best (lowest) price
+}
+
+impl PartialDepthData {
+    pub fn stream(ticker: &str) -> String {
+        format!("{}@depth20@100ms", ticker)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct DepthUpdateData {
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "U")]
+    pub first_update_id: u64,
+    #[serde(rename = "u")]
+    pub final_update_id: u64,
+    #[serde(rename = "b")]
+    pub bids: Vec<PriceLevel>, // absolute quantities, 0 removes the level
+    #[serde(rename = "a")]
+    pub asks: Vec<PriceLevel>, // absolute quantities, 0 removes the level
+}
+
+impl DepthUpdateData {
+    pub fn stream(ticker: &str) -> String {
+        format!("{}@depth@100ms", ticker)
+    }
+}
+
+// Binance encodes levels as [price, quantity] pairs of strings
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(from = "(Decimal, Decimal)", into = "(Decimal, Decimal)")]
+pub struct PriceLevel {
+    pub price: Decimal,
+    pub quantity: Decimal,
+}
+
+impl From<(Decimal, Decimal)> for PriceLevel {
+    fn from((price, quantity): (Decimal, Decimal)) -> Self {
+        Self { price, quantity }
+    }
+}
+
+impl From<PriceLevel> for (Decimal, Decimal) {
+    fn from(level: PriceLevel) -> Self {
+        (level.price, level.quantity)
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
     use futures_util::StreamExt;
     use tokio_tungstenite::tungstenite::http::StatusCode;
 
-    use super::Binance;
+    use std::str::FromStr;
+
+    use rust_decimal::Decimal;
+
+    use super::{
+        Binance, BinanceEvent, BinanceStreamData, BookTickerData, PartialDepthData, PriceLevel,
+    };
+    use crate::test_support::{eventually, mock_binance::MockBinanceServer};
+
+    fn book_ticker(symbol: &str) -> BookTickerData {
+        BookTickerData {
+            u: 400900217,
+            s: symbol.to_string(),
+            b: "25.35190000".to_string(),
+            B: "31.21000000".to_string(),
+            a: "25.36520000".to_string(),
+            A: "40.66000000".to_string(),
+        }
+    }
 
     #[tokio::test]
     async fn test_connect() {
-        let (_, response) = Binance::connect().await.unwrap();
+        let server = MockBinanceServer::start().await;
+        let (_, response) = Binance::connect(&server.url()).await.unwrap();
         assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
     }
 
     #[tokio::test]
     async fn test_subscribe_to_ticker() {
-        let (binance, _) = Binance::connect().await.unwrap();
+        let server = MockBinanceServer::start().await;
+        let (binance, _) = Binance::connect(&server.url()).await.unwrap();
         let id = binance.subscribe_to_ticker("btcusdt").await.unwrap();
         assert!(
             id <= SystemTime::now()
@@ -176,13 +418,26 @@ mod test {
                 .unwrap()
                 .as_millis() as i64
         );
+        assert!(server.active_subscriptions().contains("btcusdt@bookTicker"));
+        assert_eq!(server.requests()[0]["id"], id);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_to_ticker_rejected() {
+        let server = MockBinanceServer::start().await;
+        server.reject_subscriptions(true);
+        let (binance, _) = Binance::connect(&server.url()).await.unwrap();
+        let result = binance.subscribe_to_ticker("btcusdt").await;
+        assert!(result.is_err());
     }
 
     #[tokio::test]
     async fn test_unsubscribe() {
-        let (binance, _) = Binance::connect().await.unwrap();
+        let server = MockBinanceServer::start().await;
+        let (binance, _) = Binance::connect(&server.url()).await.unwrap();
         let id = binance.subscribe_to_ticker("btcusdt").await.unwrap();
         binance.unsubscribe("btcusdt", id).await.unwrap();
+        assert_eq!(server.requests()[1]["method"], "UNSUBSCRIBE");
 
         let _ = binance.read.write().await.next().await;
         assert_eq!(binance.read.into_inner().count().await, 0);
@@ -190,19 +445,159 @@ mod test {
 
     #[tokio::test]
     async fn test_read_next_message_no_subscription() {
-        let (binance, _) = Binance::connect().await.unwrap();
+        let server = MockBinanceServer::start().await;
+        let (binance, _) = Binance::connect(&server.url()).await.unwrap();
+        server.push_book_ticker(&book_ticker("BTCUSDT"));
         let result =
-            tokio::time::timeout(Duration::from_secs(1), binance.read_next_message()).await;
+            tokio::time::timeout(Duration::from_millis(200), binance.read_next_message()).await;
         assert!(result.is_err());
     }
 
     #[tokio::test]
     async fn test_read_next_message() {
-        let (binance, _) = Binance::connect().await.unwrap();
+        let server = MockBinanceServer::start().await;
+        let (binance, _) = Binance::connect(&server.url()).await.unwrap();
         binance.subscribe_to_ticker("btcusdt").await.unwrap();
+        server.push_book_ticker(&book_ticker("BTCUSDT"));
 
-        let next_message = binance.read_next_message().await.unwrap();
+        let Some(BinanceEvent::Data(next_message)) = binance.read_next_message().await else {
+            panic!("Expected a data message");
+        };
         assert_eq!(next_message.stream, "btcusdt@bookTicker".to_string());
-        assert_eq!(next_message.data.s, "BTCUSDT".to_string());
+        assert_eq!(next_message.ticker(), "btcusdt");
+        assert_eq!(
+            next_message.data,
+            BinanceStreamData::BookTicker(book_ticker("BTCUSDT"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_next_message_partial_depth() {
+        let server = MockBinanceServer::start().await;
+        let (binance, _) = Binance::connect(&server.url()).await.unwrap();
+        binance.subscribe_to_partial_depth("btcusdt").await.unwrap();
+        assert!(server
+            .active_subscriptions()
+            .contains("btcusdt@depth20@100ms"));
+        server.push_frame(
+            "btcusdt@depth20@100ms",
+            r#"{"stream":"btcusdt@depth20@100ms","data":{"lastUpdateId":160,"bids":[["25.35190000","31.21000000"],["25.35000000","2.00000000"]],"asks":[["25.36520000","40.66000000"]]}}"#.to_string(),
+        );
+
+        let Some(BinanceEvent::Data(next_message)) = binance.read_next_message().await else {
+            panic!("Expected a data message");
+        };
+        let level = |price: &str, quantity: &str| PriceLevel {
+            price: Decimal::from_str(price).unwrap(),
+            quantity: Decimal::from_str(quantity).unwrap(),
+        };
+        assert_eq!(
+            next_message.data,
+            BinanceStreamData::PartialDepth(PartialDepthData {
+                last_update_id: 160,
+                bids: vec![level("25.3519", "31.21"), level("25.35", "2")],
+                asks: vec![level("25.3652", "40.66")],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_next_message_ping() {
+        let server = MockBinanceServer::start().await;
+        let (binance, _) = Binance::connect(&server.url()).await.unwrap();
+        server.ping(b"keepalive");
+
+        assert!(binance.read_next_message().await.is_none());
+        eventually(|| server.pongs() == vec![b"keepalive".to_vec()]).await;
+    }
+
+    #[tokio::test]
+    async fn test_read_next_message_reconnects_after_disconnect() {
+        let server = MockBinanceServer::start().await;
+        let (binance, _) = Binance::connect(&server.url()).await.unwrap();
+        binance.subscribe_to_ticker("btcusdt").await.unwrap();
+        binance.subscribe_to_ticker("ethusdt").await.unwrap();
+        server.disconnect();
+
+        let event = binance.read_next_message().await;
+        assert!(matches!(
+            event,
+            Some(BinanceEvent::Reconnected { attempts: 1 })
+        ));
+        assert_eq!(server.total_connections(), 2);
+        assert_eq!(
+            server.active_subscriptions(),
+            ["btcusdt@bookTicker", "ethusdt@bookTicker"]
+                .map(str::to_string)
+                .into()
+        );
+
+        server.push_book_ticker(&book_ticker("ETHUSDT"));
+        let Some(BinanceEvent::Data(next_message)) = binance.read_next_message().await else {
+            panic!("Expected a data message");
+        };
+        assert_eq!(
+            next_message.data,
+            BinanceStreamData::BookTicker(book_ticker("ETHUSDT"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_next_message_reconnect_retries_with_backoff() {
+        let server = MockBinanceServer::start().await;
+        let (binance, _) = Binance::connect(&server.url()).await.unwrap();
+        binance.subscribe_to_ticker("btcusdt").await.unwrap();
+        // Replaying the subscription fails until the server accepts it again
+        server.reject_subscriptions(true);
+        server.disconnect();
+
+        let started = Instant::now();
+        let (event, _) = tokio::join!(binance.read_next_message(), async {
+            // Second attempt after the initial 500ms backoff
+            eventually(|| server.total_connections() == 3).await;
+            server.reject_subscriptions(false);
+        });
+        assert!(matches!(
+            event,
+            Some(BinanceEvent::Reconnected { attempts: 3 })
+        ));
+        // The backoff doubles after each failed attempt, 500ms + 1s
+        assert!(started.elapsed() >= Duration::from_millis(1500));
+        assert_eq!(server.total_connections(), 4);
+        assert_eq!(
+            server.active_subscriptions(),
+            ["btcusdt@bookTicker"].map(str::to_string).into()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_next_message_reconnects_after_close_frame() {
+        let server = MockBinanceServer::start().await;
+        let (binance, _) = Binance::connect(&server.url()).await.unwrap();
+        binance.subscribe_to_ticker("btcusdt").await.unwrap();
+        server.close();
+
+        let event = binance.read_next_message().await;
+        assert!(matches!(event, Some(BinanceEvent::Reconnected { .. })));
+        assert_eq!(
+            server.active_subscriptions(),
+            ["btcusdt@bookTicker"].map(str::to_string).into()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_next_message_reconnect_skips_unsubscribed() {
+        let server = MockBinanceServer::start().await;
+        let (binance, _) = Binance::connect(&server.url()).await.unwrap();
+        binance.subscribe_to_ticker("btcusdt").await.unwrap();
+        let id = binance.subscribe_to_ticker("ethusdt").await.unwrap();
+        binance.unsubscribe("ethusdt", id).await.unwrap();
+
+        let event = binance.read_next_message().await;
+        assert!(matches!(event, Some(BinanceEvent::Reconnected { .. })));
+        assert_eq!(
+            server.active_subscriptions(),
+            ["btcusdt@bookTicker"].map(str::to_string).into()
+        );
     }
 }
@@ -0,0 +1,215 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::{binance::PartialDepthData, fees::CommissionData};
+
+/*
+    Struct representing Binance REST API, used for the data not available over the WS streams
+*/
+pub struct BinanceRest {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+/*
+    API key and secret of a Binance account, used for the signed (USER_DATA) endpoints
+*/
+#[derive(Clone)]
+pub struct BinanceCredentials {
+    pub api_key: String,
+    pub api_secret: String,
+}
+
+impl BinanceCredentials {
+    /*
+        Returns hex encoded HMAC SHA256 signature of the query string
+    */
+    fn sign(&self, query: &str) -> String {
+        // HMAC accepts keys of any length
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.api_secret.as_bytes()).unwrap();
+        mac.update(query.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+}
+
+impl BinanceRest {
+    /*
+        Creates a client for the REST API under the provided URL
+    */
+    pub fn new(base_url: &str, timeout: Duration) -> Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::builder().timeout(timeout).build()?,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /*
+        Fetches the order book snapshot of the ticker/pair with up to `limit` levels on each side
+    */
+    pub async fn get_depth_snapshot(&self, ticker: &str, limit: u32) -> Result<PartialDepthData> {
+        let snapshot = self
+            .client
+            .get(format!("{}/api/v3/depth", self.base_url))
+            .query(&[
+                ("symbol", ticker.to_uppercase()),
+                ("limit", limit.to_string()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<PartialDepthData>()
+            .await?;
+
+        Ok(snapshot)
+    }
+
+    /*
+        Fetches the commission rates of the account for the ticker/pair
+    */
+    pub async fn get_commission_rates(
+        &self,
+        ticker: &str,
+        credentials: &BinanceCredentials,
+    ) -> Result<CommissionData> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let query = format!("symbol={}&timestamp={}", ticker.to_uppercase(), timestamp);
+        let signature = credentials.sign(&query);
+
+        let commission = self
+            .client
+            .get(format!(
+                "{}/api/v3/account/commission?{}&signature={}",
+                self.base_url, query, signature
+            ))
+            .header("X-MBX-APIKEY", &credentials.api_key)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<CommissionData>()
+            .await?;
+
+        Ok(commission)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, time::Duration};
+
+    use rust_decimal::Decimal;
+    use serde_json::json;
+
+    use super::{BinanceCredentials, BinanceRest};
+    use crate::test_support::mock_http::{MockHttpServer, MockResponse};
+
+    #[tokio::test]
+    async fn test_get_depth_snapshot() {
+        let server = MockHttpServer::start().await;
+        server.json(
+            "/api/v3/depth",
+            json!({
+                "lastUpdateId": 1027024,
+                "bids": [["71.38330000", "0.85740000"]],
+                "asks": [["72.00120000", "0.92450000"]],
+            }),
+        );
+        let binance_rest = BinanceRest::new(&server.url(), Duration::from_secs(1)).unwrap();
+
+        let snapshot = binance_rest
+            .get_depth_snapshot("solusdt", 1000)
+            .await
+            .unwrap();
+        assert_eq!(snapshot.last_update_id, 1027024);
+        assert_eq!(
+            snapshot.bids[0].price,
+            Decimal::from_str("71.3833").unwrap()
+        );
+        assert_eq!(
+            snapshot.asks[0].quantity,
+            Decimal::from_str("0.9245").unwrap()
+        );
+        assert_eq!(server.requests()[0].query, "symbol=SOLUSDT&limit=1000");
+    }
+
+    #[tokio::test]
+    async fn test_get_depth_snapshot_error() {
+        let server = MockHttpServer::start().await;
+        server.route(
+            "/api/v3/depth",
+            MockResponse::Json {
+                status: 400,
+                body: json!({ "code": -1121, "msg": "Invalid symbol." }),
+            },
+        );
+        let binance_rest = BinanceRest::new(&server.url(), Duration::from_secs(1)).unwrap();
+
+        let result = binance_rest.get_depth_snapshot("notapair", 1000).await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_sign() {
+        // Example from https://developers.binance.com/docs/binance-spot-api-docs/rest-api#signed-endpoint-examples-for-post-apiv3order---hmac-keys
+        let credentials = BinanceCredentials {
+            api_key: "vmPUZE6mv9SD5VNHk4HlWFsOr6aKE2zvsw0MuIgwCIPy6utIco14y7Ju91duEh8A".to_string(),
+            api_secret: "NhqPtmdSJYdKjVHjA7PZj4Mge3R5YNiP1e3UZjInClVN65XAbvqqM6A7H5fATj0j"
+                .to_string(),
+        };
+        assert_eq!(
+            credentials.sign("symbol=LTCBTC&side=BUY&type=LIMIT&timeInForce=GTC&quantity=1&price=0.1&recvWindow=5000&timestamp=1499827319559"),
+            "c8db56825ae71d6d79447849e617115f4a920fa2acdcab2b053c4b2838bd6b71"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_commission_rates() {
+        let server = MockHttpServer::start().await;
+        server.json(
+            "/api/v3/account/commission",
+            json!({
+                "symbol": "SOLUSDT",
+                "standardCommission": {
+                    "maker": "0.00000000",
+                    "taker": "0.00050000",
+                    "buyer": "0.00000000",
+                    "seller": "0.00000000"
+                },
+                "taxCommission": {
+                    "maker": "0.00000000",
+                    "taker": "0.00000000",
+                    "buyer": "0.00000000",
+                    "seller": "0.00000000"
+                },
+                "discount": {
+                    "enabledForAccount": false,
+                    "enabledForSymbol": true,
+                    "discountAsset": "BNB",
+                    "discount": "0.75000000"
+                }
+            }),
+        );
+        let binance_rest = BinanceRest::new(&server.url(), Duration::from_secs(1)).unwrap();
+        let credentials = BinanceCredentials {
+            api_key: "key".to_string(),
+            api_secret: "secret".to_string(),
+        };
+
+        let commission = binance_rest
+            .get_commission_rates("solusdt", &credentials)
+            .await
+            .unwrap();
+        assert_eq!(
+            commission.fee_rates(true).taker,
+            Decimal::from_str("0.0005").unwrap()
+        );
+
+        let request = &server.requests()[0];
+        assert_eq!(request.headers["x-mbx-apikey"], "key");
+        let (query, signature) = request.query.split_once("&signature=").unwrap();
+        assert!(query.starts_with("symbol=SOLUSDT&timestamp="));
+        assert_eq!(signature, credentials.sign(query));
+    }
+}
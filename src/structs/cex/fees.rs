@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+/*
+    Maker and taker (regular user) rates of Binance spot VIP tiers, in 0.001% units, indexed by the tier.
+    https://www.binance.com/en/fee/schedule
+*/
+const VIP_TIERS: [(i64, i64); 10] = [
+    (100, 100),
+    (90, 100),
+    (80, 100),
+    (42, 60),
+    (42, 54),
+    (36, 48),
+    (30, 42),
+    (24, 36),
+    (18, 30),
+    (12, 24),
+];
+
+// Share of the fee paid when it is paid in BNB
+const BNB_DISCOUNT: Decimal = Decimal::from_parts(75, 0, 0, false, 2);
+
+/*
+    Commission rates, as fractions of the traded notional
+*/
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeeRates {
+    pub maker: Decimal,
+    pub taker: Decimal,
+}
+
+/*
+    Struct resolving Binance commission rates of a symbol.
+    Rates set for the symbol are taken as they are, other symbols pay the VIP tier rates,
+    discounted if fees are paid in BNB.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FeeSchedule {
+    vip_tier: usize,
+    bnb_discount: bool,
+    symbols: HashMap<String, FeeRates>,
+}
+
+impl FeeSchedule {
+    pub fn new(vip_tier: u8, bnb_discount: bool) -> Result<Self> {
+        let vip_tier = vip_tier as usize;
+        if vip_tier >= VIP_TIERS.len() {
+            return Err(anyhow!(
+                "VIP tier {} does not exist, expected 0 to {}",
+                vip_tier,
+                VIP_TIERS.len() - 1
+            ));
+        }
+
+        Ok(Self {
+            vip_tier,
+            bnb_discount,
+            symbols: HashMap::new(),
+        })
+    }
+
+    pub fn bnb_discount(&self) -> bool {
+        self.bnb_discount
+    }
+
+    /*
+        Overrides the rates of the symbol, e.g. for zero-fee promotions or rates fetched from the account
+    */
+    pub fn set_symbol_rates(&mut self, symbol: &str, rates: FeeRates) {
+        self.symbols.insert(symbol.to_lowercase(), rates);
+    }
+
+    pub fn fee_rates(&self, symbol: &str) -> FeeRates {
+        if let Some(rates) = self.symbols.get(&symbol.to_lowercase()) {
+            return *rates;
+        }
+
+        let (maker, taker) = VIP_TIERS[self.vip_tier];
+        let discount = if self.bnb_discount {
+            BNB_DISCOUNT
+        } else {
+            Decimal::ONE
+        };
+        FeeRates {
+            maker: Decimal::new(maker, 5) * discount,
+            taker: Decimal::new(taker, 5) * discount,
+        }
+    }
+}
+
+/*
+    Struct representing the response of the /api/v3/account/commission endpoint
+*/
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CommissionData {
+    pub symbol: String,
+    pub standard_commission: CommissionRates,
+    pub tax_commission: CommissionRates,
+    pub discount: CommissionDiscount,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CommissionRates {
+    pub maker: Decimal,
+    pub taker: Decimal,
+    pub buyer: Decimal,
+    pub seller: Decimal,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CommissionDiscount {
+    pub enabled_for_account: bool,
+    pub enabled_for_symbol: bool,
+    pub discount_asset: String,
+    pub discount: Decimal, // share of the standard commission paid with the discount asset
+}
+
+impl CommissionData {
+    /*
+        Returns the rates paid by the account, the discount applies to the standard commission only
+    */
+    pub fn fee_rates(&self, bnb_discount: bool) -> FeeRates {
+        let discount = &self.discount;
+        let discount = if bnb_discount
+            && discount.enabled_for_account
+            && discount.enabled_for_symbol
+            && discount.discount_asset == "BNB"
+        {
+            discount.discount
+        } else {
+            Decimal::ONE
+        };
+
+        FeeRates {
+            maker: self.standard_commission.maker * discount + self.tax_commission.maker,
+            taker: self.standard_commission.taker * discount + self.tax_commission.taker,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rust_decimal::Decimal;
+    use serde_json::json;
+
+    use super::{CommissionData, FeeRates, FeeSchedule};
+
+    fn rates(maker: &str, taker: &str) -> FeeRates {
+        FeeRates {
+            maker: Decimal::from_str(maker).unwrap(),
+            taker: Decimal::from_str(taker).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_fee_rates() {
+        // Regular user
+        {
+            let fee_schedule = FeeSchedule::new(0, false).unwrap();
+            assert_eq!(fee_schedule.fee_rates("solusdt"), rates("0.001", "0.001"));
+            // BNB pairs are not discounted by themselves
+            assert_eq!(fee_schedule.fee_rates("solbnb"), rates("0.001", "0.001"));
+        }
+
+        // Paying in BNB
+        {
+            let fee_schedule = FeeSchedule::new(0, true).unwrap();
+            assert_eq!(
+                fee_schedule.fee_rates("solusdt"),
+                rates("0.00075", "0.00075")
+            );
+        }
+
+        // VIP tier
+        {
+            let fee_schedule = FeeSchedule::new(3, true).unwrap();
+            assert_eq!(
+                fee_schedule.fee_rates("solusdt"),
+                rates("0.000315", "0.00045")
+            );
+        }
+
+        // Symbol rates are not discounted
+        {
+            let mut fee_schedule = FeeSchedule::new(0, true).unwrap();
+            fee_schedule.set_symbol_rates("FDUSDUSDT", rates("0", "0"));
+            assert_eq!(fee_schedule.fee_rates("fdusdusdt"), rates("0", "0"));
+            assert_eq!(
+                fee_schedule.fee_rates("solusdt"),
+                rates("0.00075", "0.00075")
+            );
+        }
+    }
+
+    #[test]
+    fn test_unknown_vip_tier() {
+        assert!(FeeSchedule::new(9, false).is_ok());
+        assert!(FeeSchedule::new(10, false).is_err());
+    }
+
+    #[test]
+    fn test_commission_fee_rates() {
+        let commission = serde_json::from_value::<CommissionData>(json!({
+            "symbol": "SOLUSDT",
+            "standardCommission": {
+                "maker": "0.00080000",
+                "taker": "0.00100000",
+                "buyer": "0.00000000",
+                "seller": "0.00000000"
+            },
+            "taxCommission": {
+                "maker": "0.00000000",
+                "taker": "0.00010000",
+                "buyer": "0.00000000",
+                "seller": "0.00000000"
+            },
+            "discount": {
+                "enabledForAccount": true,
+                "enabledForSymbol": true,
+                "discountAsset": "BNB",
+                "discount": "0.75000000"
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(commission.fee_rates(false), rates("0.0008", "0.0011"));
+        assert_eq!(commission.fee_rates(true), rates("0.0006", "0.00085"));
+    }
+}
@@ -1 +1,4 @@
 pub mod binance;
+pub mod binance_rest;
+pub mod fees;
+pub mod order_book;
@@ -0,0 +1,530 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use rust_decimal::Decimal;
+
+use super::{
+    binance::{DepthUpdateData, PartialDepthData, PriceLevel},
+    binance_rest::BinanceRest,
+};
+
+const DEPTH_SNAPSHOT_LIMIT: u32 = 1000;
+// Bounds memory used while the snapshot cannot be fetched, older events are dropped first
+const MAX_BUFFERED_UPDATES: usize = 1000;
+// Keeps the snapshot request weight well below the limits while the book cannot be synced
+const SNAPSHOT_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/*
+    Struct representing a local Binance order book, built from a snapshot and kept up to date with diff events
+*/
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OrderBook {
+    last_update_id: u64,
+    bids: BTreeMap<Decimal, Decimal>,
+    asks: BTreeMap<Decimal, Decimal>,
+}
+
+impl OrderBook {
+    /*
+        Builds the book from individual orders, such as the resting orders of an on-chain order book,
+        adding up the quantities of orders at the same price
+    */
+    pub fn from_orders(
+        bids: impl IntoIterator<Item = PriceLevel>,
+        asks: impl IntoIterator<Item = PriceLevel>,
+    ) -> Self {
+        let mut order_book = Self::default();
+        add_orders(&mut order_book.bids, bids);
+        add_orders(&mut order_book.asks, asks);
+        order_book
+    }
+
+    pub fn last_update_id(&self) -> u64 {
+        self.last_update_id
+    }
+
+    /*
+        Returns bid levels, starting from the best (highest) price
+    */
+    pub fn bids(&self) -> impl Iterator<Item = PriceLevel> + '_ {
+        self.bids
+            .iter()
+            .rev()
+            .map(|(&price, &quantity)| PriceLevel { price, quantity })
+    }
+
+    /*
+        Returns ask levels, starting from the best (lowest) price
+    */
+    pub fn asks(&self) -> impl Iterator<Item = PriceLevel> + '_ {
+        self.asks
+            .iter()
+            .map(|(&price, &quantity)| PriceLevel { price, quantity })
+    }
+
+    /*
+        Applies the diff event following the update id sequencing of Binance.
+        Returns false for events already included in the book and an error if events were missed.
+    */
+    pub fn apply(&mut self, update: &DepthUpdateData) -> Result<bool> {
+        if update.final_update_id <= self.last_update_id {
+            return Ok(false);
+        }
+        if update.first_update_id > self.last_update_id + 1 {
+            return Err(anyhow!(
+                "Missed order book updates between {} and {}",
+                self.last_update_id,
+                update.first_update_id
+            ));
+        }
+
+        update_levels(&mut self.bids, &update.bids);
+        update_levels(&mut self.asks, &update.asks);
+        self.last_update_id = update.final_update_id;
+
+        Ok(true)
+    }
+
+    /*
+        Calculates the execution of a market order spending (Buy) or receiving (Sell) the provided quote notional.
+        Returns None if the book is not deep enough.
+    */
+    pub fn fill_notional(&self, side: OrderSide, notional: Decimal) -> Option<Fill> {
+        let levels: Box<dyn Iterator<Item = PriceLevel>> = match side {
+            OrderSide::Buy => Box::new(self.asks()),
+            OrderSide::Sell => Box::new(self.bids()),
+        };
+
+        let mut fill = Fill::default();
+        for level in levels {
+            let remaining_notional = notional - fill.notional;
+            if remaining_notional.le(&Decimal::ZERO) {
+                break;
+            }
+
+            let level_notional = level.price.checked_mul(level.quantity).unwrap();
+            let (quantity, filled_notional) = if level_notional.le(&remaining_notional) {
+                (level.quantity, level_notional)
+            } else {
+                (
+                    remaining_notional.checked_div(level.price).unwrap(),
+                    remaining_notional,
+                )
+            };
+            fill.quantity += quantity;
+            fill.notional += filled_notional;
+            fill.worst_price = level.price;
+        }
+
+        if fill.notional.lt(&notional) || fill.quantity.is_zero() {
+            return None;
+        }
+        fill.average_price = fill.notional.checked_div(fill.quantity).unwrap();
+
+        Some(fill)
+    }
+}
+
+impl From<&PartialDepthData> for OrderBook {
+    fn from(snapshot: &PartialDepthData) -> Self {
+        let mut order_book = Self {
+            last_update_id: snapshot.last_update_id,
+            ..Default::default()
+        };
+        update_levels(&mut order_book.bids, &snapshot.bids);
+        update_levels(&mut order_book.asks, &snapshot.asks);
+        order_book
+    }
+}
+
+fn update_levels(side: &mut BTreeMap<Decimal, Decimal>, levels: &[PriceLevel]) {
+    for level in levels {
+        if level.quantity.is_zero() {
+            side.remove(&level.price);
+        } else {
+            side.insert(level.price, level.quantity);
+        }
+    }
+}
+
+fn add_orders(side: &mut BTreeMap<Decimal, Decimal>, orders: impl IntoIterator<Item = PriceLevel>) {
+    for order in orders {
+        if !order.quantity.is_zero() {
+            *side.entry(order.price).or_default() += order.quantity;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Fill {
+    pub quantity: Decimal,
+    pub notional: Decimal,
+    pub average_price: Decimal,
+    pub worst_price: Decimal,
+}
+
+/*
+    Struct syncing the local order book of the ticker/pair from diff events and REST snapshots.
+    Events are buffered until a snapshot covering them is applied, a gap in the events drops the book.
+    Snapshots are fetched by the caller with SnapshotFetcher, so that applying events never waits for REST.
+    The book is shared with its readers, events are applied to a copy only while a reader still holds it.
+*/
+#[derive(Default)]
+pub struct OrderBookSync {
+    order_book: Option<Arc<OrderBook>>,
+    buffer: Vec<DepthUpdateData>,
+}
+
+impl OrderBookSync {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /*
+        Applies the diff event, returning the order book if it is in sync.
+        Otherwise the event is buffered and a snapshot has to be applied.
+    */
+    pub fn apply(&mut self, update: DepthUpdateData) -> Option<Arc<OrderBook>> {
+        if let Some(order_book) = &mut self.order_book {
+            match Arc::make_mut(order_book).apply(&update) {
+                Ok(_) => return self.order_book.clone(),
+                Err(err) => {
+                    eprintln!("{}, resyncing the order book", err);
+                    self.reset();
+                }
+            }
+        }
+
+        if self.buffer.len() == MAX_BUFFERED_UPDATES {
+            self.buffer.remove(0);
+        }
+        self.buffer.push(update);
+        None
+    }
+
+    /*
+        Drops the book and buffered events, e.g. after events were lost on reconnect
+    */
+    pub fn reset(&mut self) {
+        self.order_book = None;
+        self.buffer.clear();
+    }
+
+    /*
+        Applies buffered events on top of the snapshot, returning the order book if it is in sync.
+        Fails if the snapshot is older than the first buffered event, a newer one has to be fetched then.
+        Nothing is done if the book is already in sync or there are no buffered events to sync with.
+    */
+    pub fn apply_snapshot(
+        &mut self,
+        snapshot: &PartialDepthData,
+    ) -> Result<Option<Arc<OrderBook>>> {
+        let Some(first_update) = self.buffer.first().filter(|_| self.order_book.is_none()) else {
+            return Ok(self.order_book.clone());
+        };
+        if snapshot.last_update_id < first_update.first_update_id {
+            return Err(anyhow!(
+                "Snapshot {} is older than the first buffered event {}",
+                snapshot.last_update_id,
+                first_update.first_update_id
+            ));
+        }
+
+        let mut order_book = OrderBook::from(snapshot);
+        for update in self.buffer.drain(..) {
+            order_book.apply(&update)?;
+        }
+        self.order_book = Some(Arc::new(order_book));
+
+        Ok(self.order_book.clone())
+    }
+}
+
+/*
+    Struct fetching REST depth snapshots of the ticker/pair for OrderBookSync
+*/
+pub struct SnapshotFetcher {
+    ticker: String,
+    binance_rest: BinanceRest,
+    // Delay between consecutive requests while the book cannot be synced
+    pub retry_interval: Duration,
+}
+
+impl SnapshotFetcher {
+    pub fn new(ticker: &str, binance_rest: BinanceRest) -> Self {
+        Self {
+            ticker: ticker.to_string(),
+            binance_rest,
+            retry_interval: SNAPSHOT_RETRY_INTERVAL,
+        }
+    }
+
+    pub async fn fetch(&self) -> Result<PartialDepthData> {
+        self.binance_rest
+            .get_depth_snapshot(&self.ticker, DEPTH_SNAPSHOT_LIMIT)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, time::Duration};
+
+    use rust_decimal::Decimal;
+    use serde_json::json;
+
+    use super::{OrderBook, OrderBookSync, OrderSide, SnapshotFetcher};
+    use crate::{
+        structs::cex::{
+            binance::{DepthUpdateData, PartialDepthData, PriceLevel},
+            binance_rest::BinanceRest,
+        },
+        test_support::mock_http::MockHttpServer,
+    };
+
+    fn levels(levels: &[(&str, &str)]) -> Vec<PriceLevel> {
+        levels
+            .iter()
+            .map(|(price, quantity)| PriceLevel {
+                price: Decimal::from_str(price).unwrap(),
+                quantity: Decimal::from_str(quantity).unwrap(),
+            })
+            .collect()
+    }
+
+    fn order_book() -> OrderBook {
+        OrderBook::from(&PartialDepthData {
+            last_update_id: 100,
+            bids: levels(&[("71.38", "1"), ("71.37", "2"), ("71.35", "5")]),
+            asks: levels(&[("71.40", "1"), ("71.41", "2"), ("71.45", "5")]),
+        })
+    }
+
+    fn depth_update(
+        first_update_id: u64,
+        final_update_id: u64,
+        bids: &[(&str, &str)],
+        asks: &[(&str, &str)],
+    ) -> DepthUpdateData {
+        DepthUpdateData {
+            symbol: "SOLUSDT".to_string(),
+            first_update_id,
+            final_update_id,
+            bids: levels(bids),
+            asks: levels(asks),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_from_orders() {
+        let order_book = OrderBook::from_orders(
+            levels(&[("71.37", "2"), ("71.38", "1"), ("71.37", "0.5")]),
+            levels(&[("71.40", "1"), ("71.41", "0")]),
+        );
+        assert_eq!(
+            order_book.bids().collect::<Vec<_>>(),
+            levels(&[("71.38", "1"), ("71.37", "2.5")])
+        );
+        assert_eq!(
+            order_book.asks().collect::<Vec<_>>(),
+            levels(&[("71.40", "1")])
+        );
+    }
+
+    #[test]
+    fn test_from_snapshot() {
+        let order_book = order_book();
+        assert_eq!(order_book.last_update_id(), 100);
+        assert_eq!(
+            order_book.bids().collect::<Vec<_>>(),
+            levels(&[("71.38", "1"), ("71.37", "2"), ("71.35", "5")])
+        );
+        assert_eq!(
+            order_book.asks().collect::<Vec<_>>(),
+            levels(&[("71.40", "1"), ("71.41", "2"), ("71.45", "5")])
+        );
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut order_book = order_book();
+
+        // Updates, removes and inserts levels
+        let update = depth_update(
+            95,
+            101,
+            &[("71.38", "3"), ("71.37", "0"), ("71.39", "1")],
+            &[("71.40", "0.00000000")],
+        );
+        assert!(order_book.apply(&update).unwrap());
+        assert_eq!(order_book.last_update_id(), 101);
+        assert_eq!(
+            order_book.bids().collect::<Vec<_>>(),
+            levels(&[("71.39", "1"), ("71.38", "3"), ("71.35", "5")])
+        );
+        assert_eq!(
+            order_book.asks().collect::<Vec<_>>(),
+            levels(&[("71.41", "2"), ("71.45", "5")])
+        );
+
+        // Events already included in the book are ignored
+        let stale = depth_update(99, 101, &[("71.00", "1")], &[]);
+        assert!(!order_book.apply(&stale).unwrap());
+        assert_eq!(order_book.bids().count(), 3);
+
+        // Missed events are reported
+        let gap = depth_update(103, 105, &[("71.00", "1")], &[]);
+        assert!(order_book.apply(&gap).is_err());
+        assert_eq!(order_book.last_update_id(), 101);
+    }
+
+    #[test]
+    fn test_fill_notional() {
+        let order_book = order_book();
+
+        // Within the best level
+        {
+            let fill = order_book
+                .fill_notional(OrderSide::Buy, Decimal::from_str("35.7").unwrap())
+                .unwrap();
+            assert_eq!(fill.quantity, Decimal::from_str("0.5").unwrap());
+            assert_eq!(fill.average_price, Decimal::from_str("71.4").unwrap());
+            assert_eq!(fill.worst_price, Decimal::from_str("71.4").unwrap());
+        }
+
+        // Across levels
+        {
+            let fill = order_book
+                .fill_notional(OrderSide::Sell, Decimal::from_str("214.12").unwrap())
+                .unwrap();
+            assert_eq!(fill.quantity, Decimal::from_str("3").unwrap());
+            assert_eq!(fill.notional, Decimal::from_str("214.12").unwrap());
+            assert_eq!(
+                fill.average_price.round_dp(8),
+                Decimal::from_str("71.37333333").unwrap()
+            );
+            assert_eq!(fill.worst_price, Decimal::from_str("71.37").unwrap());
+        }
+
+        // Not enough depth
+        {
+            let fill = order_book.fill_notional(OrderSide::Buy, Decimal::from(1000));
+            assert!(fill.is_none());
+        }
+    }
+
+    fn snapshot(last_update_id: u64) -> PartialDepthData {
+        PartialDepthData {
+            last_update_id,
+            bids: levels(&[("71.38", "1"), ("71.37", "2")]),
+            asks: levels(&[("71.40", "1")]),
+        }
+    }
+
+    #[test]
+    fn test_sync_discards_events_older_than_snapshot() {
+        let mut order_book_sync = OrderBookSync::new();
+
+        assert!(order_book_sync
+            .apply(depth_update(90, 95, &[("70", "1")], &[]))
+            .is_none());
+        let order_book = order_book_sync
+            .apply_snapshot(&snapshot(100))
+            .unwrap()
+            .unwrap();
+        assert_eq!(order_book.last_update_id(), 100);
+        assert_eq!(order_book.bids().count(), 2);
+
+        let order_book = order_book_sync
+            .apply(depth_update(101, 102, &[("71.39", "1")], &[]))
+            .unwrap();
+        assert_eq!(order_book.last_update_id(), 102);
+        assert_eq!(
+            order_book.bids().next().unwrap().price,
+            Decimal::from_str("71.39").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sync_waits_for_snapshot_covering_buffered_events() {
+        let mut order_book_sync = OrderBookSync::new();
+
+        // Snapshot older than the first buffered event has to be refetched
+        assert!(order_book_sync
+            .apply(depth_update(105, 110, &[], &[]))
+            .is_none());
+        assert!(order_book_sync.apply_snapshot(&snapshot(100)).is_err());
+        assert!(order_book_sync
+            .apply(depth_update(111, 112, &[], &[]))
+            .is_none());
+
+        let order_book = order_book_sync
+            .apply_snapshot(&snapshot(111))
+            .unwrap()
+            .unwrap();
+        assert_eq!(order_book.last_update_id(), 112);
+        let order_book = order_book_sync
+            .apply(depth_update(113, 115, &[], &[]))
+            .unwrap();
+        assert_eq!(order_book.last_update_id(), 115);
+    }
+
+    #[test]
+    fn test_sync_resyncs_after_gap() {
+        let mut order_book_sync = OrderBookSync::new();
+        order_book_sync.apply(depth_update(99, 101, &[], &[]));
+        order_book_sync.apply_snapshot(&snapshot(100)).unwrap();
+
+        assert!(order_book_sync
+            .apply(depth_update(110, 121, &[("71.39", "1")], &[]))
+            .is_none());
+        let order_book = order_book_sync
+            .apply_snapshot(&snapshot(120))
+            .unwrap()
+            .unwrap();
+        assert_eq!(order_book.last_update_id(), 121);
+        assert_eq!(order_book.bids().count(), 3);
+    }
+
+    #[test]
+    fn test_sync_without_buffered_events() {
+        let mut order_book_sync = OrderBookSync::new();
+        assert!(order_book_sync
+            .apply_snapshot(&snapshot(100))
+            .unwrap()
+            .is_none());
+
+        order_book_sync.apply(depth_update(99, 101, &[], &[]));
+        order_book_sync.apply_snapshot(&snapshot(100)).unwrap();
+        order_book_sync.reset();
+        assert!(order_book_sync
+            .apply_snapshot(&snapshot(120))
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_fetcher() {
+        let server = MockHttpServer::start().await;
+        server.json(
+            "/api/v3/depth",
+            json!({
+                "lastUpdateId": 100,
+                "bids": [["71.38", "1"], ["71.37", "2"]],
+                "asks": [["71.40", "1"]],
+            }),
+        );
+        let binance_rest = BinanceRest::new(&server.url(), Duration::from_secs(1)).unwrap();
+        let snapshot_fetcher = SnapshotFetcher::new("solusdt", binance_rest);
+
+        assert_eq!(snapshot_fetcher.fetch().await.unwrap(), snapshot(100));
+        assert_eq!(server.requests()[0].query, "symbol=SOLUSDT&limit=1000");
+    }
+}
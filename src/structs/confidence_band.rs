@@ -0,0 +1,219 @@
+use std::{fmt, str::FromStr};
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+/*
+    Model of the band around the Pyth price in which the fair price probably lies.
+    Opportunities are only reported when the DEX leg is profitable against the whole band.
+    https://docs.pyth.network/documentation/solana-price-feeds/best-practices#confidence-intervals
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum ConfidenceBand {
+    // Fixed multiple of the Pyth confidence interval
+    Multiplier(Decimal),
+    // Two-sided quantile of Laplace distribution with the confidence interval as its standard deviation
+    Laplace(Quantile),
+    // Two-sided quantile of normal distribution with the confidence interval as its standard deviation
+    Gaussian(Quantile),
+    // Fixed buffer around the Pyth price in bps of the price, the confidence interval is ignored
+    Bps(Decimal),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quantile {
+    P90,
+    P95,
+    P99,
+}
+
+impl Default for ConfidenceBand {
+    fn default() -> Self {
+        Self::Laplace(Quantile::P95)
+    }
+}
+
+impl ConfidenceBand {
+    /*
+        Returns (higher, lower) bounds of the band around the price
+    */
+    pub fn bounds(&self, price: Decimal, confidence: Decimal) -> (Decimal, Decimal) {
+        let half_width = match self {
+            Self::Multiplier(multiplier) => confidence.checked_mul(*multiplier),
+            Self::Laplace(quantile) => confidence.checked_mul(quantile.laplace_multiplier()),
+            Self::Gaussian(quantile) => confidence.checked_mul(quantile.gaussian_multiplier()),
+            Self::Bps(bps) => price
+                .checked_mul(*bps)
+                .and_then(|buffer| buffer.checked_div(Decimal::from(10000))),
+        }
+        .unwrap();
+
+        (
+            price.checked_add(half_width).unwrap(),
+            price.checked_sub(half_width).unwrap(),
+        )
+    }
+}
+
+impl Quantile {
+    // -ln(1 - p) / sqrt(2), rounded to 2 decimal places
+    fn laplace_multiplier(&self) -> Decimal {
+        match self {
+            Self::P90 => Decimal::new(163, 2),
+            Self::P95 => Decimal::new(212, 2),
+            Self::P99 => Decimal::new(326, 2),
+        }
+    }
+
+    // Inverse of the standard normal CDF at (1 + p) / 2
+    fn gaussian_multiplier(&self) -> Decimal {
+        match self {
+            Self::P90 => Decimal::new(1645, 3),
+            Self::P95 => Decimal::new(196, 2),
+            Self::P99 => Decimal::new(2576, 3),
+        }
+    }
+}
+
+/*
+    Parses laplace:<90|95|99>, gaussian:<90|95|99>, bps:<bps> or multiplier:<multiplier>
+*/
+impl FromStr for ConfidenceBand {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let parse = || {
+            let (model, parameter) = value.split_once(':')?;
+            let parameter = parameter.trim();
+            let quantile = || match parameter {
+                "90" => Some(Quantile::P90),
+                "95" => Some(Quantile::P95),
+                "99" => Some(Quantile::P99),
+                _ => None,
+            };
+            let non_negative = || {
+                Decimal::from_str(parameter)
+                    .ok()
+                    .filter(|value| !value.is_sign_negative())
+            };
+
+            match model.trim().to_lowercase().as_str() {
+                "laplace" => quantile().map(Self::Laplace),
+                "gaussian" => quantile().map(Self::Gaussian),
+                "bps" => non_negative().map(Self::Bps),
+                "multiplier" => non_negative().map(Self::Multiplier),
+                _ => None,
+            }
+        };
+
+        parse().ok_or_else(|| {
+            format!(
+                "expected laplace:<90|95|99>, gaussian:<90|95|99>, bps:<bps> or multiplier:<multiplier>, got '{}'",
+                value
+            )
+        })
+    }
+}
+
+impl TryFrom<String> for ConfidenceBand {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for ConfidenceBand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = |quantile: &Quantile| match quantile {
+            Quantile::P90 => 90,
+            Quantile::P95 => 95,
+            Quantile::P99 => 99,
+        };
+        match self {
+            Self::Multiplier(multiplier) => write!(f, "multiplier:{}", multiplier),
+            Self::Laplace(quantile) => write!(f, "laplace:{}", percent(quantile)),
+            Self::Gaussian(quantile) => write!(f, "gaussian:{}", percent(quantile)),
+            Self::Bps(bps) => write!(f, "bps:{}", bps),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Pyth price and confidence of 48561.26854 ± 6.12455
+    fn price() -> (Decimal, Decimal) {
+        (Decimal::new(4856126854, 5), Decimal::new(612455, 5))
+    }
+
+    fn bounds(band: &str) -> (String, String) {
+        let (higher, lower) = band
+            .parse::<ConfidenceBand>()
+            .unwrap()
+            .bounds(price().0, price().1);
+        (
+            higher.normalize().to_string(),
+            lower.normalize().to_string(),
+        )
+    }
+
+    #[test]
+    fn test_default_matches_multiplier() {
+        let (price, confidence) = price();
+        let multiplier = ConfidenceBand::Multiplier(Decimal::new(212, 2));
+        assert_eq!(
+            ConfidenceBand::default().bounds(price, confidence),
+            multiplier.bounds(price, confidence)
+        );
+        assert_eq!(
+            bounds("laplace:95"),
+            ("48574.252586".to_string(), "48548.284494".to_string())
+        );
+    }
+
+    #[test]
+    fn test_quantiles() {
+        assert_eq!(
+            bounds("laplace:90"),
+            ("48571.2515565".to_string(), "48551.2855235".to_string())
+        );
+        assert_eq!(
+            bounds("laplace:99"),
+            ("48581.234573".to_string(), "48541.302507".to_string())
+        );
+        assert_eq!(
+            bounds("gaussian:90"),
+            ("48571.34342475".to_string(), "48551.19365525".to_string())
+        );
+        assert_eq!(
+            bounds("gaussian:95"),
+            ("48573.272658".to_string(), "48549.264422".to_string())
+        );
+        assert_eq!(bounds("Gaussian: 99").0, "48577.0453808".to_string());
+    }
+
+    #[test]
+    fn test_bps() {
+        assert_eq!(
+            bounds("bps:10"),
+            ("48609.82980854".to_string(), "48512.70727146".to_string())
+        );
+        assert_eq!(
+            bounds("multiplier:0"),
+            ("48561.26854".to_string(), "48561.26854".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        for band in ["laplace:90", "gaussian:99", "bps:12.5", "multiplier:1.5"] {
+            assert_eq!(band.parse::<ConfidenceBand>().unwrap().to_string(), band);
+        }
+        for band in ["laplace:97", "student:95", "bps:-1", "bps", "1.96"] {
+            assert!(band.parse::<ConfidenceBand>().is_err(), "{}", band);
+        }
+    }
+}
@@ -1,4 +1,7 @@
+pub mod app;
 pub mod arbitrage_finder;
 pub mod cex;
+pub mod confidence_band;
 pub mod on_chain;
+pub mod opportunity_tracker;
 pub mod state;
@@ -0,0 +1,117 @@
+use anyhow::{anyhow, Result};
+use rust_decimal::Decimal;
+use sha2::{Digest, Sha256};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcAccountInfoConfig};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::account::Account;
+
+// Offset of the decimals in an SPL token mint account
+pub const MINT_DECIMALS_OFFSET: usize = 44;
+
+/*
+    Fetches the accounts with a single request, returned in the order of the addresses with None for missing ones
+*/
+pub async fn get_accounts(
+    client: &RpcClient,
+    addresses: &[Pubkey],
+) -> Result<Vec<Option<Account>>> {
+    let config = RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        ..Default::default()
+    };
+    Ok(client
+        .get_multiple_accounts_with_config(addresses, config)
+        .await?
+        .value)
+}
+
+/*
+    Fetches a single account, failing if it does not exist. The account name is only used in the error.
+*/
+pub async fn get_account(
+    client: &RpcClient,
+    address: &Pubkey,
+    account_name: &str,
+) -> Result<Account> {
+    get_accounts(client, &[*address])
+        .await?
+        .pop()
+        .flatten()
+        .ok_or_else(|| anyhow!("{} {} does not exist", account_name, address))
+}
+
+/*
+    Returns the decimals of the SPL token mint fetched from the address
+*/
+pub fn mint_decimals(account: Option<&Account>, address: &Pubkey) -> Result<u8> {
+    account
+        .and_then(|account| account.data.get(MINT_DECIMALS_OFFSET).copied())
+        .ok_or_else(|| anyhow!("Mint {} does not exist", address))
+}
+
+/*
+    Returns the value of a single atom of a token with the decimals, failing beyond the precision of Decimal
+*/
+pub fn token_unit(decimals: u8) -> Result<Decimal> {
+    Decimal::try_new(1, decimals as u32)
+        .map_err(|_| anyhow!("Unsupported number of token decimals {}", decimals))
+}
+
+/*
+    Returns the Anchor discriminator of the account type, the first 8 bytes of its data
+*/
+pub fn account_discriminator(account_name: &str) -> [u8; 8] {
+    let hash = Sha256::digest(format!("account:{}", account_name));
+    read(&hash, 0)
+}
+
+/*
+    Returns N bytes of the data starting at the offset, the data has to be checked to be long enough
+*/
+pub(crate) fn read<const N: usize>(data: &[u8], offset: usize) -> [u8; N] {
+    data[offset..offset + N].try_into().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use solana_client::nonblocking::rpc_client::RpcClient;
+    use solana_program::pubkey::Pubkey;
+
+    use super::{get_account, get_accounts, mint_decimals, token_unit};
+    use crate::test_support::{
+        decimal, mock_http::MockHttpServer, solana_fixtures::mint_account_data,
+    };
+
+    #[tokio::test]
+    async fn test_get_accounts() {
+        let server = MockHttpServer::start().await;
+        let (mint, missing) = (Pubkey::new_unique(), Pubkey::new_unique());
+        server.solana_accounts("/", HashMap::from([(mint, mint_account_data(6))]));
+        let client = RpcClient::new_with_timeout(server.url(), Duration::from_secs(1));
+
+        let accounts = get_accounts(&client, &[missing, mint]).await.unwrap();
+        assert!(accounts[0].is_none());
+        assert_eq!(mint_decimals(accounts[1].as_ref(), &mint).unwrap(), 6);
+        assert!(mint_decimals(accounts[0].as_ref(), &missing).is_err());
+
+        assert!(get_account(&client, &mint, "Mint").await.is_ok());
+        assert_eq!(
+            get_account(&client, &missing, "Pool")
+                .await
+                .unwrap_err()
+                .to_string(),
+            format!("Pool {} does not exist", missing)
+        );
+    }
+
+    #[test]
+    fn test_token_unit() {
+        assert_eq!(token_unit(0).unwrap(), decimal("1"));
+        assert_eq!(token_unit(6).unwrap(), decimal("0.000001"));
+        assert!(token_unit(28).is_ok());
+        assert!(token_unit(29).is_err());
+    }
+}
@@ -0,0 +1,80 @@
+use std::{any::Any, sync::Arc};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use rust_decimal::Decimal;
+use solana_program::pubkey::Pubkey;
+
+use crate::structs::cex::order_book::OrderSide;
+
+/*
+    Whether the amount of a quote is the exact input or the exact output of the swap
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapMode {
+    ExactIn,
+    ExactOut,
+}
+
+/*
+    Result of a swap quote, amounts are in token units (not atoms)
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DexQuote {
+    pub amount_in: Decimal,
+    pub amount_out: Decimal,
+    pub fee: Decimal, // part of amount_in kept by the pool
+}
+
+/*
+    Trait for a snapshot of a DEX pool, able to quote swaps between its base and quote tokens.
+    Buying swaps the quote token for the base token, selling the other way around.
+*/
+pub trait DexQuoter: Any {
+    /*
+        Returns the current price of the base token in the quote token, before fees
+    */
+    fn spot_price(&self) -> Decimal;
+
+    /*
+        Returns the best price of buying or selling the base token before fees, pools have a single
+        spot price while order books have a spread between their best ask and best bid
+    */
+    fn best_price(&self, _side: OrderSide) -> Decimal {
+        self.spot_price()
+    }
+
+    /*
+        Quotes the swap, the amount is in the input token for SwapMode::ExactIn and in the output token otherwise
+    */
+    fn quote(&self, side: OrderSide, mode: SwapMode, amount: Decimal) -> Result<DexQuote>;
+
+    /*
+        Returns the mint of the quote token, None when the quoter doesn't know it
+    */
+    fn quote_mint(&self) -> Option<Pubkey> {
+        None
+    }
+
+    /*
+        Whether the other quoter is a snapshot of the same pool state, unknown states are never the same
+    */
+    fn same_state(&self, _other: &dyn DexQuoter) -> bool {
+        false
+    }
+}
+
+/*
+    Compares the quoter to another one of the same type, for implementing DexQuoter::same_state
+*/
+pub fn same_state<T: DexQuoter + PartialEq>(quoter: &T, other: &dyn DexQuoter) -> bool {
+    (other as &dyn Any).downcast_ref::<T>() == Some(quoter)
+}
+
+/*
+    Trait for anything able to fetch the current state of a DEX pool
+*/
+#[async_trait]
+pub trait DexPoolSource {
+    async fn fetch_quoter(&self) -> Result<Arc<dyn DexQuoter + Send + Sync>>;
+}
@@ -0,0 +1,388 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use pyth_sdk_solana::{Price, PriceFeed, PriceIdentifier};
+use serde::Deserialize;
+use solana_program::pubkey::Pubkey;
+
+use super::price_source::{get_fresh_price, PriceSource, PriceStream, MAX_PRICE_AGE};
+
+/*
+    Struct representing a connection to Pyth Hermes, serving pull oracle price updates keyed by price feed id
+*/
+pub struct Hermes {
+    client: reqwest::Client,
+    base_url: String,
+    timeout: Duration,
+    // The stream is dropped if nothing, not even a keepalive comment, arrives for this long
+    pub idle_timeout: Duration,
+}
+
+impl Hermes {
+    /*
+        Creates a client for the Hermes HTTP API under the provided URL.
+        The timeout applies to REST requests, the stream is kept open as long as the server allows and keeps sending.
+    */
+    pub fn new(base_url: &str, timeout: Duration) -> Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .connect_timeout(timeout)
+                .build()?,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            timeout,
+            idle_timeout: MAX_PRICE_AGE,
+        })
+    }
+}
+
+#[async_trait]
+impl PriceSource for Hermes {
+    /*
+        Fetches the most current price of the feed from the REST latest_price_feeds endpoint
+    */
+    async fn get_price(&self, price_id: &Pubkey) -> Result<Option<Price>> {
+        let feed_id = hex::encode(price_id.to_bytes());
+        let price_feeds = self
+            .client
+            .get(format!("{}/api/latest_price_feeds", self.base_url))
+            .query(&[("ids[]", &feed_id)])
+            .timeout(self.timeout)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Vec<HermesPriceFeed>>()
+            .await?;
+
+        let price_feed = price_feeds
+            .into_iter()
+            .find(|price_feed| price_feed.matches(&feed_id))
+            .ok_or_else(|| anyhow!("Price feed {} not found", feed_id))?;
+
+        Ok(get_fresh_price(&price_feed.try_into()?))
+    }
+}
+
+#[async_trait]
+impl PriceStream for Hermes {
+    /*
+        Subscribes to the SSE price update stream of the feed, failing once no chunk arrives within the idle timeout
+    */
+    async fn subscribe(
+        &self,
+        price_id: &Pubkey,
+        on_price: &mut (dyn FnMut(Option<Price>) + Send),
+    ) -> Result<()> {
+        let feed_id = hex::encode(price_id.to_bytes());
+        let mut body = self
+            .client
+            .get(format!("{}/v2/updates/price/stream", self.base_url))
+            .query(&[("ids[]", feed_id.as_str()), ("parsed", "true")])
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes_stream();
+
+        let mut events = ServerSentEvents::default();
+        loop {
+            let chunk = match tokio::time::timeout(self.idle_timeout, body.next()).await {
+                Ok(Some(chunk)) => chunk?,
+                Ok(None) => break,
+                Err(_) => {
+                    return Err(anyhow!(
+                        "No data from the price stream of {} within {:?}",
+                        feed_id,
+                        self.idle_timeout
+                    ))
+                }
+            };
+            for data in events.push(&chunk) {
+                let price_feed = serde_json::from_str::<HermesPriceUpdate>(&data)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|update| {
+                        update
+                            .parsed
+                            .into_iter()
+                            .find(|price_feed| price_feed.matches(&feed_id))
+                            .ok_or_else(|| anyhow!("Price feed {} not in the update", feed_id))
+                    })
+                    .and_then(PriceFeed::try_from);
+
+                match price_feed {
+                    Ok(price_feed) => on_price(get_fresh_price(&price_feed)),
+                    Err(err) => eprintln!("Skipping Hermes price update: {}", err),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/*
+    Incremental parser of a text/event-stream body, yielding data of complete events.
+    Bytes are buffered until a line is complete, so characters split across chunks are decoded whole.
+*/
+#[derive(Default)]
+struct ServerSentEvents {
+    buffer: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl ServerSentEvents {
+    fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+        let mut complete_events = Vec::new();
+
+        while let Some(line_end) = self.buffer.iter().position(|byte| *byte == b'\n') {
+            let line_bytes: Vec<u8> = self.buffer.drain(..=line_end).collect();
+            let line = String::from_utf8_lossy(&line_bytes[..line_end]);
+            let line = line.trim_end_matches('\r');
+
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    complete_events.push(self.data.join("\n"));
+                    self.data.clear();
+                }
+            } else if let Some(data) = line.strip_prefix("data:") {
+                self.data.push(data.trim_start().to_string());
+            }
+            // Comments, event names, ids and retry hints are not used
+        }
+
+        complete_events
+    }
+}
+
+/*
+    Structs representing JSON responses of Hermes
+*/
+
+#[derive(Debug, Deserialize)]
+struct HermesPriceUpdate {
+    parsed: Vec<HermesPriceFeed>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HermesPriceFeed {
+    id: String,
+    price: HermesPrice,
+    ema_price: HermesPrice,
+}
+
+// Hermes encodes 64 bit integers as strings
+#[derive(Debug, Deserialize)]
+struct HermesPrice {
+    price: String,
+    conf: String,
+    expo: i32,
+    publish_time: i64,
+}
+
+impl HermesPriceFeed {
+    fn matches(&self, feed_id: &str) -> bool {
+        self.id
+            .trim_start_matches("0x")
+            .eq_ignore_ascii_case(feed_id)
+    }
+}
+
+impl TryFrom<HermesPrice> for Price {
+    type Error = anyhow::Error;
+
+    fn try_from(price: HermesPrice) -> Result<Self> {
+        Ok(Price {
+            price: price.price.parse()?,
+            conf: price.conf.parse()?,
+            expo: price.expo,
+            publish_time: price.publish_time,
+        })
+    }
+}
+
+impl TryFrom<HermesPriceFeed> for PriceFeed {
+    type Error = anyhow::Error;
+
+    fn try_from(price_feed: HermesPriceFeed) -> Result<Self> {
+        let id = PriceIdentifier::from_hex(price_feed.id.trim_start_matches("0x"))
+            .map_err(|err| anyhow!("Invalid price feed id {}: {}", price_feed.id, err))?;
+        Ok(PriceFeed::new(
+            id,
+            price_feed.price.try_into()?,
+            price_feed.ema_price.try_into()?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::{json, Value};
+    use solana_program::pubkey::Pubkey;
+
+    use super::{Hermes, ServerSentEvents};
+    use crate::{
+        structs::on_chain::price_source::{parse_price_id, PriceSource, PriceStream},
+        test_support::{
+            mock_http::{MockHttpServer, MockResponse},
+            pyth_fixtures::now,
+        },
+    };
+
+    const SOL_USD_FEED_ID: &str =
+        "ef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d";
+
+    fn price_feed(feed_id: &str, price: i64, publish_time: i64) -> Value {
+        let price = json!({
+            "price": price.to_string(),
+            "conf": "669724",
+            "expo": -6,
+            "publish_time": publish_time,
+        });
+        json!({ "id": feed_id, "price": price, "ema_price": price })
+    }
+
+    fn sol_usd_price_id() -> Pubkey {
+        parse_price_id(SOL_USD_FEED_ID).unwrap()
+    }
+
+    fn hermes(server: &MockHttpServer) -> Hermes {
+        Hermes::new(&server.url(), Duration::from_secs(1)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_get_price() {
+        let server = MockHttpServer::start().await;
+        server.json(
+            "/api/latest_price_feeds",
+            json!([price_feed(SOL_USD_FEED_ID, 69852445, now())]),
+        );
+
+        let price = hermes(&server)
+            .get_price(&sol_usd_price_id())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(price.price, 69852445);
+        assert_eq!(price.conf, 669724);
+        assert_eq!(price.expo, -6);
+        assert!(server.requests()[0].query.contains(SOL_USD_FEED_ID));
+    }
+
+    #[tokio::test]
+    async fn test_get_price_stale() {
+        let server = MockHttpServer::start().await;
+        server.json(
+            "/api/latest_price_feeds",
+            json!([price_feed(SOL_USD_FEED_ID, 69852445, now() - 61)]),
+        );
+
+        let result = hermes(&server).get_price(&sol_usd_price_id()).await;
+        assert!(result.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_price_feed_not_found() {
+        let server = MockHttpServer::start().await;
+        server.json(
+            "/api/latest_price_feeds",
+            json!([price_feed(&"ab".repeat(32), 69852445, now())]),
+        );
+
+        let result = hermes(&server).get_price(&sol_usd_price_id()).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_price_server_error() {
+        let server = MockHttpServer::start().await;
+        server.route(
+            "/api/latest_price_feeds",
+            MockResponse::Json {
+                status: 500,
+                body: json!({ "message": "Internal error" }),
+            },
+        );
+
+        let result = hermes(&server).get_price(&sol_usd_price_id()).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let server = MockHttpServer::start().await;
+        let update = |feed_id: &str, price: i64| {
+            format!(
+                "data:{}\n\n",
+                json!({
+                    "binary": { "encoding": "hex", "data": [] },
+                    "parsed": [price_feed(feed_id, price, now())],
+                })
+            )
+        };
+        server.route(
+            "/v2/updates/price/stream",
+            MockResponse::EventStream(vec![
+                ":keepalive\n\n".to_string(),
+                update(SOL_USD_FEED_ID, 69852445),
+                "data:{\"not\":\"an update\"}\n\n".to_string(),
+                update(&"ab".repeat(32), 1),
+                update(&format!("0x{}", SOL_USD_FEED_ID), 69900000),
+            ]),
+        );
+
+        let mut prices = Vec::new();
+        hermes(&server)
+            .subscribe(&sol_usd_price_id(), &mut |price| prices.push(price))
+            .await
+            .unwrap();
+
+        let prices = prices
+            .into_iter()
+            .map(|price| price.unwrap().price)
+            .collect::<Vec<_>>();
+        assert_eq!(prices, vec![69852445, 69900000]);
+        assert!(server.requests()[0].query.contains("parsed=true"));
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_stalled() {
+        let server = MockHttpServer::start().await;
+        server.route(
+            "/v2/updates/price/stream",
+            MockResponse::StalledEventStream(vec![":keepalive\n\n".to_string()]),
+        );
+        let mut hermes = hermes(&server);
+        hermes.idle_timeout = Duration::from_millis(200);
+
+        let err = hermes
+            .subscribe(&sol_usd_price_id(), &mut |_| {})
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("No data"), "{}", err);
+    }
+
+    #[test]
+    fn test_server_sent_events_split_across_chunks() {
+        let mut events = ServerSentEvents::default();
+
+        assert!(events.push(b"data: {\"a\":").is_empty());
+        assert!(events.push(b"1}\r\n").is_empty());
+        assert_eq!(
+            events.push(b"\r\nevent: price\ndata:x\ndata:y\n"),
+            vec!["{\"a\":1}".to_string()]
+        );
+        assert_eq!(events.push(b"\n:comment\n\n"), vec!["x\ny".to_string()]);
+
+        // Multibyte character split between chunks
+        let data = "data: {\"symbol\":\"€\"}\n\n".as_bytes();
+        let split = data.iter().position(|byte| *byte == 0xe2).unwrap() + 1;
+        assert!(events.push(&data[..split]).is_empty());
+        assert_eq!(
+            events.push(&data[split..]),
+            vec!["{\"symbol\":\"€\"}".to_string()]
+        );
+    }
+}
@@ -0,0 +1,503 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde::Deserialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_program::pubkey::Pubkey;
+use tokio::sync::OnceCell;
+
+use super::{
+    account::{get_accounts, mint_decimals, token_unit},
+    dex_quoter::{same_state, DexPoolSource, DexQuote, DexQuoter, SwapMode},
+};
+use crate::structs::cex::order_book::OrderSide;
+
+/*
+    Struct representing the Jupiter swap aggregator quoting a single mint pair
+*/
+pub struct Jupiter {
+    client: reqwest::Client,
+    base_url: String,
+    rpc_client: RpcClient,
+    base_mint: Pubkey,
+    quote_mint: Pubkey,
+    notional: Decimal, // quote tokens spent by the buy quote
+    // decimals of the base and quote mints, fetched once
+    decimals: OnceCell<(u8, u8)>,
+}
+
+/*
+    Response of the quote endpoint, amounts are in atoms
+*/
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JupiterQuote {
+    pub input_mint: String,
+    pub in_amount: Decimal,
+    pub output_mint: String,
+    pub out_amount: Decimal,
+    pub price_impact_pct: Decimal, // fraction of the spot price, despite the name
+    pub route_plan: Vec<RoutePlanStep>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoutePlanStep {
+    pub swap_info: SwapInfo,
+    pub percent: u8,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SwapInfo {
+    pub amm_key: String,
+    pub label: Option<String>,
+    pub input_mint: String,
+    pub output_mint: String,
+    pub in_amount: Decimal,
+    pub out_amount: Decimal,
+    pub fee_amount: Decimal,
+    pub fee_mint: String,
+}
+
+impl JupiterQuote {
+    /*
+        Returns the fees of the route charged in the input mint, fees of the intermediate hops are part of the amounts
+    */
+    pub fn input_fee(&self) -> Decimal {
+        self.route_plan
+            .iter()
+            .filter(|step| step.swap_info.fee_mint == self.input_mint)
+            .map(|step| step.swap_info.fee_amount)
+            .sum()
+    }
+
+    /*
+        Returns the labels of the AMMs along the route, e.g. Whirlpool -> Raydium
+    */
+    pub fn route(&self) -> String {
+        self.route_plan
+            .iter()
+            .map(|step| {
+                step.swap_info
+                    .label
+                    .clone()
+                    .unwrap_or_else(|| step.swap_info.amm_key.clone())
+            })
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    }
+}
+
+impl Jupiter {
+    /*
+        Creates a client for the quote API under the provided URL, mint decimals are read from the Solana JSON-RPC endpoint
+    */
+    pub fn new(
+        base_url: &str,
+        rpc_url: &str,
+        timeout: Duration,
+        base_mint: Pubkey,
+        quote_mint: Pubkey,
+        notional: Decimal,
+    ) -> Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::builder().timeout(timeout).build()?,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            rpc_client: RpcClient::new_with_timeout(rpc_url.to_string(), timeout),
+            base_mint,
+            quote_mint,
+            notional,
+            decimals: OnceCell::new(),
+        })
+    }
+
+    /*
+        Fetches the best route swapping the exact amount of atoms of the input mint
+    */
+    pub async fn get_quote(
+        &self,
+        input_mint: &Pubkey,
+        output_mint: &Pubkey,
+        amount: u64,
+    ) -> Result<JupiterQuote> {
+        let quote = self
+            .client
+            .get(format!("{}/quote", self.base_url))
+            .query(&[
+                ("inputMint", input_mint.to_string()),
+                ("outputMint", output_mint.to_string()),
+                ("amount", amount.to_string()),
+                ("swapMode", "ExactIn".to_string()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<JupiterQuote>()
+            .await?;
+
+        Ok(quote)
+    }
+
+    async fn mint_decimals(&self) -> Result<(u8, u8)> {
+        let decimals = self
+            .decimals
+            .get_or_try_init(|| async {
+                let addresses = [self.base_mint, self.quote_mint];
+                let accounts = get_accounts(&self.rpc_client, &addresses).await?;
+                Ok::<_, anyhow::Error>((
+                    mint_decimals(accounts[0].as_ref(), &addresses[0])?,
+                    mint_decimals(accounts[1].as_ref(), &addresses[1])?,
+                ))
+            })
+            .await?;
+        Ok(*decimals)
+    }
+}
+
+#[async_trait]
+impl DexPoolSource for Jupiter {
+    /*
+        Quotes buying the base token with the notional, then selling the bought amount back
+    */
+    async fn fetch_quoter(&self) -> Result<Arc<dyn DexQuoter + Send + Sync>> {
+        let (base_decimals, quote_decimals) = self.mint_decimals().await?;
+        let notional = self
+            .notional
+            .checked_div(token_unit(quote_decimals)?)
+            .and_then(|atoms| atoms.floor().to_u64())
+            .ok_or_else(|| anyhow!("Invalid notional {}", self.notional))?;
+
+        let buy = self
+            .get_quote(&self.quote_mint, &self.base_mint, notional)
+            .await?;
+        let bought = buy
+            .out_amount
+            .to_u64()
+            .ok_or_else(|| anyhow!("Invalid amount {}", buy.out_amount))?;
+        let sell = self
+            .get_quote(&self.base_mint, &self.quote_mint, bought)
+            .await?;
+
+        Ok(Arc::new(JupiterQuoter::new(
+            &buy,
+            &sell,
+            base_decimals,
+            quote_decimals,
+            self.quote_mint,
+        )?))
+    }
+}
+
+/*
+    Routed fill of a fixed size, in tokens
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RouteFill {
+    base_amount: Decimal,
+    quote_amount: Decimal,
+    fee: Decimal, // in the input token
+    price_impact: Decimal,
+}
+
+impl RouteFill {
+    fn average_price(&self) -> Decimal {
+        self.quote_amount / self.base_amount
+    }
+}
+
+/*
+    Snapshot of the routes buying and selling a fixed size. Smaller amounts are quoted at the average price
+    of the route, which overestimates their price impact, while larger amounts can't be quoted.
+*/
+#[derive(PartialEq)]
+pub struct JupiterQuoter {
+    buy: RouteFill,
+    sell: RouteFill,
+    quote_mint: Pubkey,
+}
+
+impl JupiterQuoter {
+    /*
+        Creates the quoter from the buy (quote to base) and sell (base to quote) quotes of the mint pair
+    */
+    pub fn new(
+        buy: &JupiterQuote,
+        sell: &JupiterQuote,
+        base_decimals: u8,
+        quote_decimals: u8,
+        quote_mint: Pubkey,
+    ) -> Result<Self> {
+        let base_unit = token_unit(base_decimals)?;
+        let quote_unit = token_unit(quote_decimals)?;
+        let buy = RouteFill {
+            base_amount: buy.out_amount * base_unit,
+            quote_amount: buy.in_amount * quote_unit,
+            fee: buy.input_fee() * quote_unit,
+            price_impact: buy.price_impact_pct,
+        };
+        let sell = RouteFill {
+            base_amount: sell.in_amount * base_unit,
+            quote_amount: sell.out_amount * quote_unit,
+            fee: sell.input_fee() * base_unit,
+            price_impact: sell.price_impact_pct,
+        };
+        if [buy, sell]
+            .iter()
+            .any(|fill| fill.base_amount.is_zero() || fill.quote_amount.is_zero())
+        {
+            return Err(anyhow!("Jupiter found no route"));
+        }
+
+        Ok(Self {
+            buy,
+            sell,
+            quote_mint,
+        })
+    }
+}
+
+impl DexQuoter for JupiterQuoter {
+    fn spot_price(&self) -> Decimal {
+        (self.best_price(OrderSide::Buy) + self.best_price(OrderSide::Sell)) / Decimal::TWO
+    }
+
+    /*
+        Returns the price of the route before its fees and price impact
+    */
+    fn best_price(&self, side: OrderSide) -> Decimal {
+        match side {
+            OrderSide::Buy => {
+                (self.buy.quote_amount - self.buy.fee)
+                    / self.buy.base_amount
+                    / (Decimal::ONE + self.buy.price_impact)
+            }
+            OrderSide::Sell => {
+                self.sell.average_price()
+                    / (Decimal::ONE - self.sell.fee / self.sell.base_amount)
+                    / (Decimal::ONE - self.sell.price_impact)
+            }
+        }
+    }
+
+    fn quote(&self, side: OrderSide, mode: SwapMode, amount: Decimal) -> Result<DexQuote> {
+        let fill = match side {
+            OrderSide::Buy => &self.buy,
+            OrderSide::Sell => &self.sell,
+        };
+        let base_amount = match (side, mode) {
+            (OrderSide::Buy, SwapMode::ExactOut) | (OrderSide::Sell, SwapMode::ExactIn) => amount,
+            _ => amount / fill.average_price(),
+        };
+        if base_amount > fill.base_amount {
+            return Err(anyhow!(
+                "Jupiter quote only covers {} of the base token",
+                fill.base_amount
+            ));
+        }
+        let share = base_amount / fill.base_amount;
+        let quote_amount = base_amount * fill.average_price();
+
+        Ok(match side {
+            OrderSide::Buy => DexQuote {
+                amount_in: quote_amount,
+                amount_out: base_amount,
+                fee: fill.fee * share,
+            },
+            OrderSide::Sell => DexQuote {
+                amount_in: base_amount,
+                amount_out: quote_amount,
+                fee: fill.fee * share,
+            },
+        })
+    }
+
+    fn quote_mint(&self) -> Option<Pubkey> {
+        Some(self.quote_mint)
+    }
+
+    fn same_state(&self, other: &dyn DexQuoter) -> bool {
+        same_state(self, other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, str::FromStr, time::Duration};
+
+    use solana_program::pubkey::Pubkey;
+
+    use super::{DexPoolSource, DexQuoter, Jupiter, JupiterQuote, JupiterQuoter, SwapMode};
+    use crate::{
+        structs::cex::order_book::OrderSide,
+        test_support::{
+            decimal,
+            jupiter_fixtures::{sol_usdc_buy_quote, sol_usdc_sell_quote},
+            mock_http::MockHttpServer,
+            solana_fixtures::{mint_account_data, SOL_MINT, USDC_MINT},
+        },
+    };
+
+    fn quoter() -> JupiterQuoter {
+        let buy: JupiterQuote = serde_json::from_value(sol_usdc_buy_quote()).unwrap();
+        let sell: JupiterQuote = serde_json::from_value(sol_usdc_sell_quote()).unwrap();
+        JupiterQuoter::new(&buy, &sell, 9, 6, Pubkey::from_str(USDC_MINT).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_quote() {
+        let quote: JupiterQuote = serde_json::from_value(sol_usdc_sell_quote()).unwrap();
+        assert_eq!(quote.out_amount, decimal("694000000"));
+        assert_eq!(quote.price_impact_pct, decimal("0.0005"));
+        assert_eq!(quote.input_fee(), decimal("27390000"));
+        assert_eq!(quote.route(), "Raydium -> Whirlpool");
+    }
+
+    #[test]
+    fn test_quote() {
+        let quoter = quoter();
+        // (700 - 2.1) / 9.96 / 1.001
+        assert_eq!(
+            quoter.best_price(OrderSide::Buy).round_dp(6),
+            decimal("70.000281")
+        );
+        // 694 / 9.96 / (1 - 0.02739 / 9.96) / 0.9995
+        assert_eq!(
+            quoter.best_price(OrderSide::Sell).round_dp(6),
+            decimal("69.905813")
+        );
+
+        // Half of the quoted size at the average price
+        let quote = quoter
+            .quote(OrderSide::Buy, SwapMode::ExactOut, decimal("4.98"))
+            .unwrap();
+        assert_eq!(quote.amount_in, decimal("350"));
+        assert_eq!(quote.fee, decimal("1.05"));
+        let quote = quoter
+            .quote(OrderSide::Sell, SwapMode::ExactIn, decimal("4.98"))
+            .unwrap();
+        assert_eq!(quote.amount_out, decimal("347"));
+        assert_eq!(quote.fee, decimal("0.013695"));
+        let quote = quoter
+            .quote(OrderSide::Buy, SwapMode::ExactIn, decimal("350"))
+            .unwrap();
+        assert_eq!(quote.amount_out, decimal("4.98"));
+
+        // Beyond the quoted size
+        assert!(quoter
+            .quote(OrderSide::Buy, SwapMode::ExactOut, decimal("10"))
+            .is_err());
+
+        // Decimals beyond the precision of Decimal
+        let buy: JupiterQuote = serde_json::from_value(sol_usdc_buy_quote()).unwrap();
+        let sell: JupiterQuote = serde_json::from_value(sol_usdc_sell_quote()).unwrap();
+        assert!(JupiterQuoter::new(&buy, &sell, 29, 6, Pubkey::default()).is_err());
+        assert!(JupiterQuoter::new(&buy, &sell, 9, 255, Pubkey::default()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_quoter() {
+        let server = MockHttpServer::start().await;
+        let (sol_mint, usdc_mint) = (
+            Pubkey::from_str(SOL_MINT).unwrap(),
+            Pubkey::from_str(USDC_MINT).unwrap(),
+        );
+        server.solana_accounts(
+            "/",
+            HashMap::from([
+                (sol_mint, mint_account_data(9)),
+                (usdc_mint, mint_account_data(6)),
+            ]),
+        );
+        server.json_by_query(
+            "/quote",
+            vec![
+                (&format!("inputMint={}", USDC_MINT), sol_usdc_buy_quote()),
+                (&format!("inputMint={}", SOL_MINT), sol_usdc_sell_quote()),
+            ],
+        );
+        let jupiter = Jupiter::new(
+            &server.url(),
+            &server.url(),
+            Duration::from_secs(1),
+            sol_mint,
+            usdc_mint,
+            decimal("700"),
+        )
+        .unwrap();
+
+        let quoter = jupiter.fetch_quoter().await.unwrap();
+        assert_eq!(
+            quoter
+                .quote(OrderSide::Sell, SwapMode::ExactIn, decimal("9.96"))
+                .unwrap()
+                .amount_out,
+            decimal("694")
+        );
+        assert_eq!(quoter.quote_mint(), Some(usdc_mint));
+        let queries = server
+            .requests()
+            .into_iter()
+            .filter(|request| request.path == "/quote")
+            .map(|request| request.query)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            queries,
+            vec![
+                format!(
+                    "inputMint={}&outputMint={}&amount=700000000&swapMode=ExactIn",
+                    USDC_MINT, SOL_MINT
+                ),
+                format!(
+                    "inputMint={}&outputMint={}&amount=9960000000&swapMode=ExactIn",
+                    SOL_MINT, USDC_MINT
+                ),
+            ]
+        );
+
+        // Mints are fetched only once
+        jupiter.fetch_quoter().await.unwrap();
+        assert_eq!(
+            server
+                .requests()
+                .iter()
+                .filter(|request| request.path == "/")
+                .count(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_quoter_unsupported_decimals() {
+        let server = MockHttpServer::start().await;
+        let (sol_mint, usdc_mint) = (
+            Pubkey::from_str(SOL_MINT).unwrap(),
+            Pubkey::from_str(USDC_MINT).unwrap(),
+        );
+        server.solana_accounts(
+            "/",
+            HashMap::from([
+                (sol_mint, mint_account_data(9)),
+                (usdc_mint, mint_account_data(20)),
+            ]),
+        );
+        let jupiter = Jupiter::new(
+            &server.url(),
+            &server.url(),
+            Duration::from_secs(1),
+            sol_mint,
+            usdc_mint,
+            decimal("700"),
+        )
+        .unwrap();
+
+        // The notional in atoms overflows u64 instead of panicking
+        assert!(jupiter.fetch_quoter().await.is_err());
+        assert!(server
+            .requests()
+            .iter()
+            .all(|request| request.path != "/quote"));
+    }
+}
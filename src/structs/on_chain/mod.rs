@@ -1 +1,9 @@
+pub mod account;
+pub mod dex_quoter;
+pub mod hermes;
+pub mod jupiter;
+pub mod openbook;
+pub mod orca;
+pub mod price_source;
 pub mod pyth;
+pub mod raydium;
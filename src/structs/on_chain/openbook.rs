@@ -0,0 +1,576 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use rust_decimal::Decimal;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_program::pubkey::Pubkey;
+
+use super::{
+    account::{account_discriminator, get_account, get_accounts, read, token_unit},
+    dex_quoter::{same_state, DexPoolSource, DexQuote, DexQuoter, SwapMode},
+};
+use crate::structs::cex::{
+    binance::PriceLevel,
+    order_book::{OrderBook, OrderSide},
+};
+
+pub const MARKET_LEN: usize = 848;
+pub const BOOK_SIDE_LEN: usize = 90952;
+pub const BOOK_SIDE_NODES_OFFSET: usize = 840;
+pub const MAX_BOOK_SIDE_NODES: usize = 1024;
+pub const NODE_LEN: usize = 88;
+pub const INNER_NODE_TAG: u8 = 1;
+pub const LEAF_NODE_TAG: u8 = 2;
+
+// Denominator of the market fees
+const FEES_SCALE_FACTOR: i64 = 1_000_000;
+
+/*
+    Struct representing OpenBook v2 markets of a Solana RPC node
+*/
+pub struct OpenBook {
+    client: RpcClient,
+    market: Pubkey,
+}
+
+impl OpenBook {
+    /*
+        Creates a client reading the market and its book sides from the Solana JSON-RPC endpoint,
+        failing requests taking longer than the timeout
+    */
+    pub fn new(rpc_url: &str, timeout: Duration, market: Pubkey) -> Self {
+        Self {
+            client: RpcClient::new_with_timeout(rpc_url.to_string(), timeout),
+            market,
+        }
+    }
+}
+
+#[async_trait]
+impl DexPoolSource for OpenBook {
+    /*
+        Fetches the market account, followed by its bids and asks
+    */
+    async fn fetch_quoter(&self) -> Result<Arc<dyn DexQuoter + Send + Sync>> {
+        let market_account = get_account(&self.client, &self.market, "OpenBook market").await?;
+        let market = Market::from_account_data(&market_account.data)?;
+
+        let addresses = [market.bids, market.asks];
+        let accounts = get_accounts(&self.client, &addresses).await?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let levels = |index: usize| -> Result<Vec<PriceLevel>> {
+            let account = accounts[index]
+                .as_ref()
+                .ok_or_else(|| anyhow!("Book side {} does not exist", addresses[index]))?;
+            BookSide::from_account_data(&account.data)?
+                .orders(now)
+                .into_iter()
+                .map(|order| market.price_level(order))
+                .collect()
+        };
+        let (bids, asks) = (levels(0)?, levels(1)?);
+
+        Ok(Arc::new(ClobQuoter::new(
+            OrderBook::from_orders(bids, asks),
+            market.taker_fee(),
+            market.quote_mint,
+        )))
+    }
+}
+
+/*
+    Subset of the OpenBook v2 Market account fields needed for quoting
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Market {
+    pub base_decimals: u8,
+    pub quote_decimals: u8,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub quote_mint: Pubkey,
+    pub quote_lot_size: i64,
+    pub base_lot_size: i64,
+    pub taker_fee: i64, // in millionths of the quote notional
+}
+
+impl Market {
+    /*
+        Decodes the market, failing for decimals or lot sizes the levels can't be converted with
+    */
+    pub fn from_account_data(data: &[u8]) -> Result<Self> {
+        if data.len() < MARKET_LEN || data[..8] != account_discriminator("Market") {
+            return Err(anyhow!("Account is not an OpenBook market"));
+        }
+
+        let market = Self {
+            base_decimals: data[9],
+            quote_decimals: data[10],
+            bids: Pubkey::new_from_array(read(data, 200)),
+            asks: Pubkey::new_from_array(read(data, 232)),
+            quote_mint: Pubkey::new_from_array(read(data, 608)),
+            quote_lot_size: i64::from_le_bytes(read(data, 448)),
+            base_lot_size: i64::from_le_bytes(read(data, 456)),
+            taker_fee: i64::from_le_bytes(read(data, 488)),
+        };
+        token_unit(market.base_decimals)?;
+        token_unit(market.quote_decimals)?;
+        if market.base_lot_size <= 0 || market.quote_lot_size <= 0 {
+            return Err(anyhow!(
+                "Invalid OpenBook lot sizes {} (base) and {} (quote)",
+                market.base_lot_size,
+                market.quote_lot_size
+            ));
+        }
+        Ok(market)
+    }
+
+    /*
+        Converts the order from lots into a price in quote tokens per base token and a quantity in base tokens
+    */
+    pub fn price_level(&self, order: Order) -> Result<PriceLevel> {
+        let (base_unit, quote_unit) = (
+            token_unit(self.base_decimals)?,
+            token_unit(self.quote_decimals)?,
+        );
+        let base_atoms = Decimal::from(self.base_lot_size) * Decimal::from(order.quantity_lots);
+        let quote_atoms_per_base_atom = (Decimal::from(order.price_lots)
+            * Decimal::from(self.quote_lot_size))
+        .checked_div(Decimal::from(self.base_lot_size))
+        .ok_or_else(|| anyhow!("Invalid OpenBook base lot size {}", self.base_lot_size))?;
+        Ok(PriceLevel {
+            price: (quote_atoms_per_base_atom * quote_unit / base_unit).normalize(),
+            quantity: (base_atoms * base_unit).normalize(),
+        })
+    }
+
+    pub fn taker_fee(&self) -> Decimal {
+        Decimal::from(self.taker_fee) / Decimal::from(FEES_SCALE_FACTOR)
+    }
+}
+
+/*
+    Resting order of a book side, in lots
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Order {
+    pub price_lots: i64,
+    pub quantity_lots: i64,
+}
+
+/*
+    Decoded BookSide account, a crit-bit tree of orders per order type
+*/
+pub struct BookSide<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> BookSide<'a> {
+    pub fn from_account_data(data: &'a [u8]) -> Result<Self> {
+        if data.len() < BOOK_SIDE_LEN || data[..8] != account_discriminator("BookSide") {
+            return Err(anyhow!("Account is not an OpenBook book side"));
+        }
+        Ok(Self { data })
+    }
+
+    /*
+        Returns the fixed price orders not expired at the unix timestamp. Orders pegged to the oracle
+        are kept in a separate tree and are left out, as their price moves with the oracle.
+    */
+    pub fn orders(&self, now: u64) -> Vec<Order> {
+        let root = u32::from_le_bytes(read(self.data, 8));
+        let leaf_count = u32::from_le_bytes(read(self.data, 12));
+        if leaf_count == 0 {
+            return Vec::new();
+        }
+
+        let mut orders = Vec::new();
+        let mut stack = vec![root];
+        // Bounded by the node count, in case the tree is corrupted
+        let mut visited = 0;
+        while let Some(index) = stack.pop() {
+            visited += 1;
+            if index as usize >= MAX_BOOK_SIDE_NODES || visited > MAX_BOOK_SIDE_NODES {
+                break;
+            }
+            let node = &self.data[BOOK_SIDE_NODES_OFFSET + index as usize * NODE_LEN..][..NODE_LEN];
+            match node[0] {
+                INNER_NODE_TAG => {
+                    stack.push(u32::from_le_bytes(read(node, 24)));
+                    stack.push(u32::from_le_bytes(read(node, 28)));
+                }
+                LEAF_NODE_TAG => {
+                    let time_in_force = u16::from_le_bytes(read(node, 2)) as u64;
+                    let timestamp = u64::from_le_bytes(read(node, 64));
+                    // Same as LeafNode::is_expired of the program
+                    if time_in_force > 0 && now >= timestamp + time_in_force {
+                        continue;
+                    }
+                    // Upper half of the key is the price, the lower one the sequence number
+                    let key = u128::from_le_bytes(read(node, 8));
+                    orders.push(Order {
+                        price_lots: (key >> 64) as i64,
+                        quantity_lots: i64::from_le_bytes(read(node, 56)),
+                    });
+                }
+                _ => {}
+            }
+        }
+        orders
+    }
+}
+
+/*
+    Snapshot of an on-chain order book quoting market orders of the base token, walking the levels
+    from the best one. The taker fee is charged on top of the quote notional.
+*/
+#[derive(PartialEq)]
+pub struct ClobQuoter {
+    order_book: OrderBook,
+    taker_fee: Decimal,
+    quote_mint: Pubkey,
+}
+
+impl ClobQuoter {
+    pub fn new(order_book: OrderBook, taker_fee: Decimal, quote_mint: Pubkey) -> Self {
+        Self {
+            order_book,
+            taker_fee,
+            quote_mint,
+        }
+    }
+
+    pub fn order_book(&self) -> &OrderBook {
+        &self.order_book
+    }
+
+    /*
+        Returns the quote notional of taking the base quantity, None if the book is not deep enough
+    */
+    fn fill_quantity(&self, side: OrderSide, quantity: Decimal) -> Option<Decimal> {
+        let levels: Box<dyn Iterator<Item = PriceLevel>> = match side {
+            OrderSide::Buy => Box::new(self.order_book.asks()),
+            OrderSide::Sell => Box::new(self.order_book.bids()),
+        };
+
+        let mut remaining = quantity;
+        let mut notional = Decimal::ZERO;
+        for level in levels {
+            if remaining.is_zero() {
+                break;
+            }
+            let filled = remaining.min(level.quantity);
+            notional += filled.checked_mul(level.price)?;
+            remaining -= filled;
+        }
+        remaining.is_zero().then_some(notional)
+    }
+}
+
+impl DexQuoter for ClobQuoter {
+    /*
+        Returns the mid price, or the only side if the other one is empty
+    */
+    fn spot_price(&self) -> Decimal {
+        match (self.order_book.bids().next(), self.order_book.asks().next()) {
+            (Some(bid), Some(ask)) => (bid.price + ask.price) / Decimal::TWO,
+            (Some(level), None) | (None, Some(level)) => level.price,
+            (None, None) => Decimal::ZERO,
+        }
+    }
+
+    /*
+        Returns the best ask when buying and the best bid when selling, with an empty side never being profitable
+    */
+    fn best_price(&self, side: OrderSide) -> Decimal {
+        match side {
+            OrderSide::Buy => self
+                .order_book
+                .asks()
+                .next()
+                .map_or(Decimal::MAX, |ask| ask.price),
+            OrderSide::Sell => self
+                .order_book
+                .bids()
+                .next()
+                .map_or(Decimal::ZERO, |bid| bid.price),
+        }
+    }
+
+    /*
+        Quotes the market order, the fee is expressed in the input token (converted at the best price when selling)
+    */
+    fn quote(&self, side: OrderSide, mode: SwapMode, amount: Decimal) -> Result<DexQuote> {
+        let not_deep_enough = || anyhow!("Order book is not deep enough for {}", amount);
+        let (quantity, notional) = match mode {
+            SwapMode::ExactIn if side == OrderSide::Sell => (
+                amount,
+                self.fill_quantity(side, amount)
+                    .ok_or_else(not_deep_enough)?,
+            ),
+            SwapMode::ExactOut if side == OrderSide::Buy => (
+                amount,
+                self.fill_quantity(side, amount)
+                    .ok_or_else(not_deep_enough)?,
+            ),
+            // The amount is in the quote token, including the fee
+            _ => {
+                let notional = match side {
+                    OrderSide::Buy => amount / (Decimal::ONE + self.taker_fee),
+                    OrderSide::Sell => amount / (Decimal::ONE - self.taker_fee),
+                };
+                let fill = self
+                    .order_book
+                    .fill_notional(side, notional)
+                    .ok_or_else(not_deep_enough)?;
+                (fill.quantity, notional)
+            }
+        };
+        let fee = notional * self.taker_fee;
+
+        Ok(match side {
+            OrderSide::Buy => DexQuote {
+                amount_in: notional + fee,
+                amount_out: quantity,
+                fee,
+            },
+            OrderSide::Sell => DexQuote {
+                amount_in: quantity,
+                amount_out: notional - fee,
+                fee: fee / self.best_price(side),
+            },
+        })
+    }
+
+    fn quote_mint(&self) -> Option<Pubkey> {
+        Some(self.quote_mint)
+    }
+
+    fn same_state(&self, other: &dyn DexQuoter) -> bool {
+        same_state(self, other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rust_decimal::Decimal;
+    use solana_program::pubkey::Pubkey;
+
+    use super::{
+        BookSide, ClobQuoter, DexPoolSource, DexQuoter, Market, OpenBook, Order, SwapMode,
+    };
+    use crate::{
+        structs::cex::{
+            binance::PriceLevel,
+            order_book::{OrderBook, OrderSide},
+        },
+        test_support::{
+            decimal,
+            mock_http::MockHttpServer,
+            openbook_fixtures::{
+                book_side_account_data, market_account_data, market_accounts, sol_usdc_market,
+                LeafFixture,
+            },
+        },
+    };
+
+    fn level(price: &str, quantity: &str) -> PriceLevel {
+        PriceLevel {
+            price: decimal(price),
+            quantity: decimal(quantity),
+        }
+    }
+
+    // 69.9 x 1, 69.8 x 2 / 70.1 x 1, 70.2 x 2 with the 0.04% taker fee
+    fn quoter() -> ClobQuoter {
+        ClobQuoter::new(
+            OrderBook::from_orders(
+                [level("69.9", "1"), level("69.8", "2")],
+                [level("70.1", "1"), level("70.2", "2")],
+            ),
+            decimal("0.0004"),
+            Pubkey::default(),
+        )
+    }
+
+    #[test]
+    fn test_decode() {
+        let market = sol_usdc_market();
+        assert_eq!(
+            Market::from_account_data(&market_account_data(&market)).unwrap(),
+            market
+        );
+        assert_eq!(market.taker_fee(), decimal("0.0004"));
+        // 69900 USDC atoms per lot of 0.001 SOL, 1000 lots of 0.001 SOL
+        assert_eq!(
+            market
+                .price_level(Order {
+                    price_lots: 69900,
+                    quantity_lots: 1000,
+                })
+                .unwrap(),
+            level("69.9", "1")
+        );
+
+        let data = book_side_account_data(&[
+            LeafFixture::new(69900, 1000),
+            LeafFixture::new(69800, 2000),
+            LeafFixture::new(69700, 500),
+        ]);
+        let mut orders = BookSide::from_account_data(&data).unwrap().orders(0);
+        orders.sort_by_key(|order| order.price_lots);
+        assert_eq!(
+            orders,
+            vec![
+                Order {
+                    price_lots: 69700,
+                    quantity_lots: 500
+                },
+                Order {
+                    price_lots: 69800,
+                    quantity_lots: 2000
+                },
+                Order {
+                    price_lots: 69900,
+                    quantity_lots: 1000
+                },
+            ]
+        );
+        assert!(BookSide::from_account_data(&book_side_account_data(&[]))
+            .unwrap()
+            .orders(0)
+            .is_empty());
+
+        assert!(Market::from_account_data(&data).is_err());
+        assert!(BookSide::from_account_data(&market_account_data(&market)).is_err());
+    }
+
+    #[test]
+    fn test_decode_invalid_market() {
+        let invalid_markets = [
+            Market {
+                base_decimals: 29,
+                ..sol_usdc_market()
+            },
+            Market {
+                base_lot_size: 0,
+                ..sol_usdc_market()
+            },
+            Market {
+                quote_lot_size: 0,
+                ..sol_usdc_market()
+            },
+        ];
+        for market in invalid_markets {
+            assert!(
+                Market::from_account_data(&market_account_data(&market)).is_err(),
+                "{:?}",
+                market
+            );
+        }
+    }
+
+    #[test]
+    fn test_expired_orders() {
+        let data = book_side_account_data(&[
+            LeafFixture::new(69900, 1000).expiring(100, 10),
+            LeafFixture::new(69800, 2000),
+        ]);
+        let book_side = BookSide::from_account_data(&data).unwrap();
+        assert_eq!(book_side.orders(109).len(), 2);
+        assert_eq!(
+            book_side.orders(110),
+            vec![Order {
+                price_lots: 69800,
+                quantity_lots: 2000
+            }]
+        );
+    }
+
+    #[test]
+    fn test_quote() {
+        let quoter = quoter();
+        assert_eq!(quoter.spot_price(), decimal("70"));
+        assert_eq!(quoter.best_price(OrderSide::Buy), decimal("70.1"));
+        assert_eq!(quoter.best_price(OrderSide::Sell), decimal("69.9"));
+
+        // Buying 2 SOL takes 70.1 x 1 and 70.2 x 1
+        let quote = quoter
+            .quote(OrderSide::Buy, SwapMode::ExactOut, decimal("2"))
+            .unwrap();
+        assert_eq!(quote.amount_in, decimal("140.35612"));
+        assert_eq!(quote.fee, decimal("0.05612"));
+
+        // Selling 2 SOL takes 69.9 x 1 and 69.8 x 1
+        let quote = quoter
+            .quote(OrderSide::Sell, SwapMode::ExactIn, decimal("2"))
+            .unwrap();
+        assert_eq!(quote.amount_out, decimal("139.64412"));
+        assert_eq!(
+            (quote.fee * decimal("69.9")).round_dp(12),
+            decimal("0.05588")
+        );
+
+        // Exact input and exact output quotes of the same order match
+        let quote = quoter
+            .quote(OrderSide::Buy, SwapMode::ExactIn, decimal("140.35612"))
+            .unwrap();
+        assert_eq!(quote.amount_out.round_dp(12), decimal("2"));
+        let quote = quoter
+            .quote(OrderSide::Sell, SwapMode::ExactOut, decimal("139.64412"))
+            .unwrap();
+        assert_eq!(quote.amount_in.round_dp(12), decimal("2"));
+
+        // Only 3 SOL are offered
+        assert!(quoter
+            .quote(OrderSide::Buy, SwapMode::ExactOut, decimal("3.1"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_empty_side() {
+        let quoter = ClobQuoter::new(
+            OrderBook::from_orders([level("69.9", "1")], []),
+            Decimal::ZERO,
+            Pubkey::default(),
+        );
+        assert_eq!(quoter.spot_price(), decimal("69.9"));
+        assert_eq!(quoter.best_price(OrderSide::Buy), Decimal::MAX);
+        assert!(quoter
+            .quote(OrderSide::Buy, SwapMode::ExactOut, Decimal::ONE)
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_quoter() {
+        let server = MockHttpServer::start().await;
+        let market_address = Pubkey::new_unique();
+        server.solana_accounts(
+            "/",
+            market_accounts(
+                &market_address,
+                &sol_usdc_market(),
+                &[LeafFixture::new(69900, 1000), LeafFixture::new(69800, 2000)],
+                &[LeafFixture::new(70100, 1000), LeafFixture::new(70200, 2000)],
+            ),
+        );
+        let openbook = OpenBook::new(&server.url(), Duration::from_secs(1), market_address);
+
+        let quoter = openbook.fetch_quoter().await.unwrap();
+        assert_eq!(quoter.best_price(OrderSide::Buy), decimal("70.1"));
+        assert_eq!(quoter.best_price(OrderSide::Sell), decimal("69.9"));
+        assert_eq!(
+            quoter
+                .quote(OrderSide::Buy, SwapMode::ExactOut, decimal("2"))
+                .unwrap()
+                .amount_in,
+            decimal("140.35612")
+        );
+        assert_eq!(quoter.quote_mint(), Some(sol_usdc_market().quote_mint));
+    }
+}
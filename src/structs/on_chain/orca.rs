@@ -0,0 +1,565 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use rust_decimal::{
+    prelude::{FromPrimitive, ToPrimitive},
+    Decimal,
+};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_program::pubkey::Pubkey;
+
+use super::{
+    account::{account_discriminator, get_account, get_accounts, mint_decimals, read},
+    dex_quoter::{same_state, DexPoolSource, DexQuote, DexQuoter, SwapMode},
+};
+use crate::structs::cex::order_book::OrderSide;
+
+pub const WHIRLPOOL_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc");
+
+pub const WHIRLPOOL_LEN: usize = 653;
+pub const TICK_ARRAY_LEN: usize = 9988;
+pub const TICK_ARRAY_SIZE: i32 = 88;
+pub const TICK_LEN: usize = 113;
+
+// Tick arrays loaded on each side of the current one, swaps can't go past them
+const TICK_ARRAYS_AROUND: i32 = 2;
+const MIN_TICK_INDEX: i32 = -443636;
+const MAX_TICK_INDEX: i32 = 443636;
+
+/*
+    Struct representing Orca Whirlpool pools of a Solana RPC node
+*/
+pub struct Orca {
+    client: RpcClient,
+    whirlpool: Pubkey,
+}
+
+impl Orca {
+    /*
+        Creates a client reading the Whirlpool and its tick arrays from the Solana JSON-RPC endpoint,
+        failing requests taking longer than the timeout
+    */
+    pub fn new(rpc_url: &str, timeout: Duration, whirlpool: Pubkey) -> Self {
+        Self {
+            client: RpcClient::new_with_timeout(rpc_url.to_string(), timeout),
+            whirlpool,
+        }
+    }
+}
+
+#[async_trait]
+impl DexPoolSource for Orca {
+    /*
+        Fetches the Whirlpool account, followed by its token mints and the tick arrays around the current price
+    */
+    async fn fetch_quoter(&self) -> Result<Arc<dyn DexQuoter + Send + Sync>> {
+        let whirlpool_account = get_account(&self.client, &self.whirlpool, "Whirlpool").await?;
+        let whirlpool = Whirlpool::from_account_data(&whirlpool_account.data)?;
+
+        let mut addresses = vec![whirlpool.token_mint_a, whirlpool.token_mint_b];
+        for offset in -TICK_ARRAYS_AROUND..=TICK_ARRAYS_AROUND {
+            let start_tick_index =
+                whirlpool.tick_array_start_index(whirlpool.tick_current_index, offset);
+            addresses.push(tick_array_address(&self.whirlpool, start_tick_index));
+        }
+        let accounts = get_accounts(&self.client, &addresses).await?;
+
+        let decimals_a = mint_decimals(accounts[0].as_ref(), &addresses[0])?;
+        let decimals_b = mint_decimals(accounts[1].as_ref(), &addresses[1])?;
+        // Tick arrays without any initialized tick are not created on-chain
+        let tick_arrays = accounts[2..]
+            .iter()
+            .flatten()
+            .map(|account| TickArray::from_account_data(&account.data))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Arc::new(WhirlpoolQuoter::new(
+            whirlpool,
+            decimals_a,
+            decimals_b,
+            &tick_arrays,
+        )))
+    }
+}
+
+/*
+    Subset of the Whirlpool account fields needed for quoting
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Whirlpool {
+    pub tick_spacing: u16,
+    pub fee_rate: u16,    // in hundredths of a bps
+    pub liquidity: u128,  // active liquidity
+    pub sqrt_price: u128, // Q64.64 square root of the price of token A in token B atoms
+    pub tick_current_index: i32,
+    pub token_mint_a: Pubkey,
+    pub token_mint_b: Pubkey,
+}
+
+impl Whirlpool {
+    pub fn from_account_data(data: &[u8]) -> Result<Self> {
+        check_account_data(data, "Whirlpool", WHIRLPOOL_LEN)?;
+
+        Ok(Self {
+            tick_spacing: u16::from_le_bytes(read(data, 41)),
+            fee_rate: u16::from_le_bytes(read(data, 45)),
+            liquidity: u128::from_le_bytes(read(data, 49)),
+            sqrt_price: u128::from_le_bytes(read(data, 65)),
+            tick_current_index: i32::from_le_bytes(read(data, 81)),
+            token_mint_a: Pubkey::new_from_array(read(data, 101)),
+            token_mint_b: Pubkey::new_from_array(read(data, 181)),
+        })
+    }
+
+    /*
+        Returns the start tick of the tick array holding the tick, shifted by offset arrays
+    */
+    pub fn tick_array_start_index(&self, tick_index: i32, offset: i32) -> i32 {
+        let ticks_in_array = TICK_ARRAY_SIZE * self.tick_spacing as i32;
+        (tick_index.div_euclid(ticks_in_array) + offset) * ticks_in_array
+    }
+}
+
+/*
+    Subset of the TickArray account fields needed for quoting
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TickArray {
+    pub start_tick_index: i32,
+    pub ticks: Vec<Tick>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tick {
+    pub initialized: bool,
+    pub liquidity_net: i128, // change of the active liquidity when the price crosses the tick upwards
+}
+
+impl TickArray {
+    pub fn from_account_data(data: &[u8]) -> Result<Self> {
+        check_account_data(data, "TickArray", TICK_ARRAY_LEN)?;
+
+        let ticks = (0..TICK_ARRAY_SIZE as usize)
+            .map(|index| {
+                let offset = 12 + index * TICK_LEN;
+                Tick {
+                    initialized: data[offset] != 0,
+                    liquidity_net: i128::from_le_bytes(read(data, offset + 1)),
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            start_tick_index: i32::from_le_bytes(read(data, 8)),
+            ticks,
+        })
+    }
+}
+
+/*
+    Returns the address of the Whirlpool tick array starting at the tick
+*/
+pub fn tick_array_address(whirlpool: &Pubkey, start_tick_index: i32) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"tick_array",
+            whirlpool.as_ref(),
+            start_tick_index.to_string().as_bytes(),
+        ],
+        &WHIRLPOOL_PROGRAM_ID,
+    )
+    .0
+}
+
+/*
+    Snapshot of a Whirlpool with the tick arrays around the current price, quoting swaps of token A (base)
+    for token B (quote). Missing tick arrays are treated as having no initialized ticks.
+    The curve math is done in floating point, which is precise enough for estimating execution prices.
+*/
+#[derive(PartialEq)]
+pub struct WhirlpoolQuoter {
+    whirlpool: Whirlpool,
+    decimals_a: u8,
+    decimals_b: u8,
+    // liquidity_net of the initialized ticks of the loaded tick arrays
+    ticks: BTreeMap<i32, i128>,
+    // range of ticks covered by the loaded tick arrays
+    min_tick_index: i32,
+    max_tick_index: i32,
+}
+
+/*
+    Amounts of a swap in atoms
+*/
+struct Swap {
+    amount_in: f64,
+    amount_out: f64,
+    fee: f64,
+}
+
+impl WhirlpoolQuoter {
+    pub fn new(
+        whirlpool: Whirlpool,
+        decimals_a: u8,
+        decimals_b: u8,
+        tick_arrays: &[TickArray],
+    ) -> Self {
+        let min_tick_index = whirlpool
+            .tick_array_start_index(whirlpool.tick_current_index, -TICK_ARRAYS_AROUND)
+            .max(MIN_TICK_INDEX);
+        let max_tick_index = whirlpool
+            .tick_array_start_index(whirlpool.tick_current_index, TICK_ARRAYS_AROUND + 1)
+            .min(MAX_TICK_INDEX);
+
+        let mut ticks = BTreeMap::new();
+        for tick_array in tick_arrays {
+            for (index, tick) in tick_array.ticks.iter().enumerate() {
+                let tick_index =
+                    tick_array.start_tick_index + index as i32 * whirlpool.tick_spacing as i32;
+                if tick.initialized && (min_tick_index..=max_tick_index).contains(&tick_index) {
+                    ticks.insert(tick_index, tick.liquidity_net);
+                }
+            }
+        }
+
+        Self {
+            whirlpool,
+            decimals_a,
+            decimals_b,
+            ticks,
+            min_tick_index,
+            max_tick_index,
+        }
+    }
+
+    /*
+        Simulates the swap step by step, each step ending either at the next initialized tick or within the current range
+    */
+    fn swap(&self, a_to_b: bool, exact_in: bool, amount: f64) -> Result<Swap> {
+        let fee_rate = self.whirlpool.fee_rate as f64 / 1_000_000.0;
+        let mut sqrt_price = self.whirlpool.sqrt_price as f64 / 2f64.powi(64);
+        let mut liquidity = self.whirlpool.liquidity as f64;
+        let mut tick_index = self.whirlpool.tick_current_index;
+        let mut remaining = amount;
+        let mut swap = Swap {
+            amount_in: 0.0,
+            amount_out: 0.0,
+            fee: 0.0,
+        };
+
+        loop {
+            let next_tick = if a_to_b {
+                self.ticks.range(..=tick_index).next_back()
+            } else {
+                self.ticks.range(tick_index + 1..).next()
+            };
+            let target_tick_index = match next_tick {
+                Some((tick_index, _)) => *tick_index,
+                None if a_to_b => self.min_tick_index,
+                None => self.max_tick_index,
+            };
+            let target_sqrt_price = 1.0001f64.powf(target_tick_index as f64 / 2.0);
+
+            // Amounts needed to move the price to the target
+            let (max_in, max_out) = if a_to_b {
+                (
+                    liquidity * (1.0 / target_sqrt_price - 1.0 / sqrt_price),
+                    liquidity * (sqrt_price - target_sqrt_price),
+                )
+            } else {
+                (
+                    liquidity * (target_sqrt_price - sqrt_price),
+                    liquidity * (1.0 / sqrt_price - 1.0 / target_sqrt_price),
+                )
+            };
+            let max_in_with_fee = max_in / (1.0 - fee_rate);
+
+            if exact_in && remaining <= max_in_with_fee {
+                let amount_in = remaining * (1.0 - fee_rate);
+                let next_sqrt_price = if a_to_b {
+                    1.0 / (1.0 / sqrt_price + amount_in / liquidity)
+                } else {
+                    sqrt_price + amount_in / liquidity
+                };
+                swap.amount_in += remaining;
+                swap.fee += remaining - amount_in;
+                swap.amount_out += if a_to_b {
+                    liquidity * (sqrt_price - next_sqrt_price)
+                } else {
+                    liquidity * (1.0 / sqrt_price - 1.0 / next_sqrt_price)
+                };
+                return Ok(swap);
+            }
+            if !exact_in && remaining <= max_out {
+                let next_sqrt_price = if a_to_b {
+                    sqrt_price - remaining / liquidity
+                } else {
+                    1.0 / (1.0 / sqrt_price - remaining / liquidity)
+                };
+                let amount_in = if a_to_b {
+                    liquidity * (1.0 / next_sqrt_price - 1.0 / sqrt_price)
+                } else {
+                    liquidity * (next_sqrt_price - sqrt_price)
+                };
+                let amount_in_with_fee = amount_in / (1.0 - fee_rate);
+                swap.amount_in += amount_in_with_fee;
+                swap.fee += amount_in_with_fee - amount_in;
+                swap.amount_out += remaining;
+                return Ok(swap);
+            }
+
+            let Some((_, liquidity_net)) = next_tick else {
+                return Err(anyhow!(
+                    "Swap exceeds the liquidity of the loaded tick arrays"
+                ));
+            };
+
+            // Moves to the target and crosses the tick
+            swap.amount_in += max_in_with_fee;
+            swap.fee += max_in_with_fee - max_in;
+            swap.amount_out += max_out;
+            remaining -= if exact_in { max_in_with_fee } else { max_out };
+            sqrt_price = target_sqrt_price;
+            if a_to_b {
+                liquidity -= *liquidity_net as f64;
+                tick_index = target_tick_index - 1;
+            } else {
+                liquidity += *liquidity_net as f64;
+                tick_index = target_tick_index;
+            }
+        }
+    }
+}
+
+impl DexQuoter for WhirlpoolQuoter {
+    fn spot_price(&self) -> Decimal {
+        let sqrt_price = self.whirlpool.sqrt_price as f64 / 2f64.powi(64);
+        let decimals = self.decimals_a as i32 - self.decimals_b as i32;
+        Decimal::from_f64(sqrt_price * sqrt_price * 10f64.powi(decimals)).unwrap_or_default()
+    }
+
+    /*
+        Quotes the swap simulated across the loaded ticks, rounding the atoms computed in floating point
+        against the trader
+    */
+    fn quote(&self, side: OrderSide, mode: SwapMode, amount: Decimal) -> Result<DexQuote> {
+        // Buying the base token swaps token B for token A
+        let a_to_b = side == OrderSide::Sell;
+        let (decimals_in, decimals_out) = if a_to_b {
+            (self.decimals_a, self.decimals_b)
+        } else {
+            (self.decimals_b, self.decimals_a)
+        };
+        let decimals = match mode {
+            SwapMode::ExactIn => decimals_in,
+            SwapMode::ExactOut => decimals_out,
+        };
+        let atoms = amount
+            .to_f64()
+            .ok_or_else(|| anyhow!("Invalid amount {}", amount))?
+            * 10f64.powi(decimals as i32);
+
+        let swap = self.swap(a_to_b, mode == SwapMode::ExactIn, atoms)?;
+        let from_atoms = |atoms: f64, decimals: u8| {
+            Decimal::from_i128_with_scale(atoms as i128, decimals as u32).normalize()
+        };
+        Ok(DexQuote {
+            amount_in: match mode {
+                SwapMode::ExactIn => amount,
+                SwapMode::ExactOut => from_atoms(swap.amount_in.ceil(), decimals_in),
+            },
+            amount_out: match mode {
+                SwapMode::ExactIn => from_atoms(swap.amount_out.floor(), decimals_out),
+                SwapMode::ExactOut => amount,
+            },
+            fee: from_atoms(swap.fee.ceil(), decimals_in),
+        })
+    }
+
+    fn quote_mint(&self) -> Option<Pubkey> {
+        Some(self.whirlpool.token_mint_b)
+    }
+
+    fn same_state(&self, other: &dyn DexQuoter) -> bool {
+        same_state(self, other)
+    }
+}
+
+fn check_account_data(data: &[u8], account_name: &str, len: usize) -> Result<()> {
+    if data.len() < len || data[..8] != account_discriminator(account_name) {
+        return Err(anyhow!("Account is not a {}", account_name));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, time::Duration};
+
+    use rust_decimal::Decimal;
+    use solana_program::pubkey::Pubkey;
+
+    use super::{
+        tick_array_address, DexPoolSource, DexQuoter, Orca, SwapMode, TickArray, Whirlpool,
+        WhirlpoolQuoter,
+    };
+    use crate::{
+        structs::cex::order_book::OrderSide,
+        test_support::{
+            decimal,
+            mock_http::MockHttpServer,
+            orca_fixtures::{
+                sol_usdc_whirlpool, tick_array_account_data, whirlpool_account_data,
+                whirlpool_accounts,
+            },
+            solana_fixtures::{mint_account_data, USDC_MINT},
+        },
+    };
+
+    const WHIRLPOOL: &str = "HJPjoWUrhoZzkNfRpHuieeFk9WcZWjwy6PBjZ81ngndJ";
+
+    fn whirlpool() -> Whirlpool {
+        sol_usdc_whirlpool()
+    }
+
+    fn quoter(initialized_ticks: &[(i32, i128)]) -> WhirlpoolQuoter {
+        let data = tick_array_account_data(&Pubkey::default(), 64, -28160, initialized_ticks);
+        let tick_array = TickArray::from_account_data(&data).unwrap();
+        WhirlpoolQuoter::new(whirlpool(), 9, 6, &[tick_array])
+    }
+
+    #[test]
+    fn test_decode() {
+        let data = whirlpool_account_data(&whirlpool());
+        assert_eq!(Whirlpool::from_account_data(&data).unwrap(), whirlpool());
+
+        let data = tick_array_account_data(&Pubkey::default(), 64, -28160, &[(-26624, -5)]);
+        let tick_array = TickArray::from_account_data(&data).unwrap();
+        assert_eq!(tick_array.start_tick_index, -28160);
+        assert_eq!(tick_array.ticks.len(), 88);
+        assert!(tick_array.ticks[24].initialized);
+        assert_eq!(tick_array.ticks[24].liquidity_net, -5);
+        assert!(!tick_array.ticks[23].initialized);
+
+        // Accounts of other types
+        assert!(Whirlpool::from_account_data(&tick_array_account_data(
+            &Pubkey::default(),
+            64,
+            0,
+            &[]
+        ))
+        .is_err());
+        assert!(TickArray::from_account_data(&mint_account_data(9)).is_err());
+    }
+
+    #[test]
+    fn test_tick_array_start_index() {
+        let whirlpool = whirlpool();
+        assert_eq!(whirlpool.tick_array_start_index(-26594, 0), -28160);
+        assert_eq!(whirlpool.tick_array_start_index(-26594, 1), -22528);
+        assert_eq!(whirlpool.tick_array_start_index(-26594, -2), -39424);
+        assert_eq!(whirlpool.tick_array_start_index(0, 0), 0);
+        assert_eq!(whirlpool.tick_array_start_index(-1, 0), -5632);
+    }
+
+    #[test]
+    fn test_quote() {
+        let quoter = quoter(&[]);
+        assert_eq!(quoter.spot_price().round_dp(8), decimal("70"));
+
+        // Selling 1 SOL
+        let quote = quoter
+            .quote(OrderSide::Sell, SwapMode::ExactIn, Decimal::ONE)
+            .unwrap();
+        assert_eq!(quote.amount_in, Decimal::ONE);
+        assert_eq!(quote.amount_out, decimal("69.771595"));
+        assert_eq!(quote.fee, decimal("0.003"));
+
+        // Buying 1 SOL
+        let quote = quoter
+            .quote(OrderSide::Buy, SwapMode::ExactOut, Decimal::ONE)
+            .unwrap();
+        assert_eq!(quote.amount_in, decimal("70.229213"));
+        assert_eq!(quote.amount_out, Decimal::ONE);
+        assert_eq!(quote.fee, decimal("0.210688"));
+
+        // Exact output and exact input quotes of the same swap match
+        let quote = quoter
+            .quote(OrderSide::Sell, SwapMode::ExactOut, decimal("69.771595"))
+            .unwrap();
+        assert!((quote.amount_in - Decimal::ONE).abs() < decimal("0.000001"));
+        let quote = quoter
+            .quote(OrderSide::Buy, SwapMode::ExactIn, decimal("70.229213"))
+            .unwrap();
+        assert!((quote.amount_out - Decimal::ONE).abs() < decimal("0.000001"));
+    }
+
+    #[test]
+    fn test_quote_crosses_ticks() {
+        // Liquidity ends 30 ticks below and 34 ticks above the current one, ~5.7 SOL can be sold and ~6.4 SOL bought
+        {
+            let quoter = quoter(&[(-26624, 1_000_000_000_000), (-26560, -1_000_000_000_000)]);
+            assert!(quoter
+                .quote(OrderSide::Sell, SwapMode::ExactIn, decimal("5"))
+                .is_ok());
+            assert!(quoter
+                .quote(OrderSide::Sell, SwapMode::ExactIn, decimal("6"))
+                .is_err());
+            assert!(quoter
+                .quote(OrderSide::Buy, SwapMode::ExactOut, decimal("6"))
+                .is_ok());
+            assert!(quoter
+                .quote(OrderSide::Buy, SwapMode::ExactOut, decimal("7"))
+                .is_err());
+        }
+
+        // Half of the liquidity ends below, the price moves faster after crossing the tick
+        {
+            let sell = |quoter: &WhirlpoolQuoter| {
+                quoter
+                    .quote(OrderSide::Sell, SwapMode::ExactIn, decimal("10"))
+                    .unwrap()
+                    .amount_out
+            };
+            assert!(sell(&quoter(&[(-26624, 500_000_000_000)])) < sell(&quoter(&[])));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_quoter() {
+        let whirlpool_address = Pubkey::from_str(WHIRLPOOL).unwrap();
+        let server = MockHttpServer::start().await;
+        let mut accounts = whirlpool_accounts(&whirlpool_address, &whirlpool());
+        accounts.insert(
+            tick_array_address(&whirlpool_address, -28160),
+            tick_array_account_data(
+                &whirlpool_address,
+                64,
+                -28160,
+                &[(-26624, 1_000_000_000_000)],
+            ),
+        );
+        server.solana_accounts("/", accounts);
+        let orca = Orca::new(&server.url(), Duration::from_secs(1), whirlpool_address);
+
+        let quoter = orca.fetch_quoter().await.unwrap();
+        assert_eq!(quoter.spot_price().round_dp(8), decimal("70"));
+        assert_eq!(
+            quoter.quote_mint(),
+            Some(Pubkey::from_str(USDC_MINT).unwrap())
+        );
+        assert_eq!(
+            quoter
+                .quote(OrderSide::Sell, SwapMode::ExactIn, Decimal::ONE)
+                .unwrap()
+                .amount_out,
+            decimal("69.771595")
+        );
+        // The tick array is loaded
+        assert!(quoter
+            .quote(OrderSide::Sell, SwapMode::ExactIn, decimal("6"))
+            .is_err());
+    }
+}
@@ -0,0 +1,232 @@
+use std::{
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use pyth_sdk_solana::{state::load_price_account, Price, PriceFeed};
+use solana_program::pubkey::Pubkey;
+
+const MAX_PRICE_AGE_SECS: u64 = 60;
+// Streams without any update for this long are considered stalled
+pub const MAX_PRICE_AGE: Duration = Duration::from_secs(MAX_PRICE_AGE_SECS);
+
+/*
+    Trait for anything able to provide the most current Pyth price of a price account
+*/
+#[async_trait]
+pub trait PriceSource {
+    async fn get_price(&self, price_id: &Pubkey) -> Result<Option<Price>>;
+}
+
+/*
+    Trait for anything able to push Pyth price updates as they happen
+*/
+#[async_trait]
+pub trait PriceStream {
+    /*
+        Passes every price update to on_price, returns once the underlying subscription drops or stalls
+    */
+    async fn subscribe(
+        &self,
+        price_id: &Pubkey,
+        on_price: &mut (dyn FnMut(Option<Price>) + Send),
+    ) -> Result<()>;
+}
+
+/*
+    Parses either a base58 Solana price account pubkey or a hex (optionally 0x prefixed) Pyth price feed id.
+    Both are 32 bytes long, so feed ids are represented as Pubkey as well.
+*/
+pub fn parse_price_id(price_id: &str) -> Result<Pubkey> {
+    let hex_price_id = price_id.strip_prefix("0x").unwrap_or(price_id);
+    if hex_price_id.len() == 64 {
+        let bytes = hex::decode(hex_price_id)?;
+        return Ok(Pubkey::try_from(bytes.as_slice())?);
+    }
+
+    Pubkey::from_str(price_id).map_err(|err| anyhow!("Invalid price id {}: {}", price_id, err))
+}
+
+/*
+    Decodes Pyth price account data and returns the price if it is not older than MAX_PRICE_AGE_SECS
+*/
+pub fn load_price_from_account_data(price_id: &Pubkey, data: &[u8]) -> Result<Option<Price>> {
+    let price_feed = load_price_account(data)?.to_price_feed(price_id);
+    Ok(get_fresh_price(&price_feed))
+}
+
+/*
+    Returns the price of the feed if it is not older than MAX_PRICE_AGE_SECS
+*/
+pub fn get_fresh_price(price_feed: &PriceFeed) -> Option<Price> {
+    let current_time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+
+    price_feed.get_price_no_older_than(current_time, MAX_PRICE_AGE_SECS)
+}
+
+/*
+    Returns the time from which the price is older than MAX_PRICE_AGE_SECS and no longer accepted
+*/
+pub fn price_expiry(price: &Price) -> SystemTime {
+    let expiry_secs = price
+        .publish_time
+        .saturating_add(MAX_PRICE_AGE_SECS as i64 + 1);
+    UNIX_EPOCH + Duration::from_secs(expiry_secs.max(0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use pyth_sdk_solana::state::PriceStatus;
+    use solana_program::pubkey::Pubkey;
+
+    use super::{parse_price_id, PriceSource};
+    use crate::test_support::pyth_fixtures::{now, PriceAccountFixture, PriceAccountFixtures};
+
+    #[tokio::test]
+    async fn test_get_price_trading() {
+        let price_id = Pubkey::new_unique();
+        let mut fixtures = PriceAccountFixtures::new();
+        fixtures.insert(
+            price_id,
+            PriceAccountFixture {
+                price: 69852445,
+                conf: 669724,
+                expo: -6,
+                ..Default::default()
+            },
+        );
+
+        let price = fixtures.get_price(&price_id).await.unwrap().unwrap();
+        assert_eq!(price.price, 69852445);
+        assert_eq!(price.conf, 669724);
+        assert_eq!(price.expo, -6);
+    }
+
+    #[tokio::test]
+    async fn test_get_price_not_trading_falls_back_to_previous_price() {
+        let price_id = Pubkey::new_unique();
+        let mut fixtures = PriceAccountFixtures::new();
+
+        for status in [PriceStatus::Halted, PriceStatus::Unknown] {
+            fixtures.insert(
+                price_id,
+                PriceAccountFixture {
+                    price: 69852445,
+                    prev_price: 69000000,
+                    prev_conf: 500000,
+                    status,
+                    ..Default::default()
+                },
+            );
+
+            let price = fixtures.get_price(&price_id).await.unwrap().unwrap();
+            assert_eq!(price.price, 69000000);
+            assert_eq!(price.conf, 500000);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_price_not_trading_with_stale_previous_price() {
+        let price_id = Pubkey::new_unique();
+        let mut fixtures = PriceAccountFixtures::new();
+
+        for status in [PriceStatus::Halted, PriceStatus::Unknown] {
+            fixtures.insert(
+                price_id,
+                PriceAccountFixture {
+                    status,
+                    prev_timestamp: now() - 3600,
+                    ..Default::default()
+                },
+            );
+
+            assert!(fixtures.get_price(&price_id).await.unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_price_stale_publish_time() {
+        let price_id = Pubkey::new_unique();
+        let mut fixtures = PriceAccountFixtures::new();
+
+        fixtures.insert(
+            price_id,
+            PriceAccountFixture {
+                timestamp: now() - 61,
+                ..Default::default()
+            },
+        );
+        assert!(fixtures.get_price(&price_id).await.unwrap().is_none());
+
+        fixtures.insert(
+            price_id,
+            PriceAccountFixture {
+                timestamp: now() - 30,
+                ..Default::default()
+            },
+        );
+        assert!(fixtures.get_price(&price_id).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_get_price_small_price_with_large_negative_exponent() {
+        // e.g. BONK/USD, quoted with ten decimal places
+        let price_id = Pubkey::new_unique();
+        let mut fixtures = PriceAccountFixtures::new();
+        fixtures.insert(
+            price_id,
+            PriceAccountFixture {
+                price: 2345,
+                conf: 12,
+                expo: -10,
+                ..Default::default()
+            },
+        );
+
+        let price = fixtures.get_price(&price_id).await.unwrap().unwrap();
+        assert_eq!(price.price, 2345);
+        assert_eq!(price.conf, 12);
+        assert_eq!(price.expo, -10);
+
+        let scaled = price.scale_to_exponent(-12).unwrap();
+        assert_eq!(scaled.price, 234500);
+        assert_eq!(scaled.conf, 1200);
+    }
+
+    #[tokio::test]
+    async fn test_get_price_account_does_not_exist() {
+        let fixtures = PriceAccountFixtures::new();
+        assert!(fixtures.get_price(&Pubkey::from([0; 32])).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_price_not_price_account() {
+        let price_id = Pubkey::new_unique();
+        let mut fixtures = PriceAccountFixtures::new();
+        fixtures.insert_raw(price_id, vec![1; 3312]);
+        assert!(fixtures.get_price(&price_id).await.is_err());
+    }
+
+    #[test]
+    fn test_parse_price_id() {
+        let pubkey = parse_price_id("H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG").unwrap();
+        assert_eq!(
+            pubkey.to_string(),
+            "H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG"
+        );
+
+        let feed_id = "ef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d";
+        let with_prefix = parse_price_id(&format!("0x{}", feed_id)).unwrap();
+        let without_prefix = parse_price_id(feed_id).unwrap();
+        assert_eq!(with_prefix, without_prefix);
+        assert_eq!(hex::encode(with_prefix.to_bytes()), feed_id);
+
+        assert!(parse_price_id("not-a-price-id").is_err());
+        assert!(parse_price_id(&"zz".repeat(32)).is_err());
+    }
+}
@@ -1,11 +1,18 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures_util::StreamExt;
 use pyth_sdk_solana::Price;
-use solana_client::rpc_client::RpcClient;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    nonblocking::{pubsub_client::PubsubClient, rpc_client::RpcClient},
+    rpc_config::RpcAccountInfoConfig,
+};
 use solana_program::pubkey::Pubkey;
+use solana_sdk::account::Account;
 
-const PYTH_RPC_URL: &str = "http:/pythnet.rpcpool.com";
+use super::price_source::{load_price_from_account_data, PriceSource, PriceStream, MAX_PRICE_AGE};
 
 /*
     Struct representing a Pyth connection
@@ -15,60 +22,237 @@ pub struct Pyth {
 }
 
 impl Pyth {
-    pub fn new() -> Self {
+    /*
+        Creates a client for the Solana JSON-RPC endpoint under the provided URL, failing requests taking longer than the timeout
+    */
+    pub fn new(rpc_url: &str, timeout: Duration) -> Self {
         Self {
-            client: RpcClient::new(PYTH_RPC_URL),
+            client: RpcClient::new_with_timeout(rpc_url.to_string(), timeout),
         }
     }
+}
 
+#[async_trait]
+impl PriceSource for Pyth {
     /*
         Fetches the most current price from Pyth
     */
-    pub fn get_price(&self, price_id: &Pubkey) -> Result<Option<Price>> {
-        let mut price_account = self.client.get_account(price_id)?;
-        let price_feed =
-            pyth_sdk_solana::load_price_feed_from_account(price_id, &mut price_account)?;
+    async fn get_price(&self, price_id: &Pubkey) -> Result<Option<Price>> {
+        let price_account = self.client.get_account(price_id).await?;
+        load_price_from_account_data(price_id, &price_account.data)
+    }
+}
+
+/*
+    Struct representing a Pyth price account subscription over Solana PubSub WS
+*/
+pub struct PythStream {
+    ws_url: String,
+    // The subscription is dropped if no notification arrives for this long, e.g. over a half-open socket
+    pub idle_timeout: Duration,
+}
+
+impl PythStream {
+    pub fn new(ws_url: &str) -> Self {
+        Self {
+            ws_url: ws_url.to_string(),
+            idle_timeout: MAX_PRICE_AGE,
+        }
+    }
+}
+
+#[async_trait]
+impl PriceStream for PythStream {
+    /*
+        Subscribes to changes of the price account and passes every decoded price to on_price.
+        Fails once no notification arrives within the idle timeout.
+    */
+    async fn subscribe(
+        &self,
+        price_id: &Pubkey,
+        on_price: &mut (dyn FnMut(Option<Price>) + Send),
+    ) -> Result<()> {
+        let client = PubsubClient::new(&self.ws_url).await?;
+        let config = RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..Default::default()
+        };
+        let (mut notifications, _unsubscribe) =
+            client.account_subscribe(price_id, Some(config)).await?;
 
-        let current_time = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as i64;
+        loop {
+            let notification =
+                match tokio::time::timeout(self.idle_timeout, notifications.next()).await {
+                    Ok(Some(notification)) => notification,
+                    Ok(None) => break,
+                    Err(_) => {
+                        return Err(anyhow!(
+                            "No notification of {} within {:?}",
+                            price_id,
+                            self.idle_timeout
+                        ))
+                    }
+                };
+            let maybe_price = notification
+                .value
+                .decode::<Account>()
+                .ok_or_else(|| anyhow!("Could not decode account notification"))
+                .and_then(|account| load_price_from_account_data(price_id, &account.data));
 
-        Ok(price_feed.get_price_no_older_than(current_time, 60))
+            match maybe_price {
+                Ok(maybe_price) => on_price(maybe_price),
+                Err(err) => eprintln!("Skipping Pyth price account notification: {}", err),
+            }
+        }
+
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::str::FromStr;
+    use std::{
+        collections::HashMap,
+        str::FromStr,
+        time::{Duration, Instant},
+    };
 
     use solana_program::pubkey::Pubkey;
+    use tokio::net::TcpListener;
+
+    use super::{Pyth, PythStream};
+    use crate::{
+        structs::on_chain::price_source::{PriceSource, PriceStream},
+        test_support::{
+            eventually, mock_http::MockHttpServer, mock_pubsub::MockPubsubServer,
+            pyth_fixtures::PriceAccountFixture, solana_fixtures::mint_account_data,
+        },
+    };
 
-    use super::Pyth;
+    const PYTH_RPC_URL: &str = "https://pythnet.rpcpool.com";
+    const TIMEOUT: Duration = Duration::from_secs(30);
 
-    #[test]
-    fn test_get_price_account_does_not_exist() {
-        let pyth = Pyth::new();
+    #[tokio::test]
+    async fn test_get_price_account_does_not_exist() {
+        let server = MockHttpServer::start().await;
+        server.solana_accounts("/", HashMap::new());
+        let pyth = Pyth::new(&server.url(), TIMEOUT);
         let invalid_pubkey = Pubkey::from([0; 32]);
-        let result = pyth.get_price(&invalid_pubkey);
-        assert!(result.is_err());
+
+        let err = pyth.get_price(&invalid_pubkey).await.unwrap_err();
+        assert!(
+            err.to_string().contains("AccountNotFound"),
+            "unexpected error: {}",
+            err
+        );
     }
 
-    #[test]
-    fn test_get_price_not_price_account() {
-        let pyth = Pyth::new();
+    #[tokio::test]
+    async fn test_get_price_not_price_account() {
+        let server = MockHttpServer::start().await;
         let not_price_account_pubkey =
             Pubkey::from_str("8pwb2jNPKvji1P76fib494WkZKH7RFPgMmGkS6a3kxp9").unwrap();
-        let result = pyth.get_price(&not_price_account_pubkey);
-        assert!(result.is_err());
+        server.solana_accounts(
+            "/",
+            HashMap::from([(not_price_account_pubkey, mint_account_data(9))]),
+        );
+        let pyth = Pyth::new(&server.url(), TIMEOUT);
+
+        let err = pyth.get_price(&not_price_account_pubkey).await.unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Failed to convert account into a Pyth account"),
+            "unexpected error: {}",
+            err
+        );
     }
 
-    #[test]
-    fn test_get_price() {
-        let pyth = Pyth::new();
+    #[tokio::test]
+    #[ignore = "Requires access to pythnet, covered offline by price_source tests"]
+    async fn test_get_price() {
+        let pyth = Pyth::new(PYTH_RPC_URL, TIMEOUT);
         let sol_usd_price_pubkey =
             Pubkey::from_str("H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG").unwrap();
-        let result = pyth.get_price(&sol_usd_price_pubkey);
+        let result = pyth.get_price(&sol_usd_price_pubkey).await;
         assert!(result.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_get_price_timeout() {
+        // Accepts connections, but never responds
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let _server = tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                connections.push(stream);
+            }
+        });
+
+        let pyth = Pyth::new(&url, Duration::from_millis(100));
+        let started = Instant::now();
+        let result = pyth.get_price(&Pubkey::new_unique()).await;
+        assert!(result.is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_pyth_stream_subscribe() {
+        let server = MockPubsubServer::start().await;
+        let price_id = Pubkey::new_unique();
+        let pyth_stream = PythStream::new(&server.url());
+        let (prices_sender, mut prices) = tokio::sync::mpsc::unbounded_channel();
+
+        let subscription = tokio::spawn(async move {
+            pyth_stream
+                .subscribe(&price_id, &mut |price| prices_sender.send(price).unwrap())
+                .await
+        });
+        eventually(|| server.active_subscriptions() == vec![price_id.to_string()]).await;
+
+        let fixture = PriceAccountFixture {
+            price: 69852445,
+            conf: 669724,
+            expo: -6,
+            ..Default::default()
+        };
+        server.notify_account(&price_id, fixture.to_account_data());
+        server.notify_account(&price_id, vec![0; 16]); // not a price account, skipped
+        server.notify_account(&Pubkey::new_unique(), fixture.to_account_data());
+        let price = prices.recv().await.unwrap().unwrap();
+        assert_eq!(price.price, 69852445);
+        assert_eq!(price.conf, 669724);
+        assert_eq!(price.expo, -6);
+
+        server.disconnect();
+        assert!(subscription.await.unwrap().is_ok());
+        assert!(prices.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pyth_stream_subscribe_stalled() {
+        let server = MockPubsubServer::start().await;
+        let price_id = Pubkey::new_unique();
+        let mut pyth_stream = PythStream::new(&server.url());
+        pyth_stream.idle_timeout = Duration::from_millis(200);
+
+        // Connected, but nothing is pushed
+        let started = Instant::now();
+        let err = pyth_stream
+            .subscribe(&price_id, &mut |_| {})
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("No notification"), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(server.total_connections(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_pyth_stream_subscribe_unreachable() {
+        let pyth_stream = PythStream::new("ws://127.0.0.1:1");
+        let result = pyth_stream
+            .subscribe(&Pubkey::new_unique(), &mut |_| {})
+            .await;
+        assert!(result.is_err());
+    }
 }
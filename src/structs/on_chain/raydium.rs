@@ -0,0 +1,430 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_program::pubkey::Pubkey;
+
+use super::{
+    account::{account_discriminator, get_account, get_accounts, read},
+    dex_quoter::{same_state, DexPoolSource, DexQuote, DexQuoter, SwapMode},
+};
+use crate::structs::cex::order_book::OrderSide;
+
+pub const AMM_V4_LEN: usize = 752;
+pub const CPMM_POOL_LEN: usize = 637;
+pub const CPMM_CONFIG_LEN: usize = 236;
+// Offset of the amount in an SPL token account
+pub const TOKEN_AMOUNT_OFFSET: usize = 64;
+
+// Denominator of the CPMM trade fee rate
+const CPMM_FEE_DENOMINATOR: u64 = 1_000_000;
+
+/*
+    Struct representing Raydium constant product pools (AMM v4 or CPMM) of a Solana RPC node
+*/
+pub struct Raydium {
+    client: RpcClient,
+    pool: Pubkey,
+}
+
+impl Raydium {
+    /*
+        Creates a client reading the AMM v4 or CPMM pool and its vaults from the Solana JSON-RPC endpoint,
+        failing requests taking longer than the timeout
+    */
+    pub fn new(rpc_url: &str, timeout: Duration, pool: Pubkey) -> Self {
+        Self {
+            client: RpcClient::new_with_timeout(rpc_url.to_string(), timeout),
+            pool,
+        }
+    }
+}
+
+#[async_trait]
+impl DexPoolSource for Raydium {
+    /*
+        Fetches the pool account, followed by its vaults (and for CPMM pools its fee config)
+    */
+    async fn fetch_quoter(&self) -> Result<Arc<dyn DexQuoter + Send + Sync>> {
+        let pool_account = get_account(&self.client, &self.pool, "Raydium pool").await?;
+        let pool = RaydiumPool::from_account_data(&pool_account.data)?;
+
+        let mut addresses = vec![pool.base_vault, pool.quote_vault];
+        if let Some(amm_config) = pool.amm_config {
+            addresses.push(amm_config);
+        }
+        let accounts = get_accounts(&self.client, &addresses).await?;
+        let data = |index: usize| {
+            accounts[index]
+                .as_ref()
+                .map(|account| account.data.as_slice())
+                .ok_or_else(|| anyhow!("Account {} does not exist", addresses[index]))
+        };
+
+        let base_reserve = token_amount(data(0)?)?
+            .checked_sub(pool.base_pending_fees)
+            .ok_or_else(|| anyhow!("Base vault holds less than the pending fees"))?;
+        let quote_reserve = token_amount(data(1)?)?
+            .checked_sub(pool.quote_pending_fees)
+            .ok_or_else(|| anyhow!("Quote vault holds less than the pending fees"))?;
+        let (fee_numerator, fee_denominator) = match pool.amm_config {
+            Some(_) => (cpmm_trade_fee_rate(data(2)?)?, CPMM_FEE_DENOMINATOR),
+            None => (pool.fee_numerator, pool.fee_denominator),
+        };
+
+        Ok(Arc::new(ConstantProductQuoter::new(
+            base_reserve,
+            quote_reserve,
+            pool.base_decimals,
+            pool.quote_decimals,
+            fee_numerator,
+            fee_denominator,
+            pool.quote_mint,
+        )?))
+    }
+}
+
+/*
+    Subset of the AMM v4 or CPMM pool account fields needed for quoting.
+    The first token of the pool (coin of AMM v4, token 0 of CPMM) is the base token.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RaydiumPool {
+    pub base_vault: Pubkey,
+    pub quote_vault: Pubkey,
+    pub quote_mint: Pubkey,
+    pub base_decimals: u8,
+    pub quote_decimals: u8,
+    // parts of the vault balances not belonging to the liquidity providers
+    pub base_pending_fees: u64,
+    pub quote_pending_fees: u64,
+    // swap fee of AMM v4 pools, CPMM pools keep it in their config account
+    pub fee_numerator: u64,
+    pub fee_denominator: u64,
+    pub amm_config: Option<Pubkey>,
+}
+
+impl RaydiumPool {
+    /*
+        Decodes either pool type, AMM v4 accounts are told apart by their size as they have no discriminator
+    */
+    pub fn from_account_data(data: &[u8]) -> Result<Self> {
+        if data.len() == AMM_V4_LEN {
+            return Ok(Self {
+                base_vault: Pubkey::new_from_array(read(data, 336)),
+                quote_vault: Pubkey::new_from_array(read(data, 368)),
+                quote_mint: Pubkey::new_from_array(read(data, 432)),
+                base_decimals: u64::from_le_bytes(read(data, 32)) as u8,
+                quote_decimals: u64::from_le_bytes(read(data, 40)) as u8,
+                base_pending_fees: u64::from_le_bytes(read(data, 192)),
+                quote_pending_fees: u64::from_le_bytes(read(data, 200)),
+                fee_numerator: u64::from_le_bytes(read(data, 176)),
+                fee_denominator: u64::from_le_bytes(read(data, 184)),
+                amm_config: None,
+            });
+        }
+        if data.len() >= CPMM_POOL_LEN && data[..8] == account_discriminator("PoolState") {
+            let fees = |offset: usize| {
+                u64::from_le_bytes(read(data, offset))
+                    .saturating_add(u64::from_le_bytes(read(data, offset + 16)))
+            };
+            return Ok(Self {
+                base_vault: Pubkey::new_from_array(read(data, 72)),
+                quote_vault: Pubkey::new_from_array(read(data, 104)),
+                quote_mint: Pubkey::new_from_array(read(data, 200)),
+                base_decimals: data[331],
+                quote_decimals: data[332],
+                base_pending_fees: fees(341),
+                quote_pending_fees: fees(349),
+                fee_numerator: 0,
+                fee_denominator: 0,
+                amm_config: Some(Pubkey::new_from_array(read(data, 8))),
+            });
+        }
+        Err(anyhow!("Account is not a Raydium AMM v4 or CPMM pool"))
+    }
+}
+
+/*
+    Returns the trade fee rate of a CPMM AmmConfig account, in millionths
+*/
+pub fn cpmm_trade_fee_rate(data: &[u8]) -> Result<u64> {
+    if data.len() < CPMM_CONFIG_LEN || data[..8] != account_discriminator("AmmConfig") {
+        return Err(anyhow!("Account is not a Raydium AmmConfig"));
+    }
+    Ok(u64::from_le_bytes(read(data, 12)))
+}
+
+/*
+    Returns the balance of an SPL token account in atoms
+*/
+pub fn token_amount(data: &[u8]) -> Result<u64> {
+    if data.len() < TOKEN_AMOUNT_OFFSET + 8 {
+        return Err(anyhow!("Account is not a token account"));
+    }
+    Ok(u64::from_le_bytes(read(data, TOKEN_AMOUNT_OFFSET)))
+}
+
+/*
+    Snapshot of a constant product pool quoting swaps of the base token for the quote token.
+    The fee is taken from the input before the swap and the amounts are computed in atoms,
+    rounded in favour of the pool the same way the on-chain programs do.
+*/
+#[derive(PartialEq)]
+pub struct ConstantProductQuoter {
+    base_reserve: u64,
+    quote_reserve: u64,
+    base_decimals: u8,
+    quote_decimals: u8,
+    fee_numerator: u64,
+    fee_denominator: u64,
+    quote_mint: Pubkey,
+}
+
+impl ConstantProductQuoter {
+    pub fn new(
+        base_reserve: u64,
+        quote_reserve: u64,
+        base_decimals: u8,
+        quote_decimals: u8,
+        fee_numerator: u64,
+        fee_denominator: u64,
+        quote_mint: Pubkey,
+    ) -> Result<Self> {
+        if base_reserve == 0 || quote_reserve == 0 {
+            return Err(anyhow!("Pool has no liquidity"));
+        }
+        if fee_denominator == 0 || fee_numerator >= fee_denominator {
+            return Err(anyhow!(
+                "Invalid pool fee {}/{}",
+                fee_numerator,
+                fee_denominator
+            ));
+        }
+        Ok(Self {
+            base_reserve,
+            quote_reserve,
+            base_decimals,
+            quote_decimals,
+            fee_numerator,
+            fee_denominator,
+            quote_mint,
+        })
+    }
+
+    fn fee(&self, amount_in: u128) -> u128 {
+        (amount_in * self.fee_numerator as u128).div_ceil(self.fee_denominator as u128)
+    }
+}
+
+impl DexQuoter for ConstantProductQuoter {
+    fn spot_price(&self) -> Decimal {
+        let decimals = self.base_decimals as i32 - self.quote_decimals as i32;
+        let price = Decimal::from(self.quote_reserve) / Decimal::from(self.base_reserve);
+        if decimals >= 0 {
+            price * Decimal::from(10u64.pow(decimals as u32))
+        } else {
+            price / Decimal::from(10u64.pow(-decimals as u32))
+        }
+    }
+
+    /*
+        Quotes the swap on the x * y = k curve in integer atoms, the output rounded down and the input up
+        like the programs do
+    */
+    fn quote(&self, side: OrderSide, mode: SwapMode, amount: Decimal) -> Result<DexQuote> {
+        // Buying the base token swaps the quote token for it
+        let (reserve_in, reserve_out, decimals_in, decimals_out) = match side {
+            OrderSide::Sell => (
+                self.base_reserve as u128,
+                self.quote_reserve as u128,
+                self.base_decimals,
+                self.quote_decimals,
+            ),
+            OrderSide::Buy => (
+                self.quote_reserve as u128,
+                self.base_reserve as u128,
+                self.quote_decimals,
+                self.base_decimals,
+            ),
+        };
+        let to_atoms = |amount: Decimal, decimals: u8| {
+            (amount * Decimal::from(10u64.pow(decimals as u32)))
+                .ceil()
+                .to_u128()
+                .ok_or_else(|| anyhow!("Invalid amount {}", amount))
+        };
+        let from_atoms = |atoms: u128, decimals: u8| {
+            Decimal::from_i128_with_scale(atoms as i128, decimals as u32).normalize()
+        };
+
+        match mode {
+            SwapMode::ExactIn => {
+                let amount_in = to_atoms(amount, decimals_in)?;
+                let fee = self.fee(amount_in);
+                let amount_in_after_fee = amount_in - fee;
+                let amount_out =
+                    reserve_out * amount_in_after_fee / (reserve_in + amount_in_after_fee);
+                Ok(DexQuote {
+                    amount_in: amount,
+                    amount_out: from_atoms(amount_out, decimals_out),
+                    fee: from_atoms(fee, decimals_in),
+                })
+            }
+            SwapMode::ExactOut => {
+                let amount_out = to_atoms(amount, decimals_out)?;
+                if amount_out >= reserve_out {
+                    return Err(anyhow!("Swap exceeds the reserves of the pool"));
+                }
+                let amount_in_after_fee =
+                    (reserve_in * amount_out).div_ceil(reserve_out - amount_out);
+                let fee_denominator = self.fee_denominator as u128;
+                let amount_in = (amount_in_after_fee * fee_denominator)
+                    .div_ceil(fee_denominator - self.fee_numerator as u128);
+                Ok(DexQuote {
+                    amount_in: from_atoms(amount_in, decimals_in),
+                    amount_out: amount,
+                    fee: from_atoms(amount_in - amount_in_after_fee, decimals_in),
+                })
+            }
+        }
+    }
+
+    fn quote_mint(&self) -> Option<Pubkey> {
+        Some(self.quote_mint)
+    }
+
+    fn same_state(&self, other: &dyn DexQuoter) -> bool {
+        same_state(self, other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rust_decimal::Decimal;
+    use solana_program::pubkey::Pubkey;
+
+    use super::{ConstantProductQuoter, DexPoolSource, DexQuoter, Raydium, RaydiumPool, SwapMode};
+    use crate::{
+        structs::cex::order_book::OrderSide,
+        test_support::{
+            decimal,
+            mock_http::MockHttpServer,
+            raydium_fixtures::{
+                amm_v4_account_data, amm_v4_accounts, cpmm_accounts, cpmm_pool_account_data,
+                sol_usdc_amm_v4, sol_usdc_cpmm,
+            },
+        },
+    };
+
+    // 1000 SOL and 70000 USDC with the 0.25% fee of AMM v4 pools
+    fn quoter() -> ConstantProductQuoter {
+        ConstantProductQuoter::new(
+            1_000_000_000_000,
+            70_000_000_000,
+            9,
+            6,
+            25,
+            10000,
+            Pubkey::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_decode() {
+        let pool = sol_usdc_amm_v4();
+        assert_eq!(
+            RaydiumPool::from_account_data(&amm_v4_account_data(&pool)).unwrap(),
+            pool
+        );
+        let pool = sol_usdc_cpmm();
+        assert_eq!(
+            RaydiumPool::from_account_data(&cpmm_pool_account_data(&pool)).unwrap(),
+            pool
+        );
+
+        assert!(RaydiumPool::from_account_data(&[0; 165]).is_err());
+        assert!(RaydiumPool::from_account_data(&[0; 637]).is_err());
+    }
+
+    #[test]
+    fn test_quote() {
+        let quoter = quoter();
+        assert_eq!(quoter.spot_price(), decimal("70"));
+
+        // Selling 1 SOL, 0.9975 SOL after the fee
+        let quote = quoter
+            .quote(OrderSide::Sell, SwapMode::ExactIn, Decimal::ONE)
+            .unwrap();
+        assert_eq!(quote.amount_in, Decimal::ONE);
+        assert_eq!(quote.amount_out, decimal("69.755418"));
+        assert_eq!(quote.fee, decimal("0.0025"));
+
+        // Buying 1 SOL
+        let quote = quoter
+            .quote(OrderSide::Buy, SwapMode::ExactOut, Decimal::ONE)
+            .unwrap();
+        assert_eq!(quote.amount_in, decimal("70.245686"));
+        assert_eq!(quote.amount_out, Decimal::ONE);
+        assert_eq!(quote.fee, decimal("0.175615"));
+
+        // Exact output and exact input quotes of the same swap match
+        let quote = quoter
+            .quote(OrderSide::Buy, SwapMode::ExactIn, decimal("70.245686"))
+            .unwrap();
+        assert!((quote.amount_out - Decimal::ONE).abs() < decimal("0.000001"));
+
+        // The whole reserve can't be bought
+        assert!(quoter
+            .quote(OrderSide::Buy, SwapMode::ExactOut, decimal("1000"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_invalid_pool() {
+        assert!(ConstantProductQuoter::new(0, 70, 9, 6, 25, 10000, Pubkey::default()).is_err());
+        assert!(ConstantProductQuoter::new(1, 70, 9, 6, 25, 0, Pubkey::default()).is_err());
+        assert!(ConstantProductQuoter::new(1, 70, 9, 6, 100, 100, Pubkey::default()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_quoter() {
+        let server = MockHttpServer::start().await;
+        let amm_v4 = Pubkey::new_unique();
+        let cpmm = Pubkey::new_unique();
+        let mut accounts = amm_v4_accounts(&amm_v4, &sol_usdc_amm_v4());
+        accounts.extend(cpmm_accounts(&cpmm, &sol_usdc_cpmm()));
+        server.solana_accounts("/", accounts);
+
+        // Pending pool fees are not part of the reserves
+        let raydium = Raydium::new(&server.url(), Duration::from_secs(1), amm_v4);
+        let quoter = raydium.fetch_quoter().await.unwrap();
+        assert_eq!(quoter.spot_price(), decimal("70"));
+        assert_eq!(
+            quoter
+                .quote(OrderSide::Sell, SwapMode::ExactIn, Decimal::ONE)
+                .unwrap()
+                .amount_out,
+            decimal("69.755418")
+        );
+        assert_eq!(quoter.quote_mint(), Some(sol_usdc_amm_v4().quote_mint));
+
+        // The CPMM fee rate comes from its config
+        let raydium = Raydium::new(&server.url(), Duration::from_secs(1), cpmm);
+        let quoter = raydium.fetch_quoter().await.unwrap();
+        assert_eq!(quoter.spot_price(), decimal("70"));
+        assert_eq!(
+            quoter
+                .quote(OrderSide::Sell, SwapMode::ExactIn, Decimal::ONE)
+                .unwrap()
+                .fee,
+            decimal("0.0025")
+        );
+        assert_eq!(quoter.quote_mint(), Some(sol_usdc_cpmm().quote_mint));
+    }
+}
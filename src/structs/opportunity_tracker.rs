@@ -0,0 +1,270 @@
+use std::time::{Duration, SystemTime};
+
+use rust_decimal::Decimal;
+
+use super::arbitrage_finder::{ArbitrageDirection, ArbitrageOpportunity};
+
+/*
+    Struct following opportunities of a single pair across consecutive evaluations.
+    An opportunity is identified by its direction, it stays open as long as every evaluation finds it,
+    and is reported again only when its size or profit changes beyond the tolerance.
+*/
+pub struct OpportunityTracker {
+    // Relative change of the quantity or net profit reported as an update, e.g. 0.05 is 5%
+    tolerance: Decimal,
+    next_id: u64,
+    open: Vec<OpenOpportunity>,
+}
+
+struct OpenOpportunity {
+    tracked: TrackedOpportunity,
+    // Last state sent in an event, changes are measured against it
+    reported: ArbitrageOpportunity,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackedOpportunity {
+    pub id: u64, // unique within the tracker, kept from opening until closing
+    pub opportunity: ArbitrageOpportunity, // latest state
+    pub first_seen: SystemTime,
+    pub last_seen: SystemTime,
+    pub peak_net_profit: Decimal,
+}
+
+impl TrackedOpportunity {
+    /*
+        Returns how long the opportunity has been open, until it was seen for the last time
+    */
+    pub fn duration(&self) -> Duration {
+        self.last_seen
+            .duration_since(self.first_seen)
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OpportunityEvent {
+    Opened(TrackedOpportunity),
+    Updated(TrackedOpportunity),
+    // Carries the last state, the opportunity was not found at the time of the event
+    Closed(TrackedOpportunity),
+}
+
+impl OpportunityEvent {
+    pub fn tracked(&self) -> &TrackedOpportunity {
+        match self {
+            Self::Opened(tracked) | Self::Updated(tracked) | Self::Closed(tracked) => tracked,
+        }
+    }
+}
+
+impl OpportunityTracker {
+    pub fn new(tolerance: Decimal) -> Self {
+        Self {
+            tolerance,
+            next_id: 1,
+            open: Vec::new(),
+        }
+    }
+
+    /*
+        Takes the opportunities found by a single evaluation and returns the events of the changes,
+        closing the open opportunities which were not found
+    */
+    pub fn update(
+        &mut self,
+        now: SystemTime,
+        opportunities: &[ArbitrageOpportunity],
+    ) -> Vec<OpportunityEvent> {
+        let mut events = Vec::new();
+
+        let (still_open, closed): (Vec<_>, Vec<_>) =
+            self.open.drain(..).partition(|open_opportunity| {
+                opportunities.iter().any(|opportunity| {
+                    opportunity.direction == open_opportunity.tracked.opportunity.direction
+                })
+            });
+        self.open = still_open;
+        events.extend(
+            closed
+                .into_iter()
+                .map(|open_opportunity| OpportunityEvent::Closed(open_opportunity.tracked)),
+        );
+
+        let tolerance = self.tolerance;
+        for opportunity in opportunities {
+            match self.find_open(opportunity.direction) {
+                Some(open_opportunity) => {
+                    let tracked = &mut open_opportunity.tracked;
+                    tracked.opportunity = opportunity.clone();
+                    tracked.last_seen = now;
+                    tracked.peak_net_profit = tracked.peak_net_profit.max(opportunity.net_profit);
+
+                    if exceeds(tolerance, &open_opportunity.reported, opportunity) {
+                        open_opportunity.reported = opportunity.clone();
+                        events.push(OpportunityEvent::Updated(open_opportunity.tracked.clone()));
+                    }
+                }
+                None => {
+                    let tracked = TrackedOpportunity {
+                        id: self.next_id,
+                        opportunity: opportunity.clone(),
+                        first_seen: now,
+                        last_seen: now,
+                        peak_net_profit: opportunity.net_profit,
+                    };
+                    self.next_id += 1;
+                    events.push(OpportunityEvent::Opened(tracked.clone()));
+                    self.open.push(OpenOpportunity {
+                        tracked,
+                        reported: opportunity.clone(),
+                    });
+                }
+            }
+        }
+
+        events
+    }
+
+    /*
+        Returns the opportunities open at the moment
+    */
+    pub fn open(&self) -> impl Iterator<Item = &TrackedOpportunity> {
+        self.open
+            .iter()
+            .map(|open_opportunity| &open_opportunity.tracked)
+    }
+
+    fn find_open(&mut self, direction: ArbitrageDirection) -> Option<&mut OpenOpportunity> {
+        self.open
+            .iter_mut()
+            .find(|open_opportunity| open_opportunity.tracked.opportunity.direction == direction)
+    }
+}
+
+/*
+    Whether the quantity or net profit changed relatively more than the tolerance
+*/
+fn exceeds(
+    tolerance: Decimal,
+    reported: &ArbitrageOpportunity,
+    opportunity: &ArbitrageOpportunity,
+) -> bool {
+    let changed = |before: Decimal, after: Decimal| {
+        if before.is_zero() {
+            return !after.is_zero();
+        }
+        ((after - before) / before).abs() > tolerance
+    };
+
+    changed(reported.quantity, opportunity.quantity)
+        || changed(reported.net_profit, opportunity.net_profit)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        str::FromStr,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use rust_decimal::Decimal;
+
+    use crate::structs::arbitrage_finder::{
+        ArbitrageCosts, ArbitrageDirection, ArbitrageOpportunity,
+    };
+
+    use super::{OpportunityEvent, OpportunityTracker};
+
+    fn opportunity(
+        direction: ArbitrageDirection,
+        quantity: &str,
+        net_profit: &str,
+    ) -> ArbitrageOpportunity {
+        ArbitrageOpportunity {
+            symbol: "SOLUSDT".to_string(),
+            direction,
+            quantity: Decimal::from_str(quantity).unwrap(),
+            gross_profit: Decimal::from_str(net_profit).unwrap(),
+            net_profit: Decimal::from_str(net_profit).unwrap(),
+            edge_bps: Decimal::ONE,
+            costs: ArbitrageCosts::default(),
+            binance_price: Decimal::from(70),
+            dex_price: Decimal::from(69),
+            quote_rate: Decimal::ONE,
+        }
+    }
+
+    fn at(seconds: u64) -> std::time::SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let mut tracker = OpportunityTracker::new(Decimal::new(5, 2));
+        let sell = |quantity, net_profit| {
+            opportunity(ArbitrageDirection::SellBinanceBuyDex, quantity, net_profit)
+        };
+
+        let events = tracker.update(at(1), &[sell("1", "0.5")]);
+        assert!(matches!(&events[..], [OpportunityEvent::Opened(tracked)] if tracked.id == 1));
+
+        // Flickering within the tolerance is not reported
+        assert!(tracker.update(at(2), &[sell("1", "0.51")]).is_empty());
+        assert!(tracker.update(at(3), &[sell("1", "0.5")]).is_empty());
+
+        let events = tracker.update(at(4), &[sell("1.2", "0.7")]);
+        let [OpportunityEvent::Updated(tracked)] = &events[..] else {
+            panic!("expected an update, got {:?}", events);
+        };
+        assert_eq!(tracked.id, 1);
+        assert_eq!(tracked.first_seen, at(1));
+        assert_eq!(tracked.last_seen, at(4));
+
+        // Changes are measured against the last reported state
+        assert!(tracker.update(at(5), &[sell("1.2", "0.68")]).is_empty());
+        assert!(tracker.update(at(6), &[sell("1.2", "0.67")]).is_empty());
+        let events = tracker.update(at(7), &[sell("1.2", "0.66")]);
+        assert!(matches!(&events[..], [OpportunityEvent::Updated(_)]));
+
+        let events = tracker.update(at(8), &[]);
+        let [OpportunityEvent::Closed(tracked)] = &events[..] else {
+            panic!("expected closing, got {:?}", events);
+        };
+        assert_eq!(tracked.id, 1);
+        assert_eq!(tracked.peak_net_profit, Decimal::from_str("0.7").unwrap());
+        assert_eq!(tracked.duration(), Duration::from_secs(6));
+        assert!(tracker.update(at(9), &[]).is_empty());
+        assert_eq!(tracker.open().count(), 0);
+
+        // Reopening gets a new id
+        let events = tracker.update(at(10), &[sell("1", "0.5")]);
+        assert!(matches!(&events[..], [OpportunityEvent::Opened(tracked)] if tracked.id == 2));
+    }
+
+    #[test]
+    fn test_directions_are_tracked_separately() {
+        let mut tracker = OpportunityTracker::new(Decimal::ZERO);
+        let sell = opportunity(ArbitrageDirection::SellBinanceBuyDex, "1", "0.5");
+        let buy = opportunity(ArbitrageDirection::BuyBinanceSellDex, "2", "0.3");
+
+        let events = tracker.update(at(1), &[sell.clone(), buy.clone()]);
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| event.tracked().id)
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+
+        let events = tracker.update(at(2), &[buy]);
+        let [OpportunityEvent::Closed(tracked)] = &events[..] else {
+            panic!("expected closing, got {:?}", events);
+        };
+        assert_eq!(tracked.opportunity, sell);
+        assert_eq!(
+            tracker.open().map(|tracked| tracked.id).collect::<Vec<_>>(),
+            vec![2]
+        );
+    }
+}
@@ -1,141 +1,1186 @@
-use std::{str::FromStr, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
+use anyhow::{anyhow, Result};
 use pyth_sdk_solana::Price;
-use rust_decimal::Decimal;
 use solana_program::pubkey::Pubkey;
-use tokio::sync::{OnceCell, RwLock};
+use tokio::sync::{watch, Mutex, Notify};
 
-use crate::config::{Config, CONFIG};
+use crate::config::{is_usd_quoted, Config, OrderBookMode};
 
 use super::{
-    cex::binance::{Binance, BookTickerData},
-    on_chain::pyth::Pyth,
+    arbitrage_finder::RejectionCounts,
+    cex::{
+        binance::{Binance, BinanceEvent, BinanceStreamData, DepthUpdateData, PartialDepthData},
+        binance_rest::{BinanceCredentials, BinanceRest},
+        fees::{FeeRates, FeeSchedule},
+        order_book::{OrderBook, OrderBookSync, SnapshotFetcher},
+    },
+    on_chain::{
+        dex_quoter::{DexPoolSource, DexQuoter},
+        jupiter::Jupiter,
+        openbook::OpenBook,
+        orca::Orca,
+        price_source::{parse_price_id, price_expiry, PriceSource, PriceStream},
+        raydium::Raydium,
+    },
 };
 
-pub static STATE: OnceCell<State> = OnceCell::const_new();
-
 /*
     Struct managing runtime state of the application
 */
 pub struct State {
-    pyth: Pyth,
+    pyth: Box<dyn PriceSource + Send + Sync>,
     binance: Binance,
-    pyth_price_id: Pubkey,
-    latest_pyth_price: Arc<RwLock<Option<Price>>>,
-    latest_binance_ticker_data: Arc<RwLock<Option<BookTickerData>>>,
-    pub binance_taker_fee: Decimal,
+    binance_streams: Vec<String>,
+    pairs: BTreeMap<String, PairState>,
+    pyth_max_retries: u32,
+    pyth_retry_backoff: Duration,
+    latest_pyth_prices: HashMap<Pubkey, Arc<watch::Sender<Option<Price>>>>,
+    latest_sol_price: Arc<watch::Sender<Option<Price>>>,
+    latest_usdt_price: Arc<watch::Sender<Option<Price>>>,
     pub subscription_id: i64,
 }
 
+/*
+    Struct holding runtime state of a single monitored pair.
+    Pairs sharing a Pyth price feed observe the same price.
+*/
+pub struct PairState {
+    pub binance_ticker: String,
+    pub pyth_price_id: Pubkey,
+    pub binance_fees: FeeRates,
+    full_order_book: Option<FullOrderBook>,
+    dex_pool_source: Option<Box<dyn DexPoolSource + Send + Sync>>,
+    latest_pyth_price: Arc<watch::Sender<Option<Price>>>,
+    latest_binance_order_book: watch::Sender<Option<Arc<OrderBook>>>,
+    latest_dex_quoter: watch::Sender<Option<Arc<dyn DexQuoter + Send + Sync>>>,
+    rejections: watch::Sender<RejectionCounts>,
+}
+
+/*
+    Full order book of the pair, diff events are applied by the Binance reader and snapshots by the resync task
+*/
+struct FullOrderBook {
+    order_book_sync: Mutex<OrderBookSync>,
+    snapshot_fetcher: SnapshotFetcher,
+    // Signalled by the reader whenever the book is out of sync
+    resync_needed: Notify,
+}
+
 impl State {
     /*
-        Establishes connections and creates instance of State
+        Establishes connections and creates instance of State.
+        Streams of all the pairs are subscribed over a single Binance WS connection.
     */
-    pub async fn new() -> Self {
-        let config = CONFIG.get_or_init(|| async { Config::new() }).await;
-        let (binance, _) = Binance::connect()
-            .await
-            .expect("Could not connect to Binance WS");
-        let subscription_id = binance
-            .subscribe_to_ticker(&config.binance_ticker)
-            .await
-            .unwrap();
+    pub async fn new(config: &Config, pyth: Box<dyn PriceSource + Send + Sync>) -> Result<Self> {
+        let mut pairs = BTreeMap::new();
+        let mut binance_streams = Vec::new();
+        let mut latest_pyth_prices = HashMap::new();
+        let fee_schedule = Self::fee_schedule(config).await?;
 
-        Self {
-            pyth: Pyth::new(),
+        // SOL/USD price converts Solana transaction fees, it is tracked only if there are any
+        let latest_sol_price = if config.has_solana_fees() {
+            latest_pyth_prices
+                .entry(parse_price_id(config.sol_usd_price_id())?)
+                .or_insert_with(|| Arc::new(watch::Sender::new(None)))
+                .clone()
+        } else {
+            Arc::new(watch::Sender::new(None))
+        };
+
+        // USDT/USD price converts Binance prices of USDT pairs, it is tracked only if enabled
+        let latest_usdt_price = if config.normalize_usdt {
+            latest_pyth_prices
+                .entry(parse_price_id(config.usdt_usd_price_id())?)
+                .or_insert_with(|| Arc::new(watch::Sender::new(None)))
+                .clone()
+        } else {
+            Arc::new(watch::Sender::new(None))
+        };
+
+        for pair in config.pairs() {
+            // There is no price converting the Solana fees into other quote currencies
+            if config.has_solana_fees() && !is_usd_quoted(&pair.binance_ticker) {
+                return Err(anyhow!(
+                    "Pair {} is not quoted in USD, Solana fees can't be converted into its quote currency",
+                    pair.binance_ticker
+                ));
+            }
+            let pyth_price_id = parse_price_id(&pair.pyth_price_id)?;
+            let latest_pyth_price = latest_pyth_prices
+                .entry(pyth_price_id)
+                .or_insert_with(|| Arc::new(watch::Sender::new(None)))
+                .clone();
+            let full_order_book = match config.binance_order_book {
+                OrderBookMode::Partial => {
+                    binance_streams.push(PartialDepthData::stream(&pair.binance_ticker));
+                    None
+                }
+                OrderBookMode::Full => {
+                    binance_streams.push(DepthUpdateData::stream(&pair.binance_ticker));
+                    let binance_rest = BinanceRest::new(
+                        &config.binance_rest_url,
+                        Duration::from_millis(config.binance_rest_timeout_ms),
+                    )?;
+                    Some(FullOrderBook {
+                        order_book_sync: Mutex::new(OrderBookSync::new()),
+                        snapshot_fetcher: SnapshotFetcher::new(&pair.binance_ticker, binance_rest),
+                        resync_needed: Notify::new(),
+                    })
+                }
+            };
+
+            let pool_address = |address: &str| {
+                Pubkey::from_str(address)
+                    .map_err(|err| anyhow!("Invalid pool address {}: {}", address, err))
+            };
+            let solana_rpc_timeout = Duration::from_millis(config.pyth_rpc_timeout_ms);
+            let mut dex_pool_sources: Vec<Box<dyn DexPoolSource + Send + Sync>> = Vec::new();
+            if let Some(address) = config.whirlpool(&pair.binance_ticker) {
+                dex_pool_sources.push(Box::new(Orca::new(
+                    &config.solana_rpc_url,
+                    solana_rpc_timeout,
+                    pool_address(address)?,
+                )));
+            }
+            if let Some(address) = config.raydium_pool(&pair.binance_ticker) {
+                dex_pool_sources.push(Box::new(Raydium::new(
+                    &config.solana_rpc_url,
+                    solana_rpc_timeout,
+                    pool_address(address)?,
+                )));
+            }
+            if let Some(address) = config.openbook_market(&pair.binance_ticker) {
+                dex_pool_sources.push(Box::new(OpenBook::new(
+                    &config.solana_rpc_url,
+                    solana_rpc_timeout,
+                    pool_address(address)?,
+                )));
+            }
+            if let Some(jupiter) = config.jupiter(&pair.binance_ticker) {
+                let mint = |mint: &str| {
+                    Pubkey::from_str(mint).map_err(|err| anyhow!("Invalid mint {}: {}", mint, err))
+                };
+                dex_pool_sources.push(Box::new(Jupiter::new(
+                    &config.jupiter_url,
+                    &config.solana_rpc_url,
+                    solana_rpc_timeout,
+                    mint(&jupiter.base_mint)?,
+                    mint(&jupiter.quote_mint)?,
+                    config.jupiter_notional,
+                )?));
+            }
+            if dex_pool_sources.len() > 1 {
+                return Err(anyhow!(
+                    "Pair {} is quoted by more than one DEX",
+                    pair.binance_ticker
+                ));
+            }
+            let dex_pool_source = dex_pool_sources.pop();
+
+            let pair_state = PairState {
+                binance_fees: fee_schedule.fee_rates(&pair.binance_ticker),
+                binance_ticker: pair.binance_ticker.clone(),
+                pyth_price_id,
+                full_order_book,
+                dex_pool_source,
+                latest_pyth_price,
+                latest_binance_order_book: watch::Sender::new(None),
+                latest_dex_quoter: watch::Sender::new(None),
+                rejections: watch::Sender::new(RejectionCounts::default()),
+            };
+            if pairs
+                .insert(pair.binance_ticker.clone(), pair_state)
+                .is_some()
+            {
+                return Err(anyhow!("Pair {} is configured twice", pair.binance_ticker));
+            }
+        }
+
+        let (binance, _) = Binance::connect(&config.binance_websocket_url).await?;
+        let subscription_id = binance.subscribe_to_streams(&binance_streams).await?;
+
+        Ok(Self {
+            pyth,
             binance,
-            pyth_price_id: Pubkey::from_str(&config.pyth_price_id).unwrap(),
-            latest_pyth_price: Arc::new(RwLock::new(None)),
-            latest_binance_ticker_data: Arc::new(RwLock::new(None)),
-            binance_taker_fee: if config.binance_ticker.contains("bnb") {
-                Decimal::new(75, 5)
-            } else {
-                Decimal::new(1, 3)
-            },
+            binance_streams,
+            pairs,
+            pyth_max_retries: config.pyth_max_retries,
+            pyth_retry_backoff: Duration::from_millis(config.pyth_retry_backoff_ms),
+            latest_pyth_prices,
+            latest_sol_price,
+            latest_usdt_price,
             subscription_id,
+        })
+    }
+
+    /*
+        Creates the fee schedule of the configured VIP tier and symbol rates.
+        If the API credentials are configured, rates of the monitored pairs are fetched from the account instead,
+        falling back to the schedule on failure.
+    */
+    async fn fee_schedule(config: &Config) -> Result<FeeSchedule> {
+        let mut fee_schedule =
+            FeeSchedule::new(config.binance_vip_tier, config.binance_bnb_discount)?;
+        for fee in &config.binance_fees {
+            fee_schedule.set_symbol_rates(
+                &fee.binance_ticker,
+                FeeRates {
+                    maker: fee.maker,
+                    taker: fee.taker,
+                },
+            );
         }
+
+        let credentials = match (&config.binance_api_key, &config.binance_api_secret) {
+            (Some(api_key), Some(api_secret)) => BinanceCredentials {
+                api_key: api_key.clone(),
+                api_secret: api_secret.clone(),
+            },
+            _ => return Ok(fee_schedule),
+        };
+        let binance_rest = BinanceRest::new(
+            &config.binance_rest_url,
+            Duration::from_millis(config.binance_rest_timeout_ms),
+        )?;
+        for pair in config.pairs() {
+            match binance_rest
+                .get_commission_rates(&pair.binance_ticker, &credentials)
+                .await
+            {
+                Ok(commission) => fee_schedule.set_symbol_rates(
+                    &pair.binance_ticker,
+                    commission.fee_rates(fee_schedule.bnb_discount()),
+                ),
+                Err(err) => eprintln!(
+                    "Could not fetch commission rates of {}: {}, using the fee schedule",
+                    pair.binance_ticker, err
+                ),
+            }
+        }
+
+        Ok(fee_schedule)
     }
 
     /*
-        Returns a pointer to latest_pyth_price field
+        Returns the monitored pairs ordered by ticker
     */
-    pub fn get_latest_pyth_price(&self) -> Arc<RwLock<Option<Price>>> {
-        self.latest_pyth_price.clone()
+    pub fn pairs(&self) -> impl Iterator<Item = &PairState> {
+        self.pairs.values()
+    }
+
+    pub fn pair(&self, binance_ticker: &str) -> Option<&PairState> {
+        self.pairs.get(binance_ticker)
+    }
+
+    /*
+        Returns ids of the Pyth price feeds used by the pairs, each one once
+    */
+    pub fn pyth_price_ids(&self) -> Vec<Pubkey> {
+        self.latest_pyth_prices.keys().copied().collect()
     }
 
     /*
-        Returns a pointer to latest_binance_ticker_data field
+        Returns a receiver notified whenever the SOL/USD price changes, it never does if the price is not tracked
     */
-    pub fn get_latest_binance_ticker_data(&self) -> Arc<RwLock<Option<BookTickerData>>> {
-        self.latest_binance_ticker_data.clone()
+    pub fn subscribe_latest_sol_price(&self) -> watch::Receiver<Option<Price>> {
+        self.latest_sol_price.subscribe()
     }
 
     /*
-        Acquires write lock and updates value of latest_pyth_price field
+        Returns a receiver notified whenever the USDT/USD price changes, it never does if the price is not tracked
     */
-    pub async fn update_latest_pyth_price(&self) {
-        let maybe_price = self
-            .pyth
-            .get_price(&self.pyth_price_id)
-            .expect("Could not load price feed from account");
-        *self.latest_pyth_price.write().await = maybe_price;
+    pub fn subscribe_latest_usdt_price(&self) -> watch::Receiver<Option<Price>> {
+        self.latest_usdt_price.subscribe()
+    }
+
+    /*
+        Updates the latest price of the Pyth feed, notifying receivers only if the price changed.
+        Failed polls are retried with exponential backoff, once retries are exhausted the price is cleared.
+    */
+    pub async fn update_latest_pyth_price(&self, pyth_price_id: &Pubkey) {
+        let Some(latest_pyth_price) = self.latest_pyth_prices.get(pyth_price_id) else {
+            return;
+        };
+        let mut backoff = self.pyth_retry_backoff;
+        let mut attempt = 0;
+
+        let maybe_price = loop {
+            match self.pyth.get_price(pyth_price_id).await {
+                Ok(maybe_price) => break maybe_price,
+                Err(err) if attempt < self.pyth_max_retries => {
+                    attempt += 1;
+                    eprintln!(
+                        "Could not load Pyth price feed {}: {}, retry {}/{} in {:?}",
+                        pyth_price_id, err, attempt, self.pyth_max_retries, backoff
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(err) => {
+                    eprintln!(
+                        "Could not load Pyth price feed {}: {}, giving up until the next poll",
+                        pyth_price_id, err
+                    );
+                    break None;
+                }
+            }
+        };
+        publish_if_changed(latest_pyth_price, maybe_price);
+    }
+
+    /*
+        Updates the latest price of the Pyth feed on every update pushed by the stream.
+        The price is cleared once it gets older than the accepted age without being replaced.
+        Returns once the subscription drops.
+    */
+    pub async fn stream_latest_pyth_price(
+        &self,
+        pyth_price_id: &Pubkey,
+        price_stream: &(dyn PriceStream + Send + Sync),
+    ) -> Result<()> {
+        let latest_pyth_price = self
+            .latest_pyth_prices
+            .get(pyth_price_id)
+            .ok_or_else(|| anyhow!("Pyth price feed {} is not monitored", pyth_price_id))?;
+
+        let mut on_price =
+            |maybe_price| publish_if_changed(latest_pyth_price.as_ref(), maybe_price);
+        tokio::select! {
+            result = price_stream.subscribe(pyth_price_id, &mut on_price) => result,
+            _ = expire_pyth_price(latest_pyth_price) => Ok(()),
+        }
     }
 
     /*
-        Acquires write lock and updates value of latest_binance_ticker_data field
+        Updates the order book of the pair the next message is about, notifying receivers only if the book changed.
+        The full order book is cleared while it is out of sync with the diff events.
+        After a reconnect the books are cleared, so that stale books are not used until fresh updates arrive.
     */
-    pub async fn update_latest_binance_ticker_data(&self) {
-        if let Some(binance_response) = self.binance.read_next_message().await {
-            *self.latest_binance_ticker_data.write().await = Some(binance_response.data);
+    pub async fn update_latest_binance_order_book(&self) {
+        match self.binance.read_next_message().await {
+            Some(BinanceEvent::Data(binance_response)) => {
+                let Some(pair) = self.pairs.get(binance_response.ticker()) else {
+                    return;
+                };
+
+                match binance_response.data {
+                    BinanceStreamData::PartialDepth(partial_depth) => {
+                        publish_if_changed(
+                            &pair.latest_binance_order_book,
+                            Some(Arc::new(OrderBook::from(&partial_depth))),
+                        );
+                    }
+                    BinanceStreamData::DepthUpdate(depth_update) => {
+                        if let Some(full_order_book) = &pair.full_order_book {
+                            let mut order_book_sync = full_order_book.order_book_sync.lock().await;
+                            let mut in_sync = true;
+                            pair.latest_binance_order_book.send_if_modified(|latest| {
+                                // The published book is released first, so that the event is applied in place
+                                // unless a finder still holds it. Receivers can't borrow it meanwhile.
+                                let previous_update_id =
+                                    latest.take().map(|order_book| order_book.last_update_id());
+                                *latest = order_book_sync.apply(depth_update);
+                                in_sync = latest.is_some();
+                                latest
+                                    .as_ref()
+                                    .map(|order_book| order_book.last_update_id())
+                                    != previous_update_id
+                            });
+                            // Snapshot is fetched by the pair's resync task, reading goes on meanwhile
+                            if !in_sync {
+                                full_order_book.resync_needed.notify_one();
+                            }
+                        }
+                    }
+                    BinanceStreamData::BookTicker(_) => {}
+                }
+            }
+            Some(BinanceEvent::Reconnected { attempts }) => {
+                println!(
+                    "Reconnected to Binance WS after {} attempt(s), waiting for fresh order books",
+                    attempts
+                );
+                for pair in self.pairs.values() {
+                    if let Some(full_order_book) = &pair.full_order_book {
+                        full_order_book.order_book_sync.lock().await.reset();
+                    }
+                    publish_if_changed(&pair.latest_binance_order_book, None);
+                }
+            }
+            None => {}
         }
     }
 
     /*
-        Unsubscribes from the Binance WS ticker stream
+        Unsubscribes from the Binance WS order book streams
     */
-    pub async fn terminate(&self) {
-        let config = CONFIG.get().unwrap();
+    pub async fn terminate(&self) -> Result<()> {
         self.binance
-            .unsubscribe(&config.binance_ticker, self.subscription_id)
+            .unsubscribe_from_streams(&self.binance_streams, self.subscription_id)
             .await
-            .unwrap();
+    }
+}
+
+impl PairState {
+    /*
+        Whether the DEX side of the pair is quoted from a pool instead of the Pyth price
+    */
+    pub fn has_dex_pool(&self) -> bool {
+        self.dex_pool_source.is_some()
+    }
+
+    /*
+        Whether the pair's order book is synced from diff events and REST snapshots
+    */
+    pub fn has_full_order_book(&self) -> bool {
+        self.full_order_book.is_some()
+    }
+
+    /*
+        Waits until the Binance reader finds the full order book out of sync, then fetches snapshots
+        until one covering the buffered events is applied. The pair's lock is not held during the requests,
+        so diff events of all the pairs keep being read and buffered.
+    */
+    pub async fn resync_binance_order_book(&self) {
+        let Some(full_order_book) = &self.full_order_book else {
+            return;
+        };
+        full_order_book.resync_needed.notified().await;
+
+        loop {
+            match full_order_book.snapshot_fetcher.fetch().await {
+                Ok(snapshot) => {
+                    let mut order_book_sync = full_order_book.order_book_sync.lock().await;
+                    match order_book_sync.apply_snapshot(&snapshot) {
+                        Ok(order_book) => {
+                            publish_if_changed(&self.latest_binance_order_book, order_book);
+                            return;
+                        }
+                        Err(err) => eprintln!(
+                            "Could not sync the order book of {}: {}",
+                            self.binance_ticker, err
+                        ),
+                    }
+                }
+                Err(err) => eprintln!(
+                    "Could not fetch the order book snapshot of {}: {}",
+                    self.binance_ticker, err
+                ),
+            }
+            tokio::time::sleep(full_order_book.snapshot_fetcher.retry_interval).await;
+        }
+    }
+
+    /*
+        Fetches the current state of the pair's DEX pool, clearing the quoter if that fails.
+        Receivers are notified only if the state changed.
+    */
+    pub async fn update_latest_dex_quoter(&self) {
+        let Some(dex_pool_source) = &self.dex_pool_source else {
+            return;
+        };
+
+        match dex_pool_source.fetch_quoter().await {
+            Ok(dex_quoter) => {
+                // An unchanged pool does not wake up the finder
+                self.latest_dex_quoter.send_if_modified(|latest| {
+                    if let Some(latest) = latest {
+                        if latest.same_state(dex_quoter.as_ref()) {
+                            return false;
+                        }
+                    }
+                    *latest = Some(dex_quoter);
+                    true
+                });
+            }
+            Err(err) => {
+                eprintln!(
+                    "Could not fetch DEX pool of {}: {}",
+                    self.binance_ticker, err
+                );
+                self.latest_dex_quoter
+                    .send_if_modified(|dex_quoter| dex_quoter.take().is_some());
+            }
+        }
+    }
+
+    /*
+        Returns a receiver notified whenever the state of the pair's DEX pool changes
+    */
+    pub fn subscribe_latest_dex_quoter(
+        &self,
+    ) -> watch::Receiver<Option<Arc<dyn DexQuoter + Send + Sync>>> {
+        self.latest_dex_quoter.subscribe()
+    }
+
+    /*
+        Returns a receiver notified whenever the price of the pair's Pyth feed changes
+    */
+    pub fn subscribe_latest_pyth_price(&self) -> watch::Receiver<Option<Price>> {
+        self.latest_pyth_price.subscribe()
+    }
+
+    /*
+        Returns a receiver notified whenever the pair's Binance order book changes
+    */
+    pub fn subscribe_latest_binance_order_book(&self) -> watch::Receiver<Option<Arc<OrderBook>>> {
+        self.latest_binance_order_book.subscribe()
+    }
+
+    /*
+        Publishes counts of the candidates rejected by the pair's arbitrage finder
+    */
+    pub fn update_rejections(&self, rejections: RejectionCounts) {
+        publish_if_changed(&self.rejections, rejections);
+    }
+
+    /*
+        Returns a receiver notified whenever a candidate of the pair is rejected
+    */
+    pub fn subscribe_rejections(&self) -> watch::Receiver<RejectionCounts> {
+        self.rejections.subscribe()
+    }
+}
+
+/*
+    Replaces the value held by the channel, waking receivers up only if it differs from the current one
+*/
+fn publish_if_changed<T: PartialEq>(sender: &watch::Sender<T>, value: T) {
+    sender.send_if_modified(|current| {
+        if *current == value {
+            return false;
+        }
+        *current = value;
+        true
+    });
+}
+
+/*
+    Clears the price once it is older than the accepted age, checked by its publish time rather than the time
+    it was received, so that a stream which stops pushing doesn't leave an old price behind. Runs until the
+    sender is dropped.
+*/
+async fn expire_pyth_price(latest_pyth_price: &watch::Sender<Option<Price>>) {
+    let mut receiver = latest_pyth_price.subscribe();
+    loop {
+        let expiry = receiver.borrow_and_update().as_ref().map(price_expiry);
+        let Some(expiry) = expiry else {
+            if receiver.changed().await.is_err() {
+                return;
+            }
+            continue;
+        };
+
+        let remaining = expiry.duration_since(SystemTime::now()).unwrap_or_default();
+        tokio::select! {
+            _ = tokio::time::sleep(remaining) => {
+                latest_pyth_price.send_if_modified(|maybe_price| {
+                    let expired = maybe_price
+                        .is_some_and(|price| price_expiry(&price) <= SystemTime::now());
+                    if expired {
+                        *maybe_price = None;
+                    }
+                    expired
+                });
+            }
+            changed = receiver.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        str::FromStr,
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use anyhow::{anyhow, Result};
+    use async_trait::async_trait;
+    use clap::Parser;
+    use pyth_sdk_solana::Price;
     use rust_decimal::Decimal;
+    use serde_json::json;
+    use solana_program::pubkey::Pubkey;
 
     use super::State;
-    use crate::config::{Config, CONFIG};
+    use crate::{
+        config::Config,
+        structs::{
+            cex::{
+                binance::{DepthUpdateData, PartialDepthData, PriceLevel},
+                order_book::OrderBook,
+            },
+            on_chain::{price_source::PriceSource, pyth::PythStream},
+        },
+        test_support::{
+            eventually,
+            mock_binance::MockBinanceServer,
+            mock_http::MockHttpServer,
+            mock_pubsub::MockPubsubServer,
+            pyth_fixtures::{now, PriceAccountFixture, PriceAccountFixtures},
+            raydium_fixtures::{amm_v4_accounts, sol_usdc_amm_v4, token_account_data},
+        },
+    };
+
+    const SOL_USD_PRICE_ID: &str = "H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG";
+    const BNB_USD_PRICE_ID: &str = "4CkQJBxhU8EZ2UjhigbtdaPbpTe6mqf811fipYBFbSYN";
+
+    /*
+        PriceSource failing the first calls before delegating to fixtures
+    */
+    struct FlakyPriceSource {
+        failures_left: AtomicU32,
+        calls: AtomicU32,
+        fixtures: PriceAccountFixtures,
+    }
+
+    #[async_trait]
+    impl PriceSource for Arc<FlakyPriceSource> {
+        async fn get_price(&self, price_id: &Pubkey) -> Result<Option<Price>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.failures_left.load(Ordering::SeqCst) > 0 {
+                self.failures_left.fetch_sub(1, Ordering::SeqCst);
+                return Err(anyhow!("RPC unavailable"));
+            }
+            self.fixtures.get_price(price_id).await
+        }
+    }
+
+    fn flaky_price_source(failures: u32) -> Arc<FlakyPriceSource> {
+        let mut fixtures = PriceAccountFixtures::new();
+        fixtures.insert(
+            Pubkey::from_str(SOL_USD_PRICE_ID).unwrap(),
+            PriceAccountFixture::default(),
+        );
+        Arc::new(FlakyPriceSource {
+            failures_left: AtomicU32::new(failures),
+            calls: AtomicU32::new(0),
+            fixtures,
+        })
+    }
+
+    async fn new_state(
+        server: &MockBinanceServer,
+        price_source: impl PriceSource + Send + Sync + 'static,
+    ) -> State {
+        let config = Config::parse_from([
+            "keyrock-task",
+            "--pyth-price-id",
+            SOL_USD_PRICE_ID,
+            "--binance-websocket-url",
+            &server.url(),
+            "--pyth-max-retries",
+            "2",
+            "--pyth-retry-backoff-ms",
+            "1",
+        ]);
+        State::new(&config, Box::new(price_source)).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_new_default_fees() {
+        let server = MockBinanceServer::start().await;
+        let config = Config::parse_from([
+            "keyrock-task",
+            "--binance-ticker",
+            "bnbusdt",
+            "--pyth-price-id",
+            "4CkQJBxhU8EZ2UjhigbtdaPbpTe6mqf811fipYBFbSYN",
+            "--binance-websocket-url",
+            &server.url(),
+        ]);
+        let state = State::new(&config, Box::new(PriceAccountFixtures::new()))
+            .await
+            .unwrap();
+        assert_eq!(
+            state.pair("bnbusdt").unwrap().binance_fees.taker,
+            Decimal::new(1, 3)
+        );
+        assert!(server
+            .active_subscriptions()
+            .contains("bnbusdt@depth20@100ms"));
+    }
 
     #[tokio::test]
-    #[ignore = "Uses global static, hence has to be ran manually"]
-    async fn test_new_bnb_pair() {
-        CONFIG
-            .get_or_init(|| async {
-                Config {
-                    binance_ticker: "bnbusdt".to_string(),
-                    pyth_price_id: "4CkQJBxhU8EZ2UjhigbtdaPbpTe6mqf811fipYBFbSYN".to_string(),
+    async fn test_new_configured_fees() {
+        let server = MockBinanceServer::start().await;
+        let config = Config::parse_from([
+            "keyrock-task",
+            "--pair",
+            &format!("solusdt={},solbnb={}", SOL_USD_PRICE_ID, SOL_USD_PRICE_ID),
+            "--binance-websocket-url",
+            &server.url(),
+            "--binance-vip-tier",
+            "1",
+            "--binance-bnb-discount",
+            "--binance-fee",
+            "solbnb=0:0.0002",
+            // SOLBNB is not quoted in USD
+            "--solana-base-fee-lamports",
+            "0",
+        ]);
+        let state = State::new(&config, Box::new(PriceAccountFixtures::new()))
+            .await
+            .unwrap();
+
+        let sol_usdt_fees = state.pair("solusdt").unwrap().binance_fees;
+        assert_eq!(sol_usdt_fees.maker, Decimal::from_str("0.000675").unwrap());
+        assert_eq!(sol_usdt_fees.taker, Decimal::from_str("0.00075").unwrap());
+        let sol_bnb_fees = state.pair("solbnb").unwrap().binance_fees;
+        assert_eq!(sol_bnb_fees.maker, Decimal::ZERO);
+        assert_eq!(sol_bnb_fees.taker, Decimal::from_str("0.0002").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_new_account_fees() {
+        let server = MockBinanceServer::start().await;
+        let rest_server = MockHttpServer::start().await;
+        rest_server.json(
+            "/api/v3/account/commission",
+            json!({
+                "symbol": "SOLUSDT",
+                "standardCommission": { "maker": "0.0002", "taker": "0.0004", "buyer": "0", "seller": "0" },
+                "taxCommission": { "maker": "0", "taker": "0", "buyer": "0", "seller": "0" },
+                "discount": {
+                    "enabledForAccount": true,
+                    "enabledForSymbol": true,
+                    "discountAsset": "BNB",
+                    "discount": "0.75"
                 }
-            })
+            }),
+        );
+        let args = |rest_url: &str| {
+            Config::parse_from([
+                "keyrock-task",
+                "--binance-websocket-url",
+                &server.url(),
+                "--binance-rest-url",
+                rest_url,
+                "--binance-api-key",
+                "key",
+                "--binance-api-secret",
+                "secret",
+            ])
+        };
+
+        let state = State::new(
+            &args(&rest_server.url()),
+            Box::new(PriceAccountFixtures::new()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            state.pair("solusdt").unwrap().binance_fees.taker,
+            Decimal::from_str("0.0004").unwrap()
+        );
+        assert_eq!(rest_server.requests()[0].headers["x-mbx-apikey"], "key");
+
+        // Falls back to the fee schedule
+        let state = State::new(
+            &args("http://127.0.0.1:1"),
+            Box::new(PriceAccountFixtures::new()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            state.pair("solusdt").unwrap().binance_fees.taker,
+            Decimal::new(1, 3)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_new_multiple_pairs() {
+        let server = MockBinanceServer::start().await;
+        let config = Config::parse_from([
+            "keyrock-task",
+            "--pair",
+            &format!("solusdt={}", SOL_USD_PRICE_ID),
+            "--pair",
+            &format!("SOLUSDC={},bnbusdt={}", SOL_USD_PRICE_ID, BNB_USD_PRICE_ID),
+            "--binance-websocket-url",
+            &server.url(),
+        ]);
+        let mut fixtures = PriceAccountFixtures::new();
+        fixtures.insert(
+            Pubkey::from_str(SOL_USD_PRICE_ID).unwrap(),
+            PriceAccountFixture::default(),
+        );
+        let state = State::new(&config, Box::new(fixtures)).await.unwrap();
+
+        // Streams of all the pairs are subscribed with a single request
+        assert_eq!(server.requests().len(), 1);
+        assert_eq!(
+            server.active_subscriptions(),
+            [
+                "solusdt@depth20@100ms",
+                "solusdc@depth20@100ms",
+                "bnbusdt@depth20@100ms"
+            ]
+            .map(str::to_string)
+            .into()
+        );
+        assert_eq!(
+            state
+                .pairs()
+                .map(|pair| pair.binance_ticker.as_str())
+                .collect::<Vec<_>>(),
+            vec!["bnbusdt", "solusdc", "solusdt"]
+        );
+
+        // Pairs sharing a feed observe the same price
+        assert_eq!(state.pyth_price_ids().len(), 2);
+        let sol_usdt_price = state.pair("solusdt").unwrap().subscribe_latest_pyth_price();
+        let sol_usdc_price = state.pair("solusdc").unwrap().subscribe_latest_pyth_price();
+        let bnb_usdt_price = state.pair("bnbusdt").unwrap().subscribe_latest_pyth_price();
+        state
+            .update_latest_pyth_price(&Pubkey::from_str(SOL_USD_PRICE_ID).unwrap())
             .await;
-        let state = State::new().await;
-        assert_eq!(state.binance_taker_fee, Decimal::new(75, 5));
+        assert!(sol_usdt_price.borrow().is_some());
+        assert!(sol_usdc_price.borrow().is_some());
+        assert!(bnb_usdt_price.borrow().is_none());
     }
 
     #[tokio::test]
-    #[ignore = "Uses global static, hence has to be ran manually"]
-    async fn test_new_not_bnb_pair() {
-        CONFIG
-            .get_or_init(|| async {
-                Config {
-                    binance_ticker: "solusdt".to_string(),
-                    pyth_price_id: "H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG".to_string(),
-                }
-            })
+    async fn test_new_duplicated_pair() {
+        let server = MockBinanceServer::start().await;
+        let config = Config::parse_from([
+            "keyrock-task",
+            "--pair",
+            &format!("solusdt={},solusdt={}", SOL_USD_PRICE_ID, BNB_USD_PRICE_ID),
+            "--binance-websocket-url",
+            &server.url(),
+        ]);
+        let result = State::new(&config, Box::new(PriceAccountFixtures::new())).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_new_pair_not_quoted_in_usd() {
+        let server = MockBinanceServer::start().await;
+        let config = Config::parse_from([
+            "keyrock-task",
+            "--pair",
+            &format!("solusdc={},solbnb={}", SOL_USD_PRICE_ID, SOL_USD_PRICE_ID),
+            "--binance-websocket-url",
+            &server.url(),
+        ]);
+        let result = State::new(&config, Box::new(PriceAccountFixtures::new())).await;
+        assert!(result.is_err_and(|err| err.to_string().contains("solbnb is not quoted in USD")));
+    }
+
+    #[tokio::test]
+    async fn test_new_invalid_pyth_price_id() {
+        let server = MockBinanceServer::start().await;
+        let config = Config::parse_from([
+            "keyrock-task",
+            "--pyth-price-id",
+            "not-a-pubkey",
+            "--binance-websocket-url",
+            &server.url(),
+        ]);
+        let result = State::new(&config, Box::new(PriceAccountFixtures::new())).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_update_latest_pyth_price_notifies_only_on_change() {
+        let server = MockBinanceServer::start().await;
+        let mut fixtures = PriceAccountFixtures::new();
+        fixtures.insert(
+            Pubkey::from_str(SOL_USD_PRICE_ID).unwrap(),
+            PriceAccountFixture::default(),
+        );
+        let state = new_state(&server, fixtures).await;
+        let price_id = Pubkey::from_str(SOL_USD_PRICE_ID).unwrap();
+        let mut receiver = state.pair("solusdt").unwrap().subscribe_latest_pyth_price();
+
+        state.update_latest_pyth_price(&price_id).await;
+        assert!(receiver.has_changed().unwrap());
+        assert_eq!(receiver.borrow_and_update().unwrap().price, 10000000000);
+
+        state.update_latest_pyth_price(&price_id).await;
+        assert!(!receiver.has_changed().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_stream_latest_pyth_price_expires_stalled_price() {
+        let server = MockBinanceServer::start().await;
+        let pubsub_server = MockPubsubServer::start().await;
+        let state = new_state(&server, PriceAccountFixtures::new()).await;
+        let price_id = Pubkey::from_str(SOL_USD_PRICE_ID).unwrap();
+        let mut receiver = state.pair("solusdt").unwrap().subscribe_latest_pyth_price();
+        let pyth_stream = PythStream::new(&pubsub_server.url());
+
+        let stalled = async {
+            eventually(|| pubsub_server.active_subscriptions() == vec![price_id.to_string()]).await;
+            // Accepted when pushed, but nothing replaces it before it gets too old
+            let price = PriceAccountFixture {
+                timestamp: now() - 59,
+                ..Default::default()
+            };
+            pubsub_server.notify_account(&price_id, price.to_account_data());
+            receiver.wait_for(Option::is_some).await.unwrap();
+            tokio::time::timeout(Duration::from_secs(5), receiver.wait_for(Option::is_none))
+                .await
+                .unwrap()
+                .unwrap();
+        };
+        tokio::select! {
+            result = state.stream_latest_pyth_price(&price_id, &pyth_stream) => {
+                panic!("subscription ended: {:?}", result)
+            }
+            _ = stalled => {}
+        }
+    }
+
+    #[tokio::test]
+    async fn test_update_latest_binance_order_book_notifies_only_on_change() {
+        let server = MockBinanceServer::start().await;
+        let state = new_state(&server, PriceAccountFixtures::new()).await;
+        let mut receiver = state
+            .pair("solusdt")
+            .unwrap()
+            .subscribe_latest_binance_order_book();
+        let order_book = PartialDepthData {
+            last_update_id: 160,
+            ..Default::default()
+        };
+
+        server.push_partial_depth("solusdt", &order_book);
+        state.update_latest_binance_order_book().await;
+        assert!(receiver.has_changed().unwrap());
+        assert_eq!(
+            receiver.borrow_and_update().as_deref(),
+            Some(&OrderBook::from(&order_book))
+        );
+
+        server.push_partial_depth("solusdt", &order_book);
+        state.update_latest_binance_order_book().await;
+        assert!(!receiver.has_changed().unwrap());
+
+        server.disconnect();
+        state.update_latest_binance_order_book().await;
+        assert!(receiver.has_changed().unwrap());
+        assert_eq!(*receiver.borrow_and_update(), None);
+    }
+
+    #[tokio::test]
+    async fn test_update_latest_binance_order_book_routes_by_pair() {
+        let server = MockBinanceServer::start().await;
+        let config = Config::parse_from([
+            "keyrock-task",
+            "--pair",
+            &format!("solusdt={},bnbusdt={}", SOL_USD_PRICE_ID, BNB_USD_PRICE_ID),
+            "--binance-websocket-url",
+            &server.url(),
+        ]);
+        let state = State::new(&config, Box::new(PriceAccountFixtures::new()))
+            .await
+            .unwrap();
+        let sol_order_book = state
+            .pair("solusdt")
+            .unwrap()
+            .subscribe_latest_binance_order_book();
+        let bnb_order_book = state
+            .pair("bnbusdt")
+            .unwrap()
+            .subscribe_latest_binance_order_book();
+
+        server.push_partial_depth(
+            "bnbusdt",
+            &PartialDepthData {
+                last_update_id: 160,
+                ..Default::default()
+            },
+        );
+        state.update_latest_binance_order_book().await;
+        assert!(!sol_order_book.has_changed().unwrap());
+        assert!(bnb_order_book.has_changed().unwrap());
+        assert_eq!(
+            bnb_order_book.borrow().as_ref().unwrap().last_update_id(),
+            160
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_latest_binance_order_book_full() {
+        let server = MockBinanceServer::start().await;
+        let rest_server = MockHttpServer::start().await;
+        rest_server.json(
+            "/api/v3/depth",
+            json!({ "lastUpdateId": 100, "bids": [["71.38", "1"]], "asks": [["71.40", "1"]] }),
+        );
+        let config = Config::parse_from([
+            "keyrock-task",
+            "--pyth-price-id",
+            SOL_USD_PRICE_ID,
+            "--binance-websocket-url",
+            &server.url(),
+            "--binance-order-book",
+            "full",
+            "--binance-rest-url",
+            &rest_server.url(),
+        ]);
+        let state = State::new(&config, Box::new(PriceAccountFixtures::new()))
+            .await
+            .unwrap();
+        let mut receiver = state
+            .pair("solusdt")
+            .unwrap()
+            .subscribe_latest_binance_order_book();
+        assert!(server
+            .active_subscriptions()
+            .contains("solusdt@depth@100ms"));
+
+        server.push_depth_update(
+            "solusdt",
+            &DepthUpdateData {
+                first_update_id: 99,
+                final_update_id: 101,
+                bids: vec![PriceLevel {
+                    price: Decimal::new(7139, 2),
+                    quantity: Decimal::ONE,
+                }],
+                ..Default::default()
+            },
+        );
+        state.update_latest_binance_order_book().await;
+        assert!(!receiver.has_changed().unwrap());
+        // The snapshot is fetched outside of the reader
+        assert!(rest_server.requests().is_empty());
+        state
+            .pair("solusdt")
+            .unwrap()
+            .resync_binance_order_book()
             .await;
-        let state = State::new().await;
-        assert_eq!(state.binance_taker_fee, Decimal::new(1, 3));
+        assert!(receiver.has_changed().unwrap());
+        {
+            let order_book = receiver.borrow_and_update();
+            let order_book = order_book.as_ref().unwrap();
+            assert_eq!(order_book.last_update_id(), 101);
+            assert_eq!(order_book.bids().count(), 2);
+        }
+
+        // Events are applied in place, unless a reader still holds the published book
+        let published = Arc::as_ptr(receiver.borrow().as_ref().unwrap());
+        let depth_update = |first_update_id, final_update_id| DepthUpdateData {
+            first_update_id,
+            final_update_id,
+            ..Default::default()
+        };
+        server.push_depth_update("solusdt", &depth_update(102, 103));
+        state.update_latest_binance_order_book().await;
+        assert!(receiver.has_changed().unwrap());
+        let held = receiver.borrow_and_update().clone().unwrap();
+        assert_eq!(Arc::as_ptr(&held), published);
+        assert_eq!(held.last_update_id(), 103);
+        server.push_depth_update("solusdt", &depth_update(104, 105));
+        state.update_latest_binance_order_book().await;
+        assert_eq!(held.last_update_id(), 103);
+        assert_eq!(
+            receiver
+                .borrow_and_update()
+                .as_ref()
+                .unwrap()
+                .last_update_id(),
+            105
+        );
+
+        server.disconnect();
+        state.update_latest_binance_order_book().await;
+        assert_eq!(*receiver.borrow_and_update(), None);
+
+        state.terminate().await.unwrap();
+        eventually(|| server.active_subscriptions().is_empty()).await;
+    }
+
+    #[tokio::test]
+    async fn test_update_latest_dex_quoter_notifies_only_on_change() {
+        let server = MockBinanceServer::start().await;
+        let rpc_server = MockHttpServer::start().await;
+        let pool_address = Pubkey::new_unique();
+        let pool = sol_usdc_amm_v4();
+        let mut accounts = amm_v4_accounts(&pool_address, &pool);
+        rpc_server.solana_accounts("/", accounts.clone());
+        let config = Config::parse_from([
+            "keyrock-task",
+            "--pyth-price-id",
+            SOL_USD_PRICE_ID,
+            "--binance-websocket-url",
+            &server.url(),
+            "--raydium-pool",
+            &format!("solusdt={}", pool_address),
+            "--solana-rpc-url",
+            &rpc_server.url(),
+        ]);
+        let state = State::new(&config, Box::new(PriceAccountFixtures::new()))
+            .await
+            .unwrap();
+        let pair = state.pair("solusdt").unwrap();
+        let mut receiver = pair.subscribe_latest_dex_quoter();
+
+        // Polling the unchanged pool wakes the receivers only once
+        let mut wakes = 0;
+        for _ in 0..10 {
+            pair.update_latest_dex_quoter().await;
+            if receiver.has_changed().unwrap() {
+                wakes += 1;
+                receiver.borrow_and_update();
+            }
+        }
+        assert_eq!(wakes, 1);
+
+        accounts.insert(pool.quote_vault, token_account_data(71_000_000_000));
+        rpc_server.solana_accounts("/", accounts);
+        pair.update_latest_dex_quoter().await;
+        assert!(receiver.has_changed().unwrap());
+        receiver.borrow_and_update();
+        pair.update_latest_dex_quoter().await;
+        assert!(!receiver.has_changed().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_update_latest_pyth_price_retries() {
+        let server = MockBinanceServer::start().await;
+        let price_source = flaky_price_source(2);
+        let state = new_state(&server, price_source.clone()).await;
+        let price_id = Pubkey::from_str(SOL_USD_PRICE_ID).unwrap();
+        let receiver = state.pair("solusdt").unwrap().subscribe_latest_pyth_price();
+
+        state.update_latest_pyth_price(&price_id).await;
+        assert_eq!(price_source.calls.load(Ordering::SeqCst), 3);
+        assert!(receiver.borrow().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_update_latest_pyth_price_retries_exhausted() {
+        let server = MockBinanceServer::start().await;
+        let price_source = flaky_price_source(4);
+        let state = new_state(&server, price_source.clone()).await;
+        let price_id = Pubkey::from_str(SOL_USD_PRICE_ID).unwrap();
+        let receiver = state.pair("solusdt").unwrap().subscribe_latest_pyth_price();
+        state.latest_pyth_prices[&price_id].send_replace(Some(Price::default()));
+
+        state.update_latest_pyth_price(&price_id).await;
+        assert_eq!(price_source.calls.load(Ordering::SeqCst), 3);
+        assert!(receiver.borrow().is_none());
+
+        state.update_latest_pyth_price(&price_id).await;
+        assert!(receiver.borrow().is_some());
     }
 }
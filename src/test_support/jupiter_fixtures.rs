@@ -0,0 +1,75 @@
+use serde_json::{json, Value};
+
+use super::solana_fixtures::{SOL_MINT, USDC_MINT};
+
+/*
+    Quote of 700 USDC buying 9.96 SOL with a 0.3% fee in USDC and 0.1% price impact
+*/
+pub fn sol_usdc_buy_quote() -> Value {
+    json!({
+        "inputMint": USDC_MINT,
+        "inAmount": "700000000",
+        "outputMint": SOL_MINT,
+        "outAmount": "9960000000",
+        "otherAmountThreshold": "9910200000",
+        "swapMode": "ExactIn",
+        "slippageBps": 50,
+        "priceImpactPct": "0.001",
+        "routePlan": [{
+            "swapInfo": {
+                "ammKey": "HJPjoWUrhoZzkNfRpHuieeFk9WcZWjwy6PBjZ81ngndJ",
+                "label": "Whirlpool",
+                "inputMint": USDC_MINT,
+                "outputMint": SOL_MINT,
+                "inAmount": "700000000",
+                "outAmount": "9960000000",
+                "feeAmount": "2100000",
+                "feeMint": USDC_MINT
+            },
+            "percent": 100
+        }],
+        "contextSlot": 1,
+        "timeTaken": 0.01
+    })
+}
+
+/*
+    Quote of 9.96 SOL selling for 694 USDC split over two pools with 0.25% and 0.3% fees in SOL
+*/
+pub fn sol_usdc_sell_quote() -> Value {
+    json!({
+        "inputMint": SOL_MINT,
+        "inAmount": "9960000000",
+        "outputMint": USDC_MINT,
+        "outAmount": "694000000",
+        "priceImpactPct": "0.0005",
+        "routePlan": [
+            {
+                "swapInfo": {
+                    "ammKey": "58oQChx4yWmvKdwLLZzBi4ChoCc2fqCUWBkwMihLYQo2",
+                    "label": "Raydium",
+                    "inputMint": SOL_MINT,
+                    "outputMint": USDC_MINT,
+                    "inAmount": "4980000000",
+                    "outAmount": "347000000",
+                    "feeAmount": "12450000",
+                    "feeMint": SOL_MINT
+                },
+                "percent": 50
+            },
+            {
+                "swapInfo": {
+                    "ammKey": "HJPjoWUrhoZzkNfRpHuieeFk9WcZWjwy6PBjZ81ngndJ",
+                    "label": "Whirlpool",
+                    "inputMint": SOL_MINT,
+                    "outputMint": USDC_MINT,
+                    "inAmount": "4980000000",
+                    "outAmount": "347000000",
+                    "feeAmount": "14940000",
+                    "feeMint": SOL_MINT
+                },
+                "percent": 50
+            }
+        ]
+    })
+}
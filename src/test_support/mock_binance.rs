@@ -0,0 +1,295 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+    task::JoinHandle,
+};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::structs::cex::binance::{BookTickerData, DepthUpdateData, PartialDepthData};
+
+/*
+    In-process WS server speaking Binance's combined stream protocol.
+    Handles SUBSCRIBE/UNSUBSCRIBE/LIST_SUBSCRIPTIONS requests and lets tests push stream frames,
+    send pings, reject subscriptions and force disconnects.
+*/
+pub struct MockBinanceServer {
+    address: SocketAddr,
+    state: Arc<Mutex<MockBinanceState>>,
+    commands: broadcast::Sender<ServerCommand>,
+    task: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct MockBinanceState {
+    next_connection_id: usize,
+    total_connections: usize,
+    subscriptions: HashMap<usize, HashSet<String>>,
+    requests: Vec<Value>,
+    pongs: Vec<Vec<u8>>,
+    reject_subscriptions: bool,
+}
+
+#[derive(Clone, Debug)]
+enum ServerCommand {
+    Frame { stream: String, payload: String },
+    Ping(Vec<u8>),
+    Disconnect,
+    Close,
+}
+
+impl MockBinanceServer {
+    /*
+        Binds to a random local port and starts accepting connections
+    */
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(MockBinanceState::default()));
+        let (commands, _) = broadcast::channel(1024);
+
+        let task = tokio::spawn({
+            let state = state.clone();
+            let commands = commands.clone();
+
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(handle_connection(
+                        stream,
+                        state.clone(),
+                        commands.subscribe(),
+                    ));
+                }
+            }
+        });
+
+        Self {
+            address,
+            state,
+            commands,
+            task,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("ws://{}/stream", self.address)
+    }
+
+    /*
+        Sends a bookTicker frame to every connection subscribed to the symbol
+    */
+    pub fn push_book_ticker(&self, data: &BookTickerData) {
+        let stream = BookTickerData::stream(&data.s.to_lowercase());
+        let payload = json!({ "stream": stream, "data": data }).to_string();
+        self.push_frame(&stream, payload);
+    }
+
+    /*
+        Sends a depth20 frame to every connection subscribed to the ticker's partial order book
+    */
+    pub fn push_partial_depth(&self, ticker: &str, data: &PartialDepthData) {
+        let stream = PartialDepthData::stream(ticker);
+        let payload = json!({ "stream": stream, "data": data }).to_string();
+        self.push_frame(&stream, payload);
+    }
+
+    /*
+        Sends a diff depth frame to every connection subscribed to the ticker's order book updates
+    */
+    pub fn push_depth_update(&self, ticker: &str, data: &DepthUpdateData) {
+        let stream = DepthUpdateData::stream(ticker);
+        let payload = json!({ "stream": stream, "data": data }).to_string();
+        self.push_frame(&stream, payload);
+    }
+
+    /*
+        Sends a raw frame to every connection subscribed to the stream
+    */
+    pub fn push_frame(&self, stream: &str, payload: String) {
+        let _ = self.commands.send(ServerCommand::Frame {
+            stream: stream.to_string(),
+            payload,
+        });
+    }
+
+    pub fn ping(&self, payload: &[u8]) {
+        let _ = self.commands.send(ServerCommand::Ping(payload.to_vec()));
+    }
+
+    /*
+        Drops every connection without a close frame, like a network failure would
+    */
+    pub fn disconnect(&self) {
+        let _ = self.commands.send(ServerCommand::Disconnect);
+    }
+
+    /*
+        Sends a close frame to every connection, like the server-side 24h disconnect does
+    */
+    pub fn close(&self) {
+        let _ = self.commands.send(ServerCommand::Close);
+    }
+
+    pub fn reject_subscriptions(&self, reject: bool) {
+        self.state.lock().unwrap().reject_subscriptions = reject;
+    }
+
+    /*
+        Returns number of connections accepted since the server started
+    */
+    pub fn total_connections(&self) -> usize {
+        self.state.lock().unwrap().total_connections
+    }
+
+    /*
+        Returns streams subscribed by currently open connections
+    */
+    pub fn active_subscriptions(&self) -> HashSet<String> {
+        self.state
+            .lock()
+            .unwrap()
+            .subscriptions
+            .values()
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    pub fn requests(&self) -> Vec<Value> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub fn pongs(&self) -> Vec<Vec<u8>> {
+        self.state.lock().unwrap().pongs.clone()
+    }
+}
+
+impl Drop for MockBinanceServer {
+    fn drop(&mut self) {
+        self.task.abort();
+        let _ = self.commands.send(ServerCommand::Disconnect);
+    }
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    state: Arc<Mutex<MockBinanceState>>,
+    mut commands: broadcast::Receiver<ServerCommand>,
+) {
+    let Ok(mut socket) = tokio_tungstenite::accept_async(stream).await else {
+        return;
+    };
+
+    let connection_id = {
+        let mut state = state.lock().unwrap();
+        let connection_id = state.next_connection_id;
+        state.next_connection_id += 1;
+        state.total_connections += 1;
+        state.subscriptions.insert(connection_id, HashSet::new());
+        connection_id
+    };
+
+    loop {
+        tokio::select! {
+            message = socket.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    let response = handle_request(&text, connection_id, &state);
+                    if socket.send(Message::Text(response)).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(Message::Pong(payload))) => state.lock().unwrap().pongs.push(payload),
+                Some(Ok(Message::Close(_))) => {
+                    let _ = socket.close(None).await;
+                    break;
+                }
+                Some(Ok(_)) => {}
+                Some(Err(_)) | None => break,
+            },
+            command = commands.recv() => match command {
+                Ok(ServerCommand::Frame { stream, payload }) => {
+                    let subscribed = state.lock().unwrap().subscriptions[&connection_id].contains(&stream);
+                    if subscribed && socket.send(Message::Text(payload)).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(ServerCommand::Ping(payload)) => {
+                    if socket.send(Message::Ping(payload)).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(ServerCommand::Close) => {
+                    let _ = socket.close(None).await;
+                    break;
+                }
+                Ok(ServerCommand::Disconnect) | Err(broadcast::error::RecvError::Closed) => break,
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+            },
+        }
+    }
+
+    state.lock().unwrap().subscriptions.remove(&connection_id);
+}
+
+/*
+    Applies a request to the connection's subscriptions and builds the response
+*/
+fn handle_request(text: &str, connection_id: usize, state: &Mutex<MockBinanceState>) -> String {
+    let Ok(request) = serde_json::from_str::<Value>(text) else {
+        return json!({ "error": { "code": 3, "msg": "Invalid JSON" }, "id": null }).to_string();
+    };
+
+    let mut state = state.lock().unwrap();
+    state.requests.push(request.clone());
+
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let streams = request
+        .get("params")
+        .and_then(Value::as_array)
+        .map(|params| {
+            params
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    match request.get("method").and_then(Value::as_str) {
+        Some("SUBSCRIBE") if state.reject_subscriptions => json!({
+            "error": { "code": 2, "msg": "Invalid request: unknown stream" },
+            "id": id,
+        }),
+        Some("SUBSCRIBE") => {
+            let subscriptions = state.subscriptions.get_mut(&connection_id).unwrap();
+            subscriptions.extend(streams);
+            json!({ "result": null, "id": id })
+        }
+        Some("UNSUBSCRIBE") => {
+            let subscriptions = state.subscriptions.get_mut(&connection_id).unwrap();
+            streams.iter().for_each(|stream| {
+                subscriptions.remove(stream);
+            });
+            json!({ "result": null, "id": id })
+        }
+        Some("LIST_SUBSCRIPTIONS") => {
+            let subscriptions = state.subscriptions[&connection_id]
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>();
+            json!({ "result": subscriptions, "id": id })
+        }
+        _ => json!({
+            "error": { "code": 2, "msg": "Invalid request: unknown method" },
+            "id": id,
+        }),
+    }
+    .to_string()
+}
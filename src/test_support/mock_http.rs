@@ -0,0 +1,289 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use serde_json::{json, Value};
+use solana_account_decoder::{UiAccount, UiAccountEncoding};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::account::Account;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+/*
+    Minimal in-process HTTP/1.1 server serving canned responses by path.
+    Every connection handles a single request and is closed afterwards.
+*/
+pub struct MockHttpServer {
+    address: SocketAddr,
+    state: Arc<Mutex<MockHttpState>>,
+    task: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct MockHttpState {
+    routes: HashMap<String, MockResponse>,
+    requests: Vec<MockRequest>,
+}
+
+#[derive(Clone, Debug)]
+pub enum MockResponse {
+    Json { status: u16, body: Value },
+    // Server-sent events, written one by one before closing the connection
+    EventStream(Vec<String>),
+    // Server-sent events written one by one, then the connection is held open without writing anything more
+    StalledEventStream(Vec<String>),
+    // Solana JSON-RPC serving getAccountInfo and getMultipleAccounts from the account data by pubkey, and getVersion
+    SolanaRpc(HashMap<Pubkey, Vec<u8>>),
+    // JSON of the first entry whose fragment is part of the query, 404 if there is none
+    JsonByQuery(Vec<(String, Value)>),
+}
+
+#[derive(Clone, Debug)]
+pub struct MockRequest {
+    pub method: String,
+    pub path: String,
+    pub query: String,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+impl MockHttpServer {
+    /*
+        Binds to a random local port and starts accepting connections
+    */
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(MockHttpState::default()));
+
+        let task = tokio::spawn({
+            let state = state.clone();
+
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(handle_connection(stream, state.clone()));
+                }
+            }
+        });
+
+        Self {
+            address,
+            state,
+            task,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    /*
+        Serves the response for every request to the path, regardless of the method and query
+    */
+    pub fn route(&self, path: &str, response: MockResponse) {
+        self.state
+            .lock()
+            .unwrap()
+            .routes
+            .insert(path.to_string(), response);
+    }
+
+    pub fn json(&self, path: &str, body: Value) {
+        self.route(path, MockResponse::Json { status: 200, body });
+    }
+
+    /*
+        Serves Solana JSON-RPC requests for the accounts, missing accounts are returned as null
+    */
+    pub fn solana_accounts(&self, path: &str, accounts: HashMap<Pubkey, Vec<u8>>) {
+        self.route(path, MockResponse::SolanaRpc(accounts));
+    }
+
+    /*
+        Serves a different JSON body depending on the query, e.g. by the value of a parameter
+    */
+    pub fn json_by_query(&self, path: &str, bodies: Vec<(&str, Value)>) {
+        let bodies = bodies
+            .into_iter()
+            .map(|(fragment, body)| (fragment.to_string(), body))
+            .collect();
+        self.route(path, MockResponse::JsonByQuery(bodies));
+    }
+
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+impl Drop for MockHttpServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn handle_connection(stream: TcpStream, state: Arc<Mutex<MockHttpState>>) {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).await.is_err() {
+        return;
+    }
+    let mut request_line = request_line.split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or_default();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await.unwrap_or(0) == 0 {
+            return;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let content_length = headers
+        .get("content-length")
+        .and_then(|length| length.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; content_length];
+    if reader.read_exact(&mut body).await.is_err() {
+        return;
+    }
+
+    let body = String::from_utf8_lossy(&body).to_string();
+    let response = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(MockRequest {
+            method,
+            path: path.to_string(),
+            query: query.to_string(),
+            headers,
+            body: body.clone(),
+        });
+        state.routes.get(path).cloned()
+    };
+
+    let response = match response {
+        Some(MockResponse::SolanaRpc(accounts)) => Some(MockResponse::Json {
+            status: 200,
+            body: solana_rpc_response(&accounts, &body),
+        }),
+        Some(MockResponse::JsonByQuery(bodies)) => bodies
+            .into_iter()
+            .find(|(fragment, _)| query.contains(fragment.as_str()))
+            .map(|(_, body)| MockResponse::Json { status: 200, body }),
+        response => response,
+    };
+
+    let mut stream = reader.into_inner();
+    match response {
+        Some(MockResponse::Json { status, body }) => {
+            let body = body.to_string();
+            let response = format!(
+                "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+        Some(MockResponse::EventStream(events)) => {
+            write_event_stream(&mut stream, events).await;
+        }
+        Some(MockResponse::StalledEventStream(events)) => {
+            if write_event_stream(&mut stream, events).await {
+                std::future::pending::<()>().await;
+            }
+        }
+        Some(MockResponse::SolanaRpc(_) | MockResponse::JsonByQuery(_)) => {
+            unreachable!("Served as JSON above")
+        }
+        None => {
+            let response =
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+    }
+    let _ = stream.shutdown().await;
+}
+
+/*
+    Writes the head and the events of a text/event-stream response, returns whether all of them were written
+*/
+async fn write_event_stream(stream: &mut TcpStream, events: Vec<String>) -> bool {
+    let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n";
+    if stream.write_all(head.as_bytes()).await.is_err() {
+        return false;
+    }
+    for event in events {
+        if stream.write_all(event.as_bytes()).await.is_err() {
+            return false;
+        }
+        let _ = stream.flush().await;
+    }
+    true
+}
+
+fn solana_rpc_response(accounts: &HashMap<Pubkey, Vec<u8>>, body: &str) -> Value {
+    let request: Value = serde_json::from_str(body).unwrap_or_default();
+    let encode = |pubkey: &Value| {
+        let pubkey = pubkey.as_str()?.parse::<Pubkey>().ok()?;
+        let data = accounts.get(&pubkey)?;
+        let account = Account {
+            lamports: 1_000_000_000,
+            data: data.clone(),
+            owner: Pubkey::default(),
+            executable: false,
+            rent_epoch: 0,
+        };
+        Some(UiAccount::encode(
+            &pubkey,
+            &account,
+            UiAccountEncoding::Base64,
+            None,
+            None,
+        ))
+    };
+
+    let params = &request["params"];
+    let value = match request["method"].as_str() {
+        Some("getAccountInfo") => json!(encode(&params[0])),
+        Some("getMultipleAccounts") => json!(params[0]
+            .as_array()
+            .map(|pubkeys| pubkeys.iter().map(encode).collect::<Vec<_>>())
+            .unwrap_or_default()),
+        // Queried by the client before the first getAccountInfo
+        Some("getVersion") => {
+            return json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "result": { "solana-core": "1.17.13", "feature-set": 0 },
+            })
+        }
+        _ => {
+            return json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "error": { "code": -32601, "message": "Method not found" },
+            })
+        }
+    };
+
+    json!({
+        "jsonrpc": "2.0",
+        "id": request["id"],
+        "result": { "context": { "slot": 1 }, "value": value },
+    })
+}
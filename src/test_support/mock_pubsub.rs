@@ -0,0 +1,236 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use solana_account_decoder::{UiAccount, UiAccountEncoding};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::account::Account;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+    task::JoinHandle,
+};
+use tokio_tungstenite::tungstenite::Message;
+
+/*
+    In-process WS server speaking the subset of Solana PubSub JSON-RPC used for accountSubscribe.
+    Lets tests push account notifications and force disconnects.
+*/
+pub struct MockPubsubServer {
+    address: SocketAddr,
+    state: Arc<Mutex<MockPubsubState>>,
+    commands: broadcast::Sender<ServerCommand>,
+    task: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct MockPubsubState {
+    next_subscription_id: u64,
+    total_connections: usize,
+    // subscription id -> subscribed account
+    subscriptions: HashMap<u64, String>,
+}
+
+#[derive(Clone, Debug)]
+enum ServerCommand {
+    Notify { pubkey: String, result: Value },
+    Disconnect,
+}
+
+impl MockPubsubServer {
+    /*
+        Binds to a random local port and starts accepting connections
+    */
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(MockPubsubState::default()));
+        let (commands, _) = broadcast::channel(1024);
+
+        let task = tokio::spawn({
+            let state = state.clone();
+            let commands = commands.clone();
+
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(handle_connection(
+                        stream,
+                        state.clone(),
+                        commands.subscribe(),
+                    ));
+                }
+            }
+        });
+
+        Self {
+            address,
+            state,
+            commands,
+            task,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("ws://{}", self.address)
+    }
+
+    /*
+        Sends an accountNotification with the provided data to every subscriber of the account
+    */
+    pub fn notify_account(&self, pubkey: &Pubkey, data: Vec<u8>) {
+        let account = Account {
+            lamports: 1_000_000,
+            data,
+            owner: Pubkey::new_unique(),
+            executable: false,
+            rent_epoch: 0,
+        };
+        let value = UiAccount::encode(pubkey, &account, UiAccountEncoding::Base64, None, None);
+        let _ = self.commands.send(ServerCommand::Notify {
+            pubkey: pubkey.to_string(),
+            result: json!({ "context": { "slot": 1 }, "value": value }),
+        });
+    }
+
+    /*
+        Drops every connection without a close frame, like a network failure would
+    */
+    pub fn disconnect(&self) {
+        let _ = self.commands.send(ServerCommand::Disconnect);
+    }
+
+    /*
+        Returns number of connections accepted since the server started
+    */
+    pub fn total_connections(&self) -> usize {
+        self.state.lock().unwrap().total_connections
+    }
+
+    /*
+        Returns accounts subscribed by currently open connections
+    */
+    pub fn active_subscriptions(&self) -> Vec<String> {
+        self.state
+            .lock()
+            .unwrap()
+            .subscriptions
+            .values()
+            .cloned()
+            .collect()
+    }
+}
+
+impl Drop for MockPubsubServer {
+    fn drop(&mut self) {
+        self.task.abort();
+        let _ = self.commands.send(ServerCommand::Disconnect);
+    }
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    state: Arc<Mutex<MockPubsubState>>,
+    mut commands: broadcast::Receiver<ServerCommand>,
+) {
+    let Ok(mut socket) = tokio_tungstenite::accept_async(stream).await else {
+        return;
+    };
+    state.lock().unwrap().total_connections += 1;
+    let mut connection_subscriptions = Vec::new();
+
+    loop {
+        tokio::select! {
+            message = socket.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    let response = handle_request(&text, &state, &mut connection_subscriptions);
+                    if socket.send(Message::Text(response)).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(Message::Close(_))) => {
+                    let _ = socket.close(None).await;
+                    break;
+                }
+                Some(Ok(_)) => {}
+                Some(Err(_)) | None => break,
+            },
+            command = commands.recv() => match command {
+                Ok(ServerCommand::Notify { pubkey, result }) => {
+                    let subscription_ids = {
+                        let state = state.lock().unwrap();
+                        connection_subscriptions
+                            .iter()
+                            .filter(|id| state.subscriptions.get(id) == Some(&pubkey))
+                            .copied()
+                            .collect::<Vec<_>>()
+                    };
+                    for subscription_id in subscription_ids {
+                        let notification = json!({
+                            "jsonrpc": "2.0",
+                            "method": "accountNotification",
+                            "params": { "result": result, "subscription": subscription_id },
+                        });
+                        if socket.send(Message::Text(notification.to_string())).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                Ok(ServerCommand::Disconnect) | Err(broadcast::error::RecvError::Closed) => break,
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+            },
+        }
+    }
+
+    let mut state = state.lock().unwrap();
+    connection_subscriptions.iter().for_each(|id| {
+        state.subscriptions.remove(id);
+    });
+}
+
+/*
+    Applies a JSON-RPC request to the subscriptions and builds the response
+*/
+fn handle_request(
+    text: &str,
+    state: &Mutex<MockPubsubState>,
+    connection_subscriptions: &mut Vec<u64>,
+) -> String {
+    let Ok(request) = serde_json::from_str::<Value>(text) else {
+        return json!({
+            "jsonrpc": "2.0",
+            "error": { "code": -32700, "message": "Parse error" },
+            "id": null,
+        })
+        .to_string();
+    };
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let first_param = request.get("params").and_then(|params| params.get(0));
+    let mut state = state.lock().unwrap();
+
+    match (request.get("method").and_then(Value::as_str), first_param) {
+        (Some("accountSubscribe"), Some(Value::String(pubkey))) => {
+            let subscription_id = state.next_subscription_id;
+            state.next_subscription_id += 1;
+            state.subscriptions.insert(subscription_id, pubkey.clone());
+            connection_subscriptions.push(subscription_id);
+            json!({ "jsonrpc": "2.0", "result": subscription_id, "id": id })
+        }
+        (Some("accountUnsubscribe"), Some(subscription_id)) => {
+            let removed = subscription_id
+                .as_u64()
+                .and_then(|subscription_id| state.subscriptions.remove(&subscription_id))
+                .is_some();
+            json!({ "jsonrpc": "2.0", "result": removed, "id": id })
+        }
+        _ => json!({
+            "jsonrpc": "2.0",
+            "error": { "code": -32601, "message": "Method not found" },
+            "id": id,
+        }),
+    }
+    .to_string()
+}
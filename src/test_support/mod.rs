@@ -0,0 +1,33 @@
+pub mod jupiter_fixtures;
+pub mod mock_binance;
+pub mod mock_http;
+pub mod mock_pubsub;
+pub mod openbook_fixtures;
+pub mod orca_fixtures;
+pub mod pyth_fixtures;
+pub mod raydium_fixtures;
+pub mod solana_fixtures;
+
+use std::{str::FromStr, time::Duration};
+
+use rust_decimal::Decimal;
+
+/*
+    Polls the condition until it holds, panicking if it does not within a second
+*/
+pub async fn eventually(mut condition: impl FnMut() -> bool) {
+    tokio::time::timeout(Duration::from_secs(1), async {
+        while !condition() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("Condition was not met in time");
+}
+
+/*
+    Parses the decimal literal
+*/
+pub fn decimal(value: &str) -> Decimal {
+    Decimal::from_str(value).unwrap()
+}
@@ -0,0 +1,128 @@
+use std::{collections::HashMap, str::FromStr};
+
+use solana_program::pubkey::Pubkey;
+
+use super::solana_fixtures::USDC_MINT;
+use crate::structs::on_chain::{
+    account::account_discriminator,
+    openbook::{
+        Market, BOOK_SIDE_LEN, BOOK_SIDE_NODES_OFFSET, INNER_NODE_TAG, LEAF_NODE_TAG, MARKET_LEN,
+        NODE_LEN,
+    },
+};
+
+/*
+    SOL/USDC market with lots of 0.001 SOL and 0.000001 USDC and the 0.04% taker fee
+*/
+pub fn sol_usdc_market() -> Market {
+    Market {
+        base_decimals: 9,
+        quote_decimals: 6,
+        bids: Pubkey::new_from_array([6; 32]),
+        asks: Pubkey::new_from_array([7; 32]),
+        quote_mint: Pubkey::from_str(USDC_MINT).unwrap(),
+        quote_lot_size: 1,
+        base_lot_size: 1_000_000,
+        taker_fee: 400,
+    }
+}
+
+/*
+    Resting order of a book side fixture, in lots
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct LeafFixture {
+    pub price_lots: i64,
+    pub quantity_lots: i64,
+    pub timestamp: u64,
+    pub time_in_force: u16,
+}
+
+impl LeafFixture {
+    pub fn new(price_lots: i64, quantity_lots: i64) -> Self {
+        Self {
+            price_lots,
+            quantity_lots,
+            timestamp: 0,
+            time_in_force: 0,
+        }
+    }
+
+    pub fn expiring(self, timestamp: u64, time_in_force: u16) -> Self {
+        Self {
+            timestamp,
+            time_in_force,
+            ..self
+        }
+    }
+}
+
+/*
+    Accounts of the market and its book sides by pubkey, as served over Solana JSON-RPC
+*/
+pub fn market_accounts(
+    address: &Pubkey,
+    market: &Market,
+    bids: &[LeafFixture],
+    asks: &[LeafFixture],
+) -> HashMap<Pubkey, Vec<u8>> {
+    HashMap::from([
+        (*address, market_account_data(market)),
+        (market.bids, book_side_account_data(bids)),
+        (market.asks, book_side_account_data(asks)),
+    ])
+}
+
+/*
+    Serializes the market into bytes of a Market account, fields not needed for quoting are zeroed
+*/
+pub fn market_account_data(market: &Market) -> Vec<u8> {
+    let mut data = vec![0; MARKET_LEN];
+    data[..8].copy_from_slice(&account_discriminator("Market"));
+    data[9] = market.base_decimals;
+    data[10] = market.quote_decimals;
+    data[200..232].copy_from_slice(market.bids.as_ref());
+    data[232..264].copy_from_slice(market.asks.as_ref());
+    data[608..640].copy_from_slice(market.quote_mint.as_ref());
+    data[448..456].copy_from_slice(&market.quote_lot_size.to_le_bytes());
+    data[456..464].copy_from_slice(&market.base_lot_size.to_le_bytes());
+    data[488..496].copy_from_slice(&market.taker_fee.to_le_bytes());
+    data
+}
+
+/*
+    Serializes a BookSide with the orders in its fixed price tree. The tree is a chain of inner nodes,
+    each holding one leaf, which is enough for readers walking the whole tree.
+*/
+pub fn book_side_account_data(orders: &[LeafFixture]) -> Vec<u8> {
+    let mut data = vec![0; BOOK_SIDE_LEN];
+    data[..8].copy_from_slice(&account_discriminator("BookSide"));
+    let leaf_count = orders.len() as u32;
+    // Leaves take the first nodes, inner nodes the following ones
+    let root = if leaf_count > 1 { leaf_count } else { 0 };
+    data[8..12].copy_from_slice(&root.to_le_bytes());
+    data[12..16].copy_from_slice(&leaf_count.to_le_bytes());
+
+    let offset = |index: u32| BOOK_SIDE_NODES_OFFSET + index as usize * NODE_LEN;
+    for (index, order) in orders.iter().enumerate() {
+        let leaf = &mut data[offset(index as u32)..][..NODE_LEN];
+        leaf[0] = LEAF_NODE_TAG;
+        leaf[2..4].copy_from_slice(&order.time_in_force.to_le_bytes());
+        let key = ((order.price_lots as u128) << 64) | index as u128;
+        leaf[8..24].copy_from_slice(&key.to_le_bytes());
+        leaf[56..64].copy_from_slice(&order.quantity_lots.to_le_bytes());
+        leaf[64..72].copy_from_slice(&order.timestamp.to_le_bytes());
+    }
+    for index in 0..leaf_count.saturating_sub(1) {
+        let inner = &mut data[offset(leaf_count + index)..][..NODE_LEN];
+        inner[0] = INNER_NODE_TAG;
+        let right = if index + 2 == leaf_count {
+            index + 1
+        } else {
+            leaf_count + index + 1
+        };
+        inner[24..28].copy_from_slice(&index.to_le_bytes());
+        inner[28..32].copy_from_slice(&right.to_le_bytes());
+    }
+    data
+}
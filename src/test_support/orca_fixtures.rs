@@ -0,0 +1,77 @@
+use std::{collections::HashMap, str::FromStr};
+
+use solana_program::pubkey::Pubkey;
+
+use super::solana_fixtures::{mint_account_data, SOL_MINT, USDC_MINT};
+use crate::structs::on_chain::{
+    account::account_discriminator,
+    orca::{Whirlpool, TICK_ARRAY_LEN, TICK_ARRAY_SIZE, TICK_LEN, WHIRLPOOL_LEN},
+};
+
+/*
+    SOL/USDC Whirlpool at 70 USDC with 0.3% fee and 10^12 liquidity around the price
+*/
+pub fn sol_usdc_whirlpool() -> Whirlpool {
+    Whirlpool {
+        tick_spacing: 64,
+        fee_rate: 3000,
+        liquidity: 1_000_000_000_000,
+        sqrt_price: 4880549731789001728,
+        tick_current_index: -26594,
+        token_mint_a: Pubkey::from_str(SOL_MINT).unwrap(),
+        token_mint_b: Pubkey::from_str(USDC_MINT).unwrap(),
+    }
+}
+
+/*
+    Accounts of the Whirlpool and its mints by pubkey, as served over Solana JSON-RPC
+*/
+pub fn whirlpool_accounts(address: &Pubkey, whirlpool: &Whirlpool) -> HashMap<Pubkey, Vec<u8>> {
+    HashMap::from([
+        (*address, whirlpool_account_data(whirlpool)),
+        (whirlpool.token_mint_a, mint_account_data(9)),
+        (whirlpool.token_mint_b, mint_account_data(6)),
+    ])
+}
+
+/*
+    Serializes the Whirlpool into bytes of an on-chain Whirlpool account, fields not needed for quoting are zeroed
+*/
+pub fn whirlpool_account_data(whirlpool: &Whirlpool) -> Vec<u8> {
+    let mut data = vec![0; WHIRLPOOL_LEN];
+    data[..8].copy_from_slice(&account_discriminator("Whirlpool"));
+    data[41..43].copy_from_slice(&whirlpool.tick_spacing.to_le_bytes());
+    data[45..47].copy_from_slice(&whirlpool.fee_rate.to_le_bytes());
+    data[49..65].copy_from_slice(&whirlpool.liquidity.to_le_bytes());
+    data[65..81].copy_from_slice(&whirlpool.sqrt_price.to_le_bytes());
+    data[81..85].copy_from_slice(&whirlpool.tick_current_index.to_le_bytes());
+    data[101..133].copy_from_slice(whirlpool.token_mint_a.as_ref());
+    data[181..213].copy_from_slice(whirlpool.token_mint_b.as_ref());
+    data
+}
+
+/*
+    Serializes a tick array with the initialized ticks given as (tick index, liquidity net)
+*/
+pub fn tick_array_account_data(
+    whirlpool: &Pubkey,
+    tick_spacing: u16,
+    start_tick_index: i32,
+    initialized_ticks: &[(i32, i128)],
+) -> Vec<u8> {
+    let mut data = vec![0; TICK_ARRAY_LEN];
+    data[..8].copy_from_slice(&account_discriminator("TickArray"));
+    data[8..12].copy_from_slice(&start_tick_index.to_le_bytes());
+    for (tick_index, liquidity_net) in initialized_ticks {
+        let index = (tick_index - start_tick_index) / tick_spacing as i32;
+        assert!(
+            (0..TICK_ARRAY_SIZE).contains(&index),
+            "Tick out of the array"
+        );
+        let offset = 12 + index as usize * TICK_LEN;
+        data[offset] = 1;
+        data[offset + 1..offset + 17].copy_from_slice(&liquidity_net.to_le_bytes());
+    }
+    data[TICK_ARRAY_LEN - 32..].copy_from_slice(whirlpool.as_ref());
+    data
+}
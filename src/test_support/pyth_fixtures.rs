@@ -0,0 +1,112 @@
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use pyth_sdk_solana::{
+    state::{AccountType, PriceAccount, PriceInfo, PriceStatus, MAGIC, VERSION_2},
+    Price,
+};
+use solana_program::pubkey::Pubkey;
+
+use crate::structs::on_chain::price_source::{load_price_from_account_data, PriceSource};
+
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+/*
+    Subset of Pyth price account fields relevant for reading prices.
+    Timestamps default to the current time, status defaults to Trading.
+*/
+pub struct PriceAccountFixture {
+    pub price: i64,
+    pub conf: u64,
+    pub expo: i32,
+    pub status: PriceStatus,
+    pub timestamp: i64,
+    pub prev_price: i64,
+    pub prev_conf: u64,
+    pub prev_timestamp: i64,
+}
+
+impl Default for PriceAccountFixture {
+    fn default() -> Self {
+        Self {
+            price: 10000000000,
+            conf: 1000000,
+            expo: -8,
+            status: PriceStatus::Trading,
+            timestamp: now(),
+            prev_price: 10000000000,
+            prev_conf: 1000000,
+            prev_timestamp: now(),
+        }
+    }
+}
+
+impl PriceAccountFixture {
+    /*
+        Serializes the fixture into bytes of an on-chain Pyth price account
+    */
+    pub fn to_account_data(&self) -> Vec<u8> {
+        let price_account = PriceAccount {
+            magic: MAGIC,
+            ver: VERSION_2,
+            atype: AccountType::Price as u32,
+            size: std::mem::size_of::<PriceAccount>() as u32,
+            expo: self.expo,
+            timestamp: self.timestamp,
+            prev_price: self.prev_price,
+            prev_conf: self.prev_conf,
+            prev_timestamp: self.prev_timestamp,
+            agg: PriceInfo {
+                price: self.price,
+                conf: self.conf,
+                status: self.status,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        bytemuck::bytes_of(&price_account).to_vec()
+    }
+}
+
+/*
+    PriceSource backed by serialized price accounts instead of an RPC endpoint
+*/
+#[derive(Default)]
+pub struct PriceAccountFixtures {
+    accounts: HashMap<Pubkey, Vec<u8>>,
+}
+
+impl PriceAccountFixtures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, price_id: Pubkey, fixture: PriceAccountFixture) {
+        self.insert_raw(price_id, fixture.to_account_data());
+    }
+
+    pub fn insert_raw(&mut self, price_id: Pubkey, data: Vec<u8>) {
+        self.accounts.insert(price_id, data);
+    }
+}
+
+#[async_trait]
+impl PriceSource for PriceAccountFixtures {
+    async fn get_price(&self, price_id: &Pubkey) -> Result<Option<Price>> {
+        let data = self
+            .accounts
+            .get(price_id)
+            .ok_or_else(|| anyhow!("AccountNotFound: pubkey={}", price_id))?;
+        load_price_from_account_data(price_id, data)
+    }
+}
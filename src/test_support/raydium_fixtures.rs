@@ -0,0 +1,137 @@
+use std::{collections::HashMap, str::FromStr};
+
+use solana_program::pubkey::Pubkey;
+
+use super::solana_fixtures::USDC_MINT;
+use crate::structs::on_chain::{
+    account::account_discriminator,
+    raydium::{RaydiumPool, AMM_V4_LEN, CPMM_CONFIG_LEN, CPMM_POOL_LEN, TOKEN_AMOUNT_OFFSET},
+};
+
+// Reserves of the SOL/USDC fixture pools, 1000 SOL and 70000 USDC
+pub const SOL_RESERVE: u64 = 1_000_000_000_000;
+pub const USDC_RESERVE: u64 = 70_000_000_000;
+
+/*
+    SOL/USDC AMM v4 pool with the 0.25% fee and fees pending withdrawal in both vaults
+*/
+pub fn sol_usdc_amm_v4() -> RaydiumPool {
+    RaydiumPool {
+        base_vault: Pubkey::new_from_array([1; 32]),
+        quote_vault: Pubkey::new_from_array([2; 32]),
+        quote_mint: Pubkey::from_str(USDC_MINT).unwrap(),
+        base_decimals: 9,
+        quote_decimals: 6,
+        base_pending_fees: 5_000_000,
+        quote_pending_fees: 350_000,
+        fee_numerator: 25,
+        fee_denominator: 10000,
+        amm_config: None,
+    }
+}
+
+/*
+    SOL/USDC CPMM pool with protocol and fund fees pending withdrawal, its config charges 0.25%
+*/
+pub fn sol_usdc_cpmm() -> RaydiumPool {
+    RaydiumPool {
+        base_vault: Pubkey::new_from_array([3; 32]),
+        quote_vault: Pubkey::new_from_array([4; 32]),
+        quote_mint: Pubkey::from_str(USDC_MINT).unwrap(),
+        base_decimals: 9,
+        quote_decimals: 6,
+        base_pending_fees: 3_000_000,
+        quote_pending_fees: 210_000,
+        fee_numerator: 0,
+        fee_denominator: 0,
+        amm_config: Some(Pubkey::new_from_array([5; 32])),
+    }
+}
+
+/*
+    Accounts of the AMM v4 pool and its vaults by pubkey, as served over Solana JSON-RPC
+*/
+pub fn amm_v4_accounts(address: &Pubkey, pool: &RaydiumPool) -> HashMap<Pubkey, Vec<u8>> {
+    HashMap::from([
+        (*address, amm_v4_account_data(pool)),
+        (
+            pool.base_vault,
+            token_account_data(SOL_RESERVE + pool.base_pending_fees),
+        ),
+        (
+            pool.quote_vault,
+            token_account_data(USDC_RESERVE + pool.quote_pending_fees),
+        ),
+    ])
+}
+
+/*
+    Accounts of the CPMM pool, its vaults and its config by pubkey, as served over Solana JSON-RPC
+*/
+pub fn cpmm_accounts(address: &Pubkey, pool: &RaydiumPool) -> HashMap<Pubkey, Vec<u8>> {
+    HashMap::from([
+        (*address, cpmm_pool_account_data(pool)),
+        (
+            pool.base_vault,
+            token_account_data(SOL_RESERVE + pool.base_pending_fees),
+        ),
+        (
+            pool.quote_vault,
+            token_account_data(USDC_RESERVE + pool.quote_pending_fees),
+        ),
+        (pool.amm_config.unwrap(), cpmm_config_account_data(2500)),
+    ])
+}
+
+/*
+    Serializes the pool into bytes of an AMM v4 account, fields not needed for quoting are zeroed
+*/
+pub fn amm_v4_account_data(pool: &RaydiumPool) -> Vec<u8> {
+    let mut data = vec![0; AMM_V4_LEN];
+    data[32..40].copy_from_slice(&(pool.base_decimals as u64).to_le_bytes());
+    data[40..48].copy_from_slice(&(pool.quote_decimals as u64).to_le_bytes());
+    data[176..184].copy_from_slice(&pool.fee_numerator.to_le_bytes());
+    data[184..192].copy_from_slice(&pool.fee_denominator.to_le_bytes());
+    data[192..200].copy_from_slice(&pool.base_pending_fees.to_le_bytes());
+    data[200..208].copy_from_slice(&pool.quote_pending_fees.to_le_bytes());
+    data[336..368].copy_from_slice(pool.base_vault.as_ref());
+    data[368..400].copy_from_slice(pool.quote_vault.as_ref());
+    data[432..464].copy_from_slice(pool.quote_mint.as_ref());
+    data
+}
+
+/*
+    Serializes the pool into bytes of a CPMM PoolState account, the pending fees are all protocol fees
+*/
+pub fn cpmm_pool_account_data(pool: &RaydiumPool) -> Vec<u8> {
+    let mut data = vec![0; CPMM_POOL_LEN];
+    data[..8].copy_from_slice(&account_discriminator("PoolState"));
+    data[8..40].copy_from_slice(pool.amm_config.unwrap_or_default().as_ref());
+    data[72..104].copy_from_slice(pool.base_vault.as_ref());
+    data[104..136].copy_from_slice(pool.quote_vault.as_ref());
+    data[200..232].copy_from_slice(pool.quote_mint.as_ref());
+    data[331] = pool.base_decimals;
+    data[332] = pool.quote_decimals;
+    data[341..349].copy_from_slice(&pool.base_pending_fees.to_le_bytes());
+    data[349..357].copy_from_slice(&pool.quote_pending_fees.to_le_bytes());
+    data
+}
+
+/*
+    Serializes a CPMM AmmConfig with the trade fee rate in millionths
+*/
+pub fn cpmm_config_account_data(trade_fee_rate: u64) -> Vec<u8> {
+    let mut data = vec![0; CPMM_CONFIG_LEN];
+    data[..8].copy_from_slice(&account_discriminator("AmmConfig"));
+    data[12..20].copy_from_slice(&trade_fee_rate.to_le_bytes());
+    data
+}
+
+/*
+    Serializes an SPL token account holding the amount of atoms
+*/
+pub fn token_account_data(amount: u64) -> Vec<u8> {
+    let mut data = vec![0; 165];
+    data[TOKEN_AMOUNT_OFFSET..TOKEN_AMOUNT_OFFSET + 8].copy_from_slice(&amount.to_le_bytes());
+    data
+}
@@ -0,0 +1,14 @@
+use crate::structs::on_chain::account::MINT_DECIMALS_OFFSET;
+
+pub const SOL_MINT: &str = "So11111111111111111111111111111111111111112";
+pub const USDC_MINT: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+
+/*
+    Serializes an initialized SPL token mint with the decimals
+*/
+pub fn mint_account_data(decimals: u8) -> Vec<u8> {
+    let mut data = vec![0; 82];
+    data[MINT_DECIMALS_OFFSET] = decimals;
+    data[MINT_DECIMALS_OFFSET + 1] = 1;
+    data
+}